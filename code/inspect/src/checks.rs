@@ -0,0 +1,306 @@
+//! Tries to create the resources and pipelines the crates in this
+//! workspace depend on.
+//!
+//! wgpu panics when it can't create something, so every check runs
+//! under `catch_unwind` and the panic message becomes the reason it
+//! failed.
+
+use anyhow::*;
+use std::panic::{self, AssertUnwindSafe};
+
+/// The outcome of one check on one device.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub result: Result<(), String>,
+}
+
+/// Formats the framework renders to, or samples from.
+const TEXTURE_FORMATS: &[(wgpu::TextureFormat, bool)] = &[
+    // (format, can be rendered to)
+    (wgpu::TextureFormat::Rgba8UnormSrgb, true),
+    (wgpu::TextureFormat::Bgra8UnormSrgb, true),
+    (wgpu::TextureFormat::Rgba8Unorm, true),
+    (wgpu::TextureFormat::Rgba16Float, true),
+    (wgpu::TextureFormat::Rgba32Float, true),
+    (wgpu::TextureFormat::R32Float, true),
+    (wgpu::TextureFormat::Bc1RgbaUnormSrgb, false),
+    (wgpu::TextureFormat::Bc3RgbaUnormSrgb, false),
+    (wgpu::TextureFormat::Bc5RgUnorm, false),
+    (wgpu::TextureFormat::Bc6hRgbUfloat, false),
+    (wgpu::TextureFormat::Bc7RgbaUnormSrgb, false),
+];
+
+const DEPTH_FORMATS: &[wgpu::TextureFormat] = &[
+    wgpu::TextureFormat::Depth32Float,
+    wgpu::TextureFormat::Depth24Plus,
+    wgpu::TextureFormat::Depth24PlusStencil8,
+];
+
+const FULLSCREEN_VERTEX_SHADER: &str = r#"
+#version 450
+
+void main() {
+    vec2 corner = vec2(float(gl_VertexIndex & 1), float((gl_VertexIndex >> 1) & 1));
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.5, 1.0);
+}
+"#;
+
+const SOLID_FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    f_color = vec4(1.0, 0.0, 1.0, 1.0);
+}
+"#;
+
+/// The instancing showcase reads its model matrices from a storage
+/// buffer in the vertex shader.
+const INSTANCING_VERTEX_SHADER: &str = include_str!("../../showcase/instancing/src/shader.vert");
+
+struct Shaders {
+    fullscreen: wgpu::ShaderModule,
+    solid: wgpu::ShaderModule,
+    instancing: wgpu::ShaderModule,
+}
+
+fn compile(
+    device: &wgpu::Device,
+    compiler: &mut shaderc::Compiler,
+    src: &str,
+    kind: shaderc::ShaderKind,
+    name: &str,
+) -> Result<wgpu::ShaderModule> {
+    let spirv = compiler
+        .compile_into_spirv(src, kind, name, "main", None)
+        .with_context(|| format!("Unable to compile {}", name))?;
+    Ok(device.create_shader_module(wgpu::util::make_spirv(spirv.as_binary_u8())))
+}
+
+impl Shaders {
+    fn new(device: &wgpu::Device) -> Result<Self> {
+        let mut compiler = shaderc::Compiler::new().context("Unable to create shader compiler")?;
+        Ok(Self {
+            fullscreen: compile(
+                device,
+                &mut compiler,
+                FULLSCREEN_VERTEX_SHADER,
+                shaderc::ShaderKind::Vertex,
+                "fullscreen.vert",
+            )?,
+            solid: compile(
+                device,
+                &mut compiler,
+                SOLID_FRAGMENT_SHADER,
+                shaderc::ShaderKind::Fragment,
+                "solid.frag",
+            )?,
+            instancing: compile(
+                device,
+                &mut compiler,
+                INSTANCING_VERTEX_SHADER,
+                shaderc::ShaderKind::Vertex,
+                "instancing/shader.vert",
+            )?,
+        })
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => String::from("Unknown panic"),
+        },
+    }
+}
+
+fn check<F: FnOnce()>(name: String, f: F) -> Check {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).map_err(panic_message);
+    Check { name, result }
+}
+
+fn texture_desc(
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsage,
+) -> wgpu::TextureDescriptor<'static> {
+    wgpu::TextureDescriptor {
+        label: Some("inspect"),
+        size: wgpu::Extent3d {
+            width: 4,
+            height: 4,
+            depth: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+    }
+}
+
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vertex: &wgpu::ShaderModule,
+    fragment: Option<&wgpu::ShaderModule>,
+    color_states: &[wgpu::ColorStateDescriptor],
+    depth_format: Option<wgpu::TextureFormat>,
+    vertex_buffers: &[wgpu::VertexBufferDescriptor],
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("inspect"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: vertex,
+            entry_point: "main",
+        },
+        fragment_stage: fragment.map(|module| wgpu::ProgrammableStageDescriptor {
+            module,
+            entry_point: "main",
+        }),
+        rasterization_state: None,
+        primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
+        color_states,
+        depth_stencil_state: depth_format.map(|format| wgpu::DepthStencilStateDescriptor {
+            format,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: Default::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers,
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
+
+fn uniform_entry(visibility: wgpu::ShaderStage) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility,
+        ty: wgpu::BindingType::UniformBuffer {
+            dynamic: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Runs every check on `device`. Only fails when the checks themselves
+/// can't be set up.
+pub fn run_checks(device: &wgpu::Device) -> Result<Vec<Check>> {
+    let shaders = Shaders::new(device)?;
+    let empty_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("inspect"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+    let mut checks = Vec::new();
+
+    // Keep wgpu's panics out of the report, which has them anyway
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    for &(format, renderable) in TEXTURE_FORMATS {
+        let mut usage = wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST;
+        if renderable {
+            usage |= wgpu::TextureUsage::OUTPUT_ATTACHMENT;
+        }
+        checks.push(check(format!("texture {:?}", format), || {
+            device.create_texture(&texture_desc(format, usage));
+        }));
+        if renderable {
+            checks.push(check(format!("render to {:?}", format), || {
+                render_pipeline(
+                    device,
+                    &empty_layout,
+                    &shaders.fullscreen,
+                    Some(&shaders.solid),
+                    &[wgpu::ColorStateDescriptor {
+                        format,
+                        alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                        color_blend: wgpu::BlendDescriptor::REPLACE,
+                        write_mask: wgpu::ColorWrite::ALL,
+                    }],
+                    None,
+                    &[],
+                );
+            }));
+        }
+    }
+
+    for &format in DEPTH_FORMATS {
+        checks.push(check(format!("depth {:?}", format), || {
+            device.create_texture(&texture_desc(
+                format,
+                wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            ));
+            render_pipeline(
+                device,
+                &empty_layout,
+                &shaders.fullscreen,
+                None,
+                &[],
+                Some(format),
+                &[],
+            );
+        }));
+    }
+
+    checks.push(check(
+        String::from("storage buffer in a vertex shader (instancing)"),
+        || {
+            let uniforms = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("inspect"),
+                entries: &[uniform_entry(wgpu::ShaderStage::VERTEX)],
+            });
+            let instances = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("inspect"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::StorageBuffer {
+                        dynamic: false,
+                        readonly: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("inspect"),
+                bind_group_layouts: &[&uniforms, &uniforms, &instances],
+                push_constant_ranges: &[],
+            });
+            render_pipeline(
+                device,
+                &layout,
+                &shaders.instancing,
+                None,
+                &[],
+                Some(wgpu::TextureFormat::Depth32Float),
+                &[wgpu::VertexBufferDescriptor {
+                    stride: 56,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float3,
+                        1 => Float2,
+                        2 => Float3,
+                        3 => Float3,
+                        4 => Float3
+                    ],
+                }],
+            );
+        },
+    ));
+
+    panic::set_hook(default_hook);
+    Ok(checks)
+}
@@ -0,0 +1,310 @@
+//! Prints what wgpu finds on this machine: every adapter on every
+//! backend, its features and limits, and whether the resources and
+//! pipelines the crates in this workspace use can be created on it.
+//!
+//! ```text
+//! cargo run -p inspect            # for people
+//! cargo run -p inspect -- --json  # for bug reports and scripts
+//! ```
+
+mod checks;
+
+use anyhow::*;
+use checks::{run_checks, Check};
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+const BACKENDS: &[wgpu::Backend] = &[
+    wgpu::Backend::Vulkan,
+    wgpu::Backend::Metal,
+    wgpu::Backend::Dx12,
+    wgpu::Backend::Dx11,
+    wgpu::Backend::Gl,
+];
+
+struct AdapterReport {
+    info: wgpu::AdapterInfo,
+    features: wgpu::Features,
+    limits: wgpu::Limits,
+    /// Fails when no device could be created to run the checks on.
+    checks: Result<Vec<Check>, String>,
+}
+
+struct BackendReport {
+    backend: wgpu::Backend,
+    adapters: Vec<AdapterReport>,
+}
+
+fn inspect_adapter(adapter: &wgpu::Adapter) -> AdapterReport {
+    let info = adapter.get_info();
+    let features = adapter.features();
+    let limits = adapter.limits();
+    let device = futures::executor::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            features,
+            limits: limits.clone(),
+            shader_validation: true,
+        },
+        None,
+    ));
+    let checks = match device {
+        Ok((device, _queue)) => run_checks(&device).map_err(|e| format!("{:#}", e)),
+        Err(e) => Err(format!("Unable to create a device: {}", e)),
+    };
+    AdapterReport {
+        info,
+        features,
+        limits,
+        checks,
+    }
+}
+
+fn inspect() -> Vec<BackendReport> {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+    BACKENDS
+        .iter()
+        .map(|&backend| BackendReport {
+            backend,
+            adapters: instance
+                .enumerate_adapters(backend.into())
+                .map(|adapter| inspect_adapter(&adapter))
+                .collect(),
+        })
+        .collect()
+}
+
+/// The names of the flags in `features`, like `TEXTURE_COMPRESSION_BC`.
+fn feature_list(features: wgpu::Features) -> Vec<String> {
+    if features.is_empty() {
+        return Vec::new();
+    }
+    format!("{:?}", features)
+        .split(" | ")
+        // Groups like ALL_NATIVE just repeat the flags they're made of
+        .filter(|name| !name.starts_with("ALL_"))
+        .map(String::from)
+        .collect()
+}
+
+fn limit_list(limits: &wgpu::Limits) -> Vec<(&'static str, u32)> {
+    vec![
+        ("max_bind_groups", limits.max_bind_groups),
+        (
+            "max_dynamic_uniform_buffers_per_pipeline_layout",
+            limits.max_dynamic_uniform_buffers_per_pipeline_layout,
+        ),
+        (
+            "max_dynamic_storage_buffers_per_pipeline_layout",
+            limits.max_dynamic_storage_buffers_per_pipeline_layout,
+        ),
+        (
+            "max_sampled_textures_per_shader_stage",
+            limits.max_sampled_textures_per_shader_stage,
+        ),
+        (
+            "max_samplers_per_shader_stage",
+            limits.max_samplers_per_shader_stage,
+        ),
+        (
+            "max_storage_buffers_per_shader_stage",
+            limits.max_storage_buffers_per_shader_stage,
+        ),
+        (
+            "max_storage_textures_per_shader_stage",
+            limits.max_storage_textures_per_shader_stage,
+        ),
+        (
+            "max_uniform_buffers_per_shader_stage",
+            limits.max_uniform_buffers_per_shader_stage,
+        ),
+        (
+            "max_uniform_buffer_binding_size",
+            limits.max_uniform_buffer_binding_size,
+        ),
+        ("max_push_constant_size", limits.max_push_constant_size),
+    ]
+}
+
+fn to_text(reports: &[BackendReport]) -> String {
+    let mut out = String::new();
+    for report in reports {
+        writeln!(out, "{:?}", report.backend).unwrap();
+        if report.adapters.is_empty() {
+            writeln!(out, "  No adapters").unwrap();
+        }
+        for adapter in &report.adapters {
+            let info = &adapter.info;
+            writeln!(
+                out,
+                "  {} ({:?}, vendor {:#06x}, device {:#06x})",
+                info.name, info.device_type, info.vendor, info.device
+            )
+            .unwrap();
+            let features = feature_list(adapter.features);
+            if features.is_empty() {
+                writeln!(out, "    Features: none").unwrap();
+            } else {
+                writeln!(out, "    Features: {}", features.join(", ")).unwrap();
+            }
+            writeln!(out, "    Limits:").unwrap();
+            for (name, value) in limit_list(&adapter.limits) {
+                writeln!(out, "      {}: {}", name, value).unwrap();
+            }
+            match &adapter.checks {
+                Ok(checks) => {
+                    writeln!(out, "    Checks:").unwrap();
+                    for check in checks {
+                        let line = match &check.result {
+                            Ok(()) => format!("ok      {}", check.name),
+                            Err(e) => format!("FAILED  {}: {}", check.name, e),
+                        };
+                        writeln!(out, "      {}", line).unwrap();
+                    }
+                }
+                Err(e) => writeln!(out, "    Checks not run: {}", e).unwrap(),
+            }
+        }
+    }
+    out
+}
+
+fn to_json(reports: &[BackendReport]) -> Value {
+    Value::Array(
+        reports
+            .iter()
+            .map(|report| {
+                json!({
+                    "backend": format!("{:?}", report.backend),
+                    "adapters": report.adapters.iter().map(adapter_json).collect::<Vec<_>>(),
+                })
+            })
+            .collect(),
+    )
+}
+
+fn adapter_json(adapter: &AdapterReport) -> Value {
+    let info = &adapter.info;
+    let limits = limit_list(&adapter.limits)
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), json!(value)))
+        .collect::<serde_json::Map<_, _>>();
+    let (checks, checks_error) = match &adapter.checks {
+        Ok(checks) => (
+            checks
+                .iter()
+                .map(|check| {
+                    json!({
+                        "name": check.name,
+                        "ok": check.result.is_ok(),
+                        "error": check.result.as_ref().err(),
+                    })
+                })
+                .collect(),
+            None,
+        ),
+        Err(e) => (Vec::new(), Some(e)),
+    };
+    json!({
+        "name": info.name,
+        "vendor": info.vendor,
+        "device": info.device,
+        "device_type": format!("{:?}", info.device_type),
+        "features": feature_list(adapter.features),
+        "limits": limits,
+        "checks": checks,
+        "checks_error": checks_error,
+    })
+}
+
+fn main() -> Result<()> {
+    let mut json = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--help" | "-h" => {
+                println!("Usage: inspect [--json]");
+                return Ok(());
+            }
+            _ => bail!("Unknown argument {:?}. Usage: inspect [--json]", arg),
+        }
+    }
+
+    let reports = inspect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&to_json(&reports))?);
+    } else {
+        print!("{}", to_text(&reports));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report() -> Vec<BackendReport> {
+        vec![
+            BackendReport {
+                backend: wgpu::Backend::Vulkan,
+                adapters: vec![AdapterReport {
+                    info: wgpu::AdapterInfo {
+                        name: String::from("Test GPU"),
+                        vendor: 0x10de,
+                        device: 0x1f02,
+                        device_type: wgpu::DeviceType::DiscreteGpu,
+                        backend: wgpu::Backend::Vulkan,
+                    },
+                    features: wgpu::Features::DEPTH_CLAMPING
+                        | wgpu::Features::TEXTURE_COMPRESSION_BC,
+                    limits: wgpu::Limits::default(),
+                    checks: Ok(vec![
+                        Check {
+                            name: String::from("texture Rgba8UnormSrgb"),
+                            result: Ok(()),
+                        },
+                        Check {
+                            name: String::from("depth Depth32Float"),
+                            result: Err(String::from("unsupported")),
+                        },
+                    ]),
+                }],
+            },
+            BackendReport {
+                backend: wgpu::Backend::Metal,
+                adapters: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn features_are_listed_by_name() {
+        assert!(feature_list(wgpu::Features::empty()).is_empty());
+        assert_eq!(
+            feature_list(wgpu::Features::DEPTH_CLAMPING | wgpu::Features::PUSH_CONSTANTS),
+            vec!["DEPTH_CLAMPING", "PUSH_CONSTANTS"]
+        );
+    }
+
+    #[test]
+    fn text_report_shows_failures() {
+        let text = to_text(&report());
+        assert!(text.contains("Test GPU (DiscreteGpu, vendor 0x10de, device 0x1f02)"));
+        assert!(text.contains("Features: DEPTH_CLAMPING, TEXTURE_COMPRESSION_BC"));
+        assert!(text.contains("ok      texture Rgba8UnormSrgb"));
+        assert!(text.contains("FAILED  depth Depth32Float: unsupported"));
+        assert!(text.contains("Metal\n  No adapters"));
+    }
+
+    #[test]
+    fn json_report_has_every_adapter() {
+        let json = to_json(&report());
+        assert_eq!(json[0]["backend"], "Vulkan");
+        let adapter = &json[0]["adapters"][0];
+        assert_eq!(adapter["name"], "Test GPU");
+        assert_eq!(adapter["limits"]["max_bind_groups"], 4);
+        assert_eq!(adapter["checks"][1]["ok"], false);
+        assert_eq!(adapter["checks"][1]["error"], "unsupported");
+        assert!(adapter["checks_error"].is_null());
+        assert_eq!(json[1]["adapters"].as_array().unwrap().len(), 0);
+    }
+}
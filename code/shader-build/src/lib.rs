@@ -0,0 +1,667 @@
+//! Compiles the GLSL shaders of a crate to SPIR-V from its `build.rs`.
+//!
+//! ```no_run
+//! fn main() -> anyhow::Result<()> {
+//!     shader_build::ShaderBuild::new()
+//!         .shader_dir("src")
+//!         .resource_dir("res")
+//!         .build()
+//! }
+//! ```
+//!
+//! Every `.vert`, `.frag` and `.comp` file is compiled to a `.spv` file
+//! next to it, so `shader.frag` becomes `shader.frag.spv`. Shaders can
+//! `#include` other files, and are only recompiled when their source,
+//! one of their includes, or their defines change.
+
+use anyhow::*;
+use fs_extra::copy_items;
+use fs_extra::dir::CopyOptions;
+use glob::glob;
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+pub const SHADER_EXTENSIONS: &[&str] = &["vert", "frag", "comp"];
+
+/// Maps a shader's file extension to the kind of shader it contains.
+pub fn shader_kind(path: &Path) -> Result<shaderc::ShaderKind> {
+    let extension = path
+        .extension()
+        .context("File has no extension")?
+        .to_str()
+        .context("Extension cannot be converted to &str")?;
+    Ok(match extension {
+        "vert" => shaderc::ShaderKind::Vertex,
+        "frag" => shaderc::ShaderKind::Fragment,
+        "comp" => shaderc::ShaderKind::Compute,
+        _ => bail!("Unsupported shader: {}", path.display()),
+    })
+}
+
+pub type Defines = Vec<(String, Option<String>)>;
+
+/// A set of preprocessor defines that shaders are compiled with in
+/// addition to the default build. The output for `shader.frag` in a
+/// variant called `shadows` is `shader.shadows.frag.spv`.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub defines: Defines,
+    /// File names of the shaders to compile this variant of. Every
+    /// shader gets it when this is empty.
+    pub shaders: Vec<String>,
+}
+
+impl Variant {
+    fn applies_to(&self, path: &Path) -> bool {
+        self.shaders.is_empty()
+            || path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| self.shaders.iter().any(|s| s == name))
+    }
+}
+
+/// A single problem reported by shaderc.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderError {
+    pub path: PathBuf,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl ShaderError {
+    /// Splits shaderc's error output into one error per line. Shaderc
+    /// formats errors as `file:line: error: message`.
+    fn parse(path: &Path, output: &str) -> Vec<Self> {
+        let errors = output
+            .lines()
+            .filter_map(|line| {
+                let (location, message) = match line.find(": error: ") {
+                    Some(i) => (&line[..i], &line[i + ": error: ".len()..]),
+                    None => return None,
+                };
+                let mut parts = location.rsplitn(2, ':');
+                let line_number = parts.next().and_then(|l| l.trim().parse().ok());
+                let file = match (line_number, parts.next()) {
+                    (Some(_), Some(file)) => PathBuf::from(file),
+                    _ => path.to_path_buf(),
+                };
+                Some(Self {
+                    path: file,
+                    line: line_number,
+                    message: message.trim().to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            vec![Self {
+                path: path.to_path_buf(),
+                line: None,
+                message: output.trim().to_string(),
+            }]
+        } else {
+            errors
+        }
+    }
+}
+
+/// The result of compiling a single shader.
+pub struct CompiledShader {
+    pub spirv: Vec<u8>,
+    /// Every file pulled in with `#include`, and the hash of its
+    /// contents at the time of compilation.
+    pub includes: Vec<(PathBuf, u64)>,
+    pub warnings: String,
+}
+
+/// Compiles one GLSL file. `include_dirs` are searched for
+/// `#include <...>` directives, and `#include "..."` directives are
+/// searched relative to the file doing the including first.
+pub fn compile_file(
+    compiler: &mut shaderc::Compiler,
+    path: &Path,
+    include_dirs: &[PathBuf],
+    defines: &[(String, Option<String>)],
+) -> Result<CompiledShader, Vec<ShaderError>> {
+    let single_error = |message: String| {
+        vec![ShaderError {
+            path: path.to_path_buf(),
+            line: None,
+            message,
+        }]
+    };
+
+    let kind = shader_kind(path).map_err(|e| single_error(e.to_string()))?;
+    let src = read_to_string(path).map_err(|e| single_error(e.to_string()))?;
+
+    let includes = RefCell::new(Vec::new());
+    let result = {
+        let mut options = shaderc::CompileOptions::new()
+            .ok_or_else(|| single_error("Unable to create compile options".to_string()))?;
+        for (name, value) in defines {
+            options.add_macro_definition(name, value.as_deref());
+        }
+        options.set_include_callback(|name, include_type, source_name, _depth| {
+            let relative = match include_type {
+                shaderc::IncludeType::Relative => Path::new(source_name)
+                    .parent()
+                    .map(|dir| dir.join(name)),
+                shaderc::IncludeType::Standard => None,
+            };
+            let candidates = relative
+                .into_iter()
+                .chain(include_dirs.iter().map(|dir| dir.join(name)));
+            for candidate in candidates {
+                if let Ok(content) = read_to_string(&candidate) {
+                    includes
+                        .borrow_mut()
+                        .push((candidate.clone(), hash_str(&content)));
+                    return Ok(shaderc::ResolvedInclude {
+                        resolved_name: candidate.to_string_lossy().into_owned(),
+                        content,
+                    });
+                }
+            }
+            Err(format!("Unable to find include {}", name))
+        });
+
+        compiler.compile_into_spirv(&src, kind, &path.to_string_lossy(), "main", Some(&options))
+    };
+
+    match result {
+        Ok(compiled) => Ok(CompiledShader {
+            spirv: compiled.as_binary_u8().to_vec(),
+            includes: includes.into_inner(),
+            warnings: compiled.get_warning_messages(),
+        }),
+        Err(shaderc::Error::CompilationError(_, output)) => Err(ShaderError::parse(path, &output)),
+        Err(e) => Err(single_error(e.to_string())),
+    }
+}
+
+/// FNV-1a. Unlike [std::collections::hash_map::DefaultHasher] the
+/// output is guaranteed not to change between Rust versions, which
+/// matters since the hashes are written to disk.
+fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+const HASH_SEED: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn hash_str(s: &str) -> u64 {
+    hash_bytes(HASH_SEED, s.as_bytes())
+}
+
+struct Job {
+    src_path: PathBuf,
+    spv_path: PathBuf,
+    defines: Defines,
+}
+
+impl Job {
+    /// Hash of everything that affects the output, except includes
+    fn hash(&self, src: &str) -> u64 {
+        let mut hash = hash_bytes(HASH_SEED, src.as_bytes());
+        for (name, value) in &self.defines {
+            hash = hash_bytes(hash, name.as_bytes());
+            hash = hash_bytes(hash, b"=");
+            hash = hash_bytes(hash, value.as_deref().unwrap_or("").as_bytes());
+            hash = hash_bytes(hash, b";");
+        }
+        hash
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CacheEntry {
+    hash: u64,
+    includes: Vec<(PathBuf, u64)>,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, hash: u64) -> bool {
+        self.hash == hash
+            && self
+                .includes
+                .iter()
+                .all(|(path, include_hash)| {
+                    read_to_string(path).map(|s| hash_str(&s)).ok() == Some(*include_hash)
+                })
+    }
+}
+
+/// Remembers what each `.spv` file was compiled from. One line per
+/// output: `hash<TAB>spv path<TAB>include=hash;include=hash`
+#[derive(Debug, Default, PartialEq)]
+struct Cache {
+    entries: HashMap<PathBuf, CacheEntry>,
+}
+
+impl Cache {
+    fn parse(text: &str) -> Self {
+        let entries = text
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split('\t');
+                let hash = u64::from_str_radix(parts.next()?, 16).ok()?;
+                let spv_path = PathBuf::from(parts.next()?);
+                let includes = parts
+                    .next()
+                    .unwrap_or("")
+                    .split(';')
+                    .filter(|s| !s.is_empty())
+                    .map(|include| {
+                        let mut parts = include.rsplitn(2, '=');
+                        let hash = u64::from_str_radix(parts.next()?, 16).ok()?;
+                        Some((PathBuf::from(parts.next()?), hash))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some((spv_path, CacheEntry { hash, includes }))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    fn to_text(&self) -> String {
+        let mut lines = self
+            .entries
+            .iter()
+            .map(|(spv_path, entry)| {
+                let includes = entry
+                    .includes
+                    .iter()
+                    .map(|(path, hash)| format!("{}={:016x}", path.display(), hash))
+                    .collect::<Vec<_>>()
+                    .join(";");
+                format!("{:016x}\t{}\t{}", entry.hash, spv_path.display(), includes)
+            })
+            .collect::<Vec<_>>();
+        lines.sort();
+        lines.join("\n")
+    }
+}
+
+enum JobResult {
+    Cached(PathBuf, CacheEntry),
+    Compiled(PathBuf, CacheEntry, String),
+}
+
+/// Finds, compiles, and caches a crate's shaders, and copies its
+/// resources into `OUT_DIR`. Meant to be called from `build.rs`.
+pub struct ShaderBuild {
+    shader_dirs: Vec<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    resource_dirs: Vec<PathBuf>,
+    defines: Defines,
+    variants: Vec<Variant>,
+    cache_path: Option<PathBuf>,
+}
+
+impl ShaderBuild {
+    pub fn new() -> Self {
+        Self {
+            shader_dirs: Vec::new(),
+            include_dirs: Vec::new(),
+            resource_dirs: Vec::new(),
+            defines: Vec::new(),
+            variants: Vec::new(),
+            cache_path: env::var_os("OUT_DIR")
+                .map(|dir| PathBuf::from(dir).join("shader-build.cache")),
+        }
+    }
+
+    /// Compile every shader in `dir` and its subdirectories.
+    pub fn shader_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.shader_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Search `dir` when resolving `#include` directives.
+    pub fn include_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.include_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Copy `dir` into `OUT_DIR`.
+    pub fn resource_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.resource_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Define a macro for every shader and every variant.
+    pub fn define(&mut self, name: &str, value: Option<&str>) -> &mut Self {
+        self.defines
+            .push((name.to_string(), value.map(|v| v.to_string())));
+        self
+    }
+
+    /// Compile every shader a second time with `defines`.
+    pub fn variant(&mut self, name: &str, defines: &[(&str, Option<&str>)]) -> &mut Self {
+        self.variant_for(&[], name, defines)
+    }
+
+    /// Like [ShaderBuild::variant], but only for the shaders whose file
+    /// names are in `shaders`. An empty list means every shader.
+    pub fn variant_for(
+        &mut self,
+        shaders: &[&str],
+        name: &str,
+        defines: &[(&str, Option<&str>)],
+    ) -> &mut Self {
+        self.variants.push(Variant {
+            name: name.to_string(),
+            defines: defines
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.map(|v| v.to_string())))
+                .collect(),
+            shaders: shaders.iter().map(|s| s.to_string()).collect(),
+        });
+        self
+    }
+
+    /// Where to remember what each shader was compiled from. Defaults
+    /// to a file in `OUT_DIR`. Without a cache every shader gets
+    /// recompiled on every build.
+    pub fn cache_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.cache_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn build(&self) -> Result<()> {
+        self.compile_shaders()?;
+        self.copy_resources()
+    }
+
+    fn find_shaders(&self) -> Result<Vec<PathBuf>> {
+        let mut shader_paths = Vec::new();
+        for dir in &self.shader_dirs {
+            // This tells cargo to rerun this script if something in the directory changes.
+            println!("cargo:rerun-if-changed={}", dir.display());
+            for extension in SHADER_EXTENSIONS {
+                let pattern = dir.join("**").join(format!("*.{}", extension));
+                for path in glob(&pattern.to_string_lossy())? {
+                    shader_paths.push(path?);
+                }
+            }
+        }
+        Ok(shader_paths)
+    }
+
+    fn jobs(&self, shader_paths: &[PathBuf]) -> Result<Vec<Job>> {
+        let mut jobs = Vec::new();
+        for src_path in shader_paths {
+            let extension = src_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .context("Extension cannot be converted to &str")?;
+            jobs.push(Job {
+                src_path: src_path.clone(),
+                spv_path: src_path.with_extension(format!("{}.spv", extension)),
+                defines: self.defines.clone(),
+            });
+            for variant in self.variants.iter().filter(|v| v.applies_to(src_path)) {
+                let mut defines = self.defines.clone();
+                defines.extend(variant.defines.iter().cloned());
+                jobs.push(Job {
+                    src_path: src_path.clone(),
+                    spv_path: src_path
+                        .with_extension(format!("{}.{}.spv", variant.name, extension)),
+                    defines,
+                });
+            }
+        }
+        Ok(jobs)
+    }
+
+    fn compile_shaders(&self) -> Result<()> {
+        let shader_paths = self.find_shaders()?;
+        let jobs = self.jobs(&shader_paths)?;
+
+        let cache = self
+            .cache_path
+            .as_ref()
+            .and_then(|path| read_to_string(path).ok())
+            .map(|text| Cache::parse(&text))
+            .unwrap_or_default();
+
+        // Each thread gets its own compiler since [shaderc::Compiler]
+        // is not thread safe.
+        let results = jobs
+            .par_iter()
+            .map_init(shaderc::Compiler::new, |compiler, job| {
+                self.run_job(compiler.as_mut(), job, &cache)
+            })
+            .collect::<Vec<_>>();
+
+        let mut new_cache = Cache::default();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(JobResult::Cached(spv_path, entry)) => {
+                    new_cache.entries.insert(spv_path, entry);
+                }
+                Ok(JobResult::Compiled(spv_path, entry, warnings)) => {
+                    for warning in warnings.lines().filter(|l| !l.trim().is_empty()) {
+                        println!("cargo:warning={}", warning);
+                    }
+                    new_cache.entries.insert(spv_path, entry);
+                }
+                Err(e) => errors.extend(e),
+            }
+        }
+
+        for entry in new_cache.entries.values() {
+            for (include, _) in &entry.includes {
+                println!("cargo:rerun-if-changed={}", include.display());
+            }
+        }
+        if let Some(path) = &self.cache_path {
+            write(path, new_cache.to_text())?;
+        }
+
+        if !errors.is_empty() {
+            for error in &errors {
+                println!("cargo:warning={}", error);
+            }
+            let messages = errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            bail!("{} shader error(s):\n{}", errors.len(), messages);
+        }
+
+        Ok(())
+    }
+
+    fn run_job(
+        &self,
+        compiler: Option<&mut shaderc::Compiler>,
+        job: &Job,
+        cache: &Cache,
+    ) -> Result<JobResult, Vec<ShaderError>> {
+        let src = read_to_string(&job.src_path).map_err(|e| {
+            vec![ShaderError {
+                path: job.src_path.clone(),
+                line: None,
+                message: e.to_string(),
+            }]
+        })?;
+        let hash = job.hash(&src);
+
+        if let Some(entry) = cache.entries.get(&job.spv_path) {
+            if job.spv_path.exists() && entry.is_fresh(hash) {
+                return Ok(JobResult::Cached(job.spv_path.clone(), entry.clone()));
+            }
+        }
+
+        let compiler = compiler.ok_or_else(|| {
+            vec![ShaderError {
+                path: job.src_path.clone(),
+                line: None,
+                message: "Unable to create shader compiler".to_string(),
+            }]
+        })?;
+        let compiled = compile_file(compiler, &job.src_path, &self.include_dirs, &job.defines)?;
+        write(&job.spv_path, &compiled.spirv).map_err(|e| {
+            vec![ShaderError {
+                path: job.spv_path.clone(),
+                line: None,
+                message: e.to_string(),
+            }]
+        })?;
+
+        Ok(JobResult::Compiled(
+            job.spv_path.clone(),
+            CacheEntry {
+                hash,
+                includes: compiled.includes,
+            },
+            compiled.warnings,
+        ))
+    }
+
+    fn copy_resources(&self) -> Result<()> {
+        if self.resource_dirs.is_empty() {
+            return Ok(());
+        }
+
+        let out_dir = env::var("OUT_DIR")?;
+        let mut copy_options = CopyOptions::new();
+        copy_options.overwrite = true;
+        let mut paths_to_copy = Vec::new();
+        for dir in &self.resource_dirs {
+            // This tells cargo to rerun this script if something in the directory changes.
+            println!("cargo:rerun-if-changed={}", dir.display());
+            if dir.exists() {
+                paths_to_copy.push(dir.clone());
+            } else {
+                println!("cargo:warning={} does not exist", dir.display());
+            }
+        }
+        copy_items(&paths_to_copy, out_dir, &copy_options)
+            .map_err(|e| anyhow!("Unable to copy resources: {}", e))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_shaderc_errors() {
+        let output = "src/shader.frag:12: error: 'foo' : undeclared identifier\n\
+                      src/common.glsl:3: error: '' : syntax error\n\
+                      2 errors generated.\n";
+        let errors = ShaderError::parse(Path::new("src/shader.frag"), output);
+        assert_eq!(
+            errors,
+            vec![
+                ShaderError {
+                    path: PathBuf::from("src/shader.frag"),
+                    line: Some(12),
+                    message: "'foo' : undeclared identifier".to_string(),
+                },
+                ShaderError {
+                    path: PathBuf::from("src/common.glsl"),
+                    line: Some(3),
+                    message: "'' : syntax error".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unparsable_errors_are_kept() {
+        let errors = ShaderError::parse(Path::new("a.vert"), "something broke");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, None);
+        assert_eq!(errors[0].message, "something broke");
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let mut cache = Cache::default();
+        cache.entries.insert(
+            PathBuf::from("src/shader.frag.spv"),
+            CacheEntry {
+                hash: 0xdead_beef,
+                includes: vec![
+                    (PathBuf::from("src/common.glsl"), 1),
+                    (PathBuf::from("src/light.glsl"), u64::MAX),
+                ],
+            },
+        );
+        cache.entries.insert(
+            PathBuf::from("src/shader.vert.spv"),
+            CacheEntry {
+                hash: 42,
+                includes: Vec::new(),
+            },
+        );
+        assert_eq!(Cache::parse(&cache.to_text()), cache);
+    }
+
+    #[test]
+    fn defines_change_the_hash() {
+        let job = |defines: Defines| Job {
+            src_path: PathBuf::from("a.frag"),
+            spv_path: PathBuf::from("a.frag.spv"),
+            defines,
+        };
+        let plain = job(Vec::new()).hash("void main() {}");
+        let shadows = job(vec![("SHADOWS".to_string(), None)]).hash("void main() {}");
+        assert_ne!(plain, shadows);
+        assert_eq!(plain, job(Vec::new()).hash("void main() {}"));
+    }
+
+    #[test]
+    fn variants_only_apply_to_their_shaders() {
+        let mut build = ShaderBuild::new();
+        build.variant("everywhere", &[]).variant_for(
+            &["pbr.frag"],
+            "shadows",
+            &[("SHADOWS", None)],
+        );
+        let paths = [PathBuf::from("src/pbr.frag"), PathBuf::from("src/pbr.vert")];
+        let spv_paths = build
+            .jobs(&paths)
+            .unwrap()
+            .into_iter()
+            .map(|job| job.spv_path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spv_paths,
+            [
+                "src/pbr.frag.spv",
+                "src/pbr.everywhere.frag.spv",
+                "src/pbr.shadows.frag.spv",
+                "src/pbr.vert.spv",
+                "src/pbr.everywhere.vert.spv",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,12 @@
+fn main() -> anyhow::Result<()> {
+    shader_build::ShaderBuild::new()
+        .shader_dir("src")
+        .variant_for(&["pbr.frag"], "shadows", &[("SHADOWS", None)])
+        .variant_for(&["pbr.frag"], "environment", &[("ENVIRONMENT", None)])
+        .variant_for(
+            &["pbr.frag"],
+            "shadows_environment",
+            &[("SHADOWS", None), ("ENVIRONMENT", None)],
+        )
+        .build()
+}
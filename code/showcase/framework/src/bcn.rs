@@ -0,0 +1,1384 @@
+//! Decoders for the BC1 to BC7 block compressed formats, for adapters
+//! that can't sample them.
+
+/// The block compressed formats, without the sRGB distinction, which
+/// doesn't change how blocks are decoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum BcFormat {
+    Bc1,
+    /// BC1 without alpha. The texels BC1 makes transparent black are
+    /// opaque black instead.
+    Bc1Rgb,
+    Bc2,
+    Bc3,
+    Bc4 {
+        signed: bool,
+    },
+    Bc5 {
+        signed: bool,
+    },
+    Bc6h {
+        signed: bool,
+    },
+    Bc7,
+}
+
+impl BcFormat {
+    pub(crate) fn block_bytes(self) -> usize {
+        match self {
+            BcFormat::Bc1 | BcFormat::Bc1Rgb | BcFormat::Bc4 { .. } => 8,
+            _ => 16,
+        }
+    }
+
+    /// Bytes per decoded texel. BC6H decodes to four half floats, the
+    /// rest to four bytes.
+    pub(crate) fn texel_bytes(self) -> usize {
+        match self {
+            BcFormat::Bc6h { .. } => 8,
+            _ => 4,
+        }
+    }
+}
+
+/// Decodes a `width` by `height` image made of 4x4 blocks, stored row
+/// by row. `data` needs to hold every block, including the partial
+/// ones on the right and bottom edges.
+///
+/// BC4 decodes to red and BC5 to red and green, with blue at 0 and
+/// alpha at 1. Signed formats keep their texels as `i8`s.
+pub(crate) fn decompress(format: BcFormat, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = (width + 3) / 4;
+    let blocks_y = (height + 3) / 4;
+    let block_bytes = format.block_bytes();
+    let texel_bytes = format.texel_bytes();
+    assert!(data.len() >= blocks_x * blocks_y * block_bytes);
+
+    let mut pixels = vec![0; width * height * texel_bytes];
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let offset = (by * blocks_x + bx) * block_bytes;
+            let block = &data[offset..offset + block_bytes];
+            let texels = decode_block(format, block);
+            for y in 0..4.min(height - by * 4) {
+                for x in 0..4.min(width - bx * 4) {
+                    let texel = &texels[(y * 4 + x) * texel_bytes..][..texel_bytes];
+                    let dst = ((by * 4 + y) * width + bx * 4 + x) * texel_bytes;
+                    pixels[dst..dst + texel_bytes].copy_from_slice(texel);
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// The 16 texels of one block, row by row.
+fn decode_block(format: BcFormat, block: &[u8]) -> Vec<u8> {
+    match format {
+        BcFormat::Bc1 => flatten(&decode_bc1(block, true)),
+        BcFormat::Bc1Rgb => {
+            let mut texels = decode_bc1(block, true);
+            for texel in texels.iter_mut() {
+                texel[3] = 255;
+            }
+            flatten(&texels)
+        }
+        BcFormat::Bc2 => {
+            let mut texels = decode_bc1(&block[8..], false);
+            let alpha = read_u64(&block[..8]);
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[3] = ((alpha >> (i * 4)) & 0xF) as u8 * 17;
+            }
+            flatten(&texels)
+        }
+        BcFormat::Bc3 => {
+            let mut texels = decode_bc1(&block[8..], false);
+            let alpha = decode_bc4(&block[..8], false);
+            for (texel, a) in texels.iter_mut().zip(alpha.iter()) {
+                texel[3] = *a;
+            }
+            flatten(&texels)
+        }
+        BcFormat::Bc4 { signed } => {
+            let red = decode_bc4(block, signed);
+            let texels: Vec<[u8; 4]> = red.iter().map(|&r| [r, 0, 0, one(signed)]).collect();
+            flatten(&texels)
+        }
+        BcFormat::Bc5 { signed } => {
+            let red = decode_bc4(&block[..8], signed);
+            let green = decode_bc4(&block[8..], signed);
+            let texels: Vec<[u8; 4]> = red
+                .iter()
+                .zip(green.iter())
+                .map(|(&r, &g)| [r, g, 0, one(signed)])
+                .collect();
+            flatten(&texels)
+        }
+        BcFormat::Bc6h { signed } => decode_bc6h(block, signed)
+            .iter()
+            .flat_map(|texel| texel.iter().flat_map(|c| c.to_le_bytes().to_vec()))
+            .collect(),
+        BcFormat::Bc7 => flatten(&decode_bc7(block)),
+    }
+}
+
+fn flatten(texels: &[[u8; 4]]) -> Vec<u8> {
+    texels
+        .iter()
+        .flat_map(|texel| texel.iter().copied())
+        .collect()
+}
+
+fn one(signed: bool) -> u8 {
+    if signed {
+        127
+    } else {
+        255
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(word)
+}
+
+fn expand_565(color: u16) -> [u8; 4] {
+    let r = ((color >> 11) & 0x1F) as u8;
+    let g = ((color >> 5) & 0x3F) as u8;
+    let b = (color & 0x1F) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+        255,
+    ]
+}
+
+/// BC1 blocks, and the color half of BC2 and BC3 blocks, which always
+/// use four colors.
+fn decode_bc1(block: &[u8], allow_transparent: bool) -> [[u8; 4]; 16] {
+    let c0 = read_u16(&block[0..]);
+    let c1 = read_u16(&block[2..]);
+    let (e0, e1) = (expand_565(c0), expand_565(c1));
+    let mix = |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+
+    let mut palette = [e0, e1, [0; 4], [0; 4]];
+    if c0 > c1 || !allow_transparent {
+        for i in 0..3 {
+            palette[2][i] = mix(e0[i], e1[i], 2, 1);
+            palette[3][i] = mix(e0[i], e1[i], 1, 2);
+        }
+        palette[2][3] = 255;
+        palette[3][3] = 255;
+    } else {
+        for i in 0..3 {
+            palette[2][i] = mix(e0[i], e1[i], 1, 1);
+        }
+        palette[2][3] = 255;
+        // palette[3] stays transparent black
+    }
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (i * 2)) & 3) as usize];
+    }
+    texels
+}
+
+/// A single channel block, also used for the alpha of BC3 and both
+/// channels of BC5.
+fn decode_bc4(block: &[u8], signed: bool) -> [u8; 16] {
+    let mut palette = [0i32; 8];
+    if signed {
+        palette[0] = (block[0] as i8).max(-127) as i32;
+        palette[1] = (block[1] as i8).max(-127) as i32;
+    } else {
+        palette[0] = block[0] as i32;
+        palette[1] = block[1] as i32;
+    }
+    let (a0, a1) = (palette[0], palette[1]);
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i as usize + 1] = ((7 - i) * a0 + i * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i as usize + 1] = ((5 - i) * a0 + i * a1) / 5;
+        }
+        let (min, max) = if signed { (-127, 127) } else { (0, 255) };
+        palette[6] = min;
+        palette[7] = max;
+    }
+
+    let indices = read_u64(block) >> 16;
+    let mut texels = [0; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        // Signed values are stored as their two's complement bytes
+        *texel = palette[((indices >> (i * 3)) & 7) as usize] as u8;
+    }
+    texels
+}
+
+/// Reads a block's bits from least to most significant.
+struct Bits {
+    bits: u128,
+}
+
+impl Bits {
+    fn new(block: &[u8]) -> Self {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&block[..16]);
+        Self {
+            bits: u128::from_le_bytes(bytes),
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits & ((1 << count) - 1)) as u32;
+        self.bits >>= count;
+        value
+    }
+}
+
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weights(index_bits: u32) -> &'static [u32] {
+    match index_bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
+
+/// Which texels belong to the second subset of the two subset
+/// partitions, one bit per texel.
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+    0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+    0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+    0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// The subset of each texel for the three subset partitions, two bits
+/// per texel.
+const PARTITIONS_3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050,
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250,
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500,
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200,
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, 0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50,
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600,
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580, 0xAA141414, 0x96960000,
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254,
+];
+
+/// The texel holding the implied index bit of the second subset of the
+/// two subset partitions.
+const ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The same for the second and third subsets of the three subset
+/// partitions.
+const ANCHORS_3: [[usize; 2]; 64] = [
+    [3, 15],
+    [3, 8],
+    [15, 8],
+    [15, 3],
+    [8, 15],
+    [3, 15],
+    [15, 3],
+    [15, 8],
+    [8, 15],
+    [8, 15],
+    [6, 15],
+    [6, 15],
+    [6, 15],
+    [5, 15],
+    [3, 15],
+    [3, 8],
+    [3, 15],
+    [3, 8],
+    [8, 15],
+    [15, 3],
+    [3, 15],
+    [3, 8],
+    [6, 15],
+    [10, 8],
+    [5, 3],
+    [8, 15],
+    [8, 6],
+    [6, 10],
+    [8, 15],
+    [5, 15],
+    [15, 10],
+    [15, 8],
+    [8, 15],
+    [15, 3],
+    [3, 15],
+    [5, 10],
+    [6, 10],
+    [10, 8],
+    [8, 9],
+    [15, 10],
+    [15, 6],
+    [3, 15],
+    [15, 8],
+    [5, 15],
+    [15, 3],
+    [15, 6],
+    [15, 6],
+    [15, 8],
+    [3, 15],
+    [15, 3],
+    [5, 15],
+    [5, 15],
+    [5, 15],
+    [8, 15],
+    [5, 15],
+    [10, 15],
+    [5, 15],
+    [10, 15],
+    [8, 15],
+    [13, 15],
+    [15, 3],
+    [12, 15],
+    [3, 15],
+    [3, 8],
+];
+
+fn subset(subsets: usize, partition: usize, texel: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => ((PARTITIONS_2[partition] >> texel) & 1) as usize,
+        _ => ((PARTITIONS_3[partition] >> (texel * 2)) & 3) as usize,
+    }
+}
+
+fn is_anchor(subsets: usize, partition: usize, texel: usize) -> bool {
+    texel == 0
+        || match subsets {
+            1 => false,
+            2 => ANCHORS_2[partition] == texel,
+            _ => ANCHORS_3[partition].contains(&texel),
+        }
+}
+
+/// Reads 16 indices, with the anchor texels one bit shorter.
+fn read_indices(bits: &mut Bits, index_bits: u32, subsets: usize, partition: usize) -> [usize; 16] {
+    let mut indices = [0; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        let count = if is_anchor(subsets, partition, texel) {
+            index_bits - 1
+        } else {
+            index_bits
+        };
+        *index = bits.read(count) as usize;
+    }
+    indices
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// One p-bit per endpoint.
+    endpoint_p_bits: bool,
+    /// One p-bit per subset, shared by both its endpoints.
+    shared_p_bits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: true,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 3,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 2,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 4,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+];
+
+/// Scales an endpoint of `bits` bits up to 8 bits by repeating its high
+/// bits in the low ones.
+fn unquantize(value: u32, bits: u32) -> u32 {
+    let value = value << (8 - bits);
+    value | (value >> bits)
+}
+
+fn interpolate(e0: u32, e1: u32, weight: u32) -> u32 {
+    ((64 - weight) * e0 + weight * e1 + 32) >> 6
+}
+
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let mode_index = block[0].trailing_zeros() as usize;
+    if mode_index >= BC7_MODES.len() {
+        // Reserved, decoders are required to return transparent black
+        return [[0; 4]; 16];
+    }
+    let mode = &BC7_MODES[mode_index];
+    let mut bits = Bits::new(block);
+    bits.read(mode_index as u32 + 1);
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let endpoints = mode.subsets * 2;
+    let mut colors = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for color in colors.iter_mut().take(endpoints) {
+            color[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for color in colors.iter_mut().take(endpoints) {
+        color[3] = bits.read(mode.alpha_bits);
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_p_bits || mode.shared_p_bits {
+        let p_bits: Vec<u32> = if mode.endpoint_p_bits {
+            (0..endpoints).map(|_| bits.read(1)).collect()
+        } else {
+            (0..mode.subsets)
+                .flat_map(|_| {
+                    let p = bits.read(1);
+                    vec![p, p]
+                })
+                .collect()
+        };
+        for (color, p) in colors.iter_mut().zip(p_bits) {
+            for c in color.iter_mut() {
+                *c = (*c << 1) | p;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+    for color in colors.iter_mut().take(endpoints) {
+        for c in color[..3].iter_mut() {
+            *c = unquantize(*c, color_bits);
+        }
+        color[3] = if alpha_bits > 0 {
+            unquantize(color[3], alpha_bits)
+        } else {
+            255
+        };
+    }
+
+    let indices = read_indices(&mut bits, mode.index_bits, mode.subsets, partition);
+    let secondary = if mode.secondary_index_bits > 0 {
+        Some(read_indices(&mut bits, mode.secondary_index_bits, 1, 0))
+    } else {
+        None
+    };
+
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let s = subset(mode.subsets, partition, i);
+        let (e0, e1) = (colors[s * 2], colors[s * 2 + 1]);
+        let (color_index, color_weights, alpha_index, alpha_weights) = match secondary {
+            None => {
+                let w = weights(mode.index_bits);
+                (indices[i], w, indices[i], w)
+            }
+            Some(secondary) if index_selection == 0 => (
+                indices[i],
+                weights(mode.index_bits),
+                secondary[i],
+                weights(mode.secondary_index_bits),
+            ),
+            Some(secondary) => (
+                secondary[i],
+                weights(mode.secondary_index_bits),
+                indices[i],
+                weights(mode.index_bits),
+            ),
+        };
+        for c in 0..3 {
+            texel[c] = interpolate(e0[c], e1[c], color_weights[color_index]) as u8;
+        }
+        texel[3] = interpolate(e0[3], e1[3], alpha_weights[alpha_index]) as u8;
+        match rotation {
+            1 => texel.swap(0, 3),
+            2 => texel.swap(1, 3),
+            3 => texel.swap(2, 3),
+            _ => {}
+        }
+    }
+    texels
+}
+
+// The endpoint fields of BC6H blocks, named after the spec's w, x, y
+// and z endpoints
+const RW: usize = 0;
+const GW: usize = 1;
+const BW: usize = 2;
+const RX: usize = 3;
+const GX: usize = 4;
+const BX: usize = 5;
+const RY: usize = 6;
+const GY: usize = 7;
+const BY: usize = 8;
+const RZ: usize = 9;
+const GZ: usize = 10;
+const BZ: usize = 11;
+
+struct Bc6hMode {
+    /// The value of the mode bits.
+    id: u32,
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    /// (field, first bit, bit count) in the order they're stored. Bits
+    /// stored backwards are listed one at a time.
+    fields: &'static [(usize, u32, u32)],
+}
+
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        id: 0b00,
+        transformed: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        fields: &[
+            (GY, 4, 1),
+            (BY, 4, 1),
+            (BZ, 4, 1),
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b01,
+        transformed: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        fields: &[
+            (GY, 5, 1),
+            (GZ, 4, 1),
+            (GZ, 5, 1),
+            (RW, 0, 7),
+            (BZ, 0, 1),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 7),
+            (BY, 5, 1),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 7),
+            (BZ, 3, 1),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        id: 0b00010,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 5),
+            (RW, 10, 1),
+            (GY, 0, 4),
+            (GX, 0, 4),
+            (GW, 10, 1),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 4),
+            (BW, 10, 1),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b00110,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 10, 1),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (GW, 10, 1),
+            (GZ, 0, 4),
+            (BX, 0, 4),
+            (BW, 10, 1),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 4),
+            (BZ, 0, 1),
+            (BZ, 2, 1),
+            (RZ, 0, 4),
+            (GY, 4, 1),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b01010,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 10, 1),
+            (BY, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 4),
+            (GW, 10, 1),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BW, 10, 1),
+            (BY, 0, 4),
+            (RY, 0, 4),
+            (BZ, 1, 1),
+            (BZ, 2, 1),
+            (RZ, 0, 4),
+            (BZ, 4, 1),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b01110,
+        transformed: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        fields: &[
+            (RW, 0, 9),
+            (BY, 4, 1),
+            (GW, 0, 9),
+            (GY, 4, 1),
+            (BW, 0, 9),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b10010,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        fields: &[
+            (RW, 0, 8),
+            (GZ, 4, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (BZ, 3, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        id: 0b10110,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        fields: &[
+            (RW, 0, 8),
+            (BZ, 0, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (GY, 5, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (GZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b11010,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        fields: &[
+            (RW, 0, 8),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (BY, 5, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b11110,
+        transformed: false,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        fields: &[
+            (RW, 0, 6),
+            (GZ, 4, 1),
+            (BZ, 0, 1),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 6),
+            (GY, 5, 1),
+            (BY, 5, 1),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 6),
+            (GZ, 5, 1),
+            (BZ, 3, 1),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        id: 0b00011,
+        transformed: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 10),
+            (GX, 0, 10),
+            (BX, 0, 10),
+        ],
+    },
+    Bc6hMode {
+        id: 0b00111,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 9),
+            (RW, 10, 1),
+            (GX, 0, 9),
+            (GW, 10, 1),
+            (BX, 0, 9),
+            (BW, 10, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b01011,
+        transformed: true,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 8),
+            (RW, 11, 1),
+            (RW, 10, 1),
+            (GX, 0, 8),
+            (GW, 11, 1),
+            (GW, 10, 1),
+            (BX, 0, 8),
+            (BW, 11, 1),
+            (BW, 10, 1),
+        ],
+    },
+    Bc6hMode {
+        id: 0b01111,
+        transformed: true,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 15, 1),
+            (RW, 14, 1),
+            (RW, 13, 1),
+            (RW, 12, 1),
+            (RW, 11, 1),
+            (RW, 10, 1),
+            (GX, 0, 4),
+            (GW, 15, 1),
+            (GW, 14, 1),
+            (GW, 13, 1),
+            (GW, 12, 1),
+            (GW, 11, 1),
+            (GW, 10, 1),
+            (BX, 0, 4),
+            (BW, 15, 1),
+            (BW, 14, 1),
+            (BW, 13, 1),
+            (BW, 12, 1),
+            (BW, 11, 1),
+            (BW, 10, 1),
+        ],
+    },
+];
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+/// Scales an endpoint up to 16 bits, or 15 bits and a sign.
+fn unquantize_bc6h(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if value < 0 {
+            -unquantized
+        } else {
+            unquantized
+        }
+    }
+}
+
+/// Scales an interpolated value down to the bits of a half float.
+fn finish_bc6h(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+/// Half float texels, with alpha at 1.
+fn decode_bc6h(block: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    const ONE: u16 = 0x3C00;
+    let mut bits = Bits::new(block);
+    let mut id = bits.read(2);
+    if id > 1 {
+        id |= bits.read(3) << 2;
+    }
+    let mode = match BC6H_MODES.iter().find(|mode| mode.id == id) {
+        Some(mode) => mode,
+        // Reserved modes decode to black
+        None => return [[0, 0, 0, ONE]; 16],
+    };
+
+    let mut fields = [0i32; 12];
+    for &(field, first, count) in mode.fields {
+        fields[field] |= (bits.read(count) as i32) << first;
+    }
+    let subsets = if mode.id & 0b11 == 0b11 { 1 } else { 2 };
+    let partition = if subsets == 2 {
+        bits.read(5) as usize
+    } else {
+        0
+    };
+
+    let endpoints = subsets * 2;
+    let mut colors = [[0i32; 3]; 4];
+    for (e, color) in colors.iter_mut().enumerate().take(endpoints) {
+        for (c, value) in color.iter_mut().enumerate() {
+            *value = fields[e * 3 + c];
+        }
+    }
+    let eb = mode.endpoint_bits;
+    for c in 0..3 {
+        if signed {
+            colors[0][c] = sign_extend(colors[0][c], eb);
+        }
+        let base = colors[0][c];
+        for color in colors.iter_mut().take(endpoints).skip(1) {
+            if mode.transformed || signed {
+                color[c] = sign_extend(color[c], mode.delta_bits[c]);
+            }
+            if mode.transformed {
+                color[c] = (base + color[c]) & ((1 << eb) - 1);
+                if signed {
+                    color[c] = sign_extend(color[c], eb);
+                }
+            }
+        }
+    }
+    for color in colors.iter_mut().take(endpoints) {
+        for value in color.iter_mut() {
+            *value = unquantize_bc6h(*value, eb, signed);
+        }
+    }
+
+    let index_bits = if subsets == 1 { 4 } else { 3 };
+    let indices = read_indices(&mut bits, index_bits, subsets, partition);
+    let weights = weights(index_bits);
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let s = subset(subsets, partition, i);
+        let (e0, e1) = (colors[s * 2], colors[s * 2 + 1]);
+        let w = weights[indices[i]] as i32;
+        for c in 0..3 {
+            let value = ((64 - w) * e0[c] + w * e1[c] + 32) >> 6;
+            texel[c] = finish_bc6h(value, signed);
+        }
+        texel[3] = ONE;
+    }
+    texels
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Red and blue endpoints, with texels 0 to 3 using indices 0, 1, 2
+    // and 3
+    const RED_BLUE: [u8; 8] = [0x00, 0xF8, 0x1F, 0x00, 0b1110_0100, 0, 0, 0];
+    // The same endpoints swapped, which switches BC1 to three colors
+    // and transparency
+    const BLUE_RED: [u8; 8] = [0x1F, 0x00, 0x00, 0xF8, 0b1110_0100, 0, 0, 0];
+
+    /// The texels of a block of a format that decodes to bytes.
+    fn rgba(format: BcFormat, block: &[u8]) -> Vec<[u8; 4]> {
+        decode_block(format, block)
+            .chunks(4)
+            .map(|texel| [texel[0], texel[1], texel[2], texel[3]])
+            .collect()
+    }
+
+    /// A BC4 block with its first texels using `indices`.
+    fn bc4_block(a0: u8, a1: u8, indices: &[u64]) -> [u8; 8] {
+        let mut block = [a0, a1, 0, 0, 0, 0, 0, 0];
+        let indices = indices
+            .iter()
+            .enumerate()
+            .fold(0u64, |bits, (i, index)| bits | index << (i * 3));
+        block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+        block
+    }
+
+    /// Packs (value, bit count) pairs into a block, least significant
+    /// bits first.
+    fn pack(fields: &[(u128, u32)]) -> [u8; 16] {
+        let mut bits = 0u128;
+        let mut offset = 0;
+        for &(value, count) in fields {
+            bits |= value << offset;
+            offset += count;
+        }
+        assert!(offset <= 128);
+        bits.to_le_bytes()
+    }
+
+    #[test]
+    fn bc1_interpolates_and_cuts_out() {
+        let texels = rgba(BcFormat::Bc1, &RED_BLUE);
+        assert_eq!(texels[0], [255, 0, 0, 255]);
+        assert_eq!(texels[1], [0, 0, 255, 255]);
+        assert_eq!(texels[2], [170, 0, 85, 255]);
+        assert_eq!(texels[3], [85, 0, 170, 255]);
+
+        let texels = rgba(BcFormat::Bc1, &BLUE_RED);
+        assert_eq!(texels[2], [127, 0, 127, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc1_without_alpha_is_opaque() {
+        let rgb = rgba(BcFormat::Bc1Rgb, &BLUE_RED);
+        assert_eq!(rgb[2], [127, 0, 127, 255]);
+        assert_eq!(rgb[3], [0, 0, 0, 255]);
+        assert_eq!(
+            rgba(BcFormat::Bc1Rgb, &RED_BLUE),
+            rgba(BcFormat::Bc1, &RED_BLUE)
+        );
+    }
+
+    #[test]
+    fn bc2_has_explicit_alpha() {
+        // Alpha 0, 5, 10 and 15 for the first texels, then the BC1 block
+        // with its endpoints swapped, which BC2 still reads as four
+        // colors
+        let mut block = [0x50, 0xFA, 0, 0, 0, 0, 0, 0].to_vec();
+        block.extend_from_slice(&BLUE_RED);
+        let texels = rgba(BcFormat::Bc2, &block);
+        assert_eq!(texels[0], [0, 0, 255, 0]);
+        assert_eq!(texels[1], [255, 0, 0, 85]);
+        assert_eq!(texels[2], [85, 0, 170, 170]);
+        assert_eq!(texels[3], [170, 0, 85, 255]);
+    }
+
+    #[test]
+    fn bc3_alpha_has_two_palettes() {
+        let mut block = bc4_block(200, 100, &[0, 1, 2, 7]).to_vec();
+        block.extend_from_slice(&RED_BLUE);
+        let alpha = rgba(BcFormat::Bc3, &block)
+            .iter()
+            .map(|texel| texel[3])
+            .collect::<Vec<_>>();
+        assert_eq!(&alpha[..4], &[200, 100, 185, 114]);
+        assert_eq!(rgba(BcFormat::Bc3, &block)[1][..3], [0, 0, 255]);
+    }
+
+    #[test]
+    fn bc4_has_two_palettes() {
+        let block = bc4_block(200, 100, &[0, 1, 2, 7]);
+        assert_eq!(&decode_bc4(&block, false)[..4], &[200, 100, 185, 114]);
+        let texels = rgba(BcFormat::Bc4 { signed: false }, &block);
+        assert_eq!(texels[2], [185, 0, 0, 255]);
+
+        // With the endpoints swapped there are four steps between
+        // them, then 0 and 255
+        let block = bc4_block(100, 200, &[0, 1, 2, 7]);
+        assert_eq!(&decode_bc4(&block, false)[..4], &[100, 200, 120, 255]);
+        let block = bc4_block(100, 200, &[6, 5]);
+        assert_eq!(&decode_bc4(&block, false)[..2], &[0, 180]);
+    }
+
+    #[test]
+    fn signed_bc4_is_clamped_and_interpolated_as_signed() {
+        // -128 is clamped to -127, and -127 is less than 127, so this
+        // is the palette with four steps and the extremes
+        let block = bc4_block(0x80, 0x7F, &[0, 2, 3, 4, 5, 7, 6]);
+        let red = decode_bc4(&block, true)
+            .iter()
+            .map(|&r| r as i8)
+            .collect::<Vec<_>>();
+        assert_eq!(&red[..7], &[-127, -76, -25, 25, 76, 127, -127]);
+
+        let block = bc4_block(0x7F, 0x81, &[2]);
+        assert_eq!(decode_bc4(&block, true)[0] as i8, 90);
+        let texels = rgba(BcFormat::Bc4 { signed: true }, &block);
+        assert_eq!(texels[0], [90, 0, 0, 127]);
+    }
+
+    #[test]
+    fn bc5_decodes_red_and_green() {
+        let mut block = bc4_block(200, 100, &[0, 1]).to_vec();
+        block.extend_from_slice(&bc4_block(0, 255, &[0, 2]));
+        let texels = rgba(BcFormat::Bc5 { signed: false }, &block);
+        assert_eq!(texels[0], [200, 0, 0, 255]);
+        assert_eq!(texels[1], [100, 51, 0, 255]);
+
+        let signed = rgba(BcFormat::Bc5 { signed: true }, &[0; 16]);
+        assert!(signed.iter().all(|&texel| texel == [0, 0, 0, 127]));
+    }
+
+    #[test]
+    fn bc6h_single_subset_covers_the_range() {
+        // Mode 11, with white and black endpoints in each channel
+        let mut fields = vec![(0b00011, 5)];
+        fields.extend_from_slice(&[(1023, 10), (1023, 10), (1023, 10)]);
+        fields.extend_from_slice(&[(0, 10), (0, 10), (0, 10)]);
+        fields.extend_from_slice(&[(0, 3), (15, 4)]);
+        let texels = decode_bc6h(&pack(&fields), false);
+        // The largest finite half float
+        assert_eq!(texels[0], [0x7BFF, 0x7BFF, 0x7BFF, 0x3C00]);
+        assert_eq!(texels[1], [0, 0, 0, 0x3C00]);
+    }
+
+    #[test]
+    fn signed_bc6h_keeps_the_sign() {
+        // Mode 11 again, from the largest to the smallest 10 bit value
+        let mut fields = vec![(0b00011, 5)];
+        fields.extend_from_slice(&[(0x1FF, 10), (0x1FF, 10), (0x1FF, 10)]);
+        fields.extend_from_slice(&[(0x201, 10), (0x201, 10), (0x201, 10)]);
+        fields.extend_from_slice(&[(0, 3), (15, 4)]);
+        let texels = decode_bc6h(&pack(&fields), true);
+        assert_eq!(texels[0], [0x7BFF, 0x7BFF, 0x7BFF, 0x3C00]);
+        assert_eq!(texels[1], [0xFBFF, 0xFBFF, 0xFBFF, 0x3C00]);
+    }
+
+    #[test]
+    fn bc6h_deltas_are_added_to_the_first_endpoint() {
+        // Mode 12 stores an 11 bit endpoint, with its top bit after the
+        // 9 bit delta from it, which here is -1
+        let mut fields = vec![(0b00111, 5)];
+        fields.extend_from_slice(&[(0, 10), (0, 10), (0, 10)]);
+        fields.extend_from_slice(&[(0x1FF, 9), (1, 1), (0x1FF, 9), (1, 1), (0x1FF, 9), (1, 1)]);
+        fields.extend_from_slice(&[(0, 3), (15, 4)]);
+        let texels = decode_bc6h(&pack(&fields), false);
+        // 1024 and 1023 out of 2047
+        assert_eq!(texels[0], [0x3E07, 0x3E07, 0x3E07, 0x3C00]);
+        assert_eq!(texels[1], [0x3DF8, 0x3DF8, 0x3DF8, 0x3C00]);
+    }
+
+    #[test]
+    fn bc6h_reserved_mode_is_black() {
+        let texels = decode_bc6h(&pack(&[(0b10011, 5)]), false);
+        assert_eq!(texels, [[0, 0, 0, 0x3C00]; 16]);
+        // Half floats are stored little endian
+        let pixels = decompress(
+            BcFormat::Bc6h { signed: false },
+            1,
+            1,
+            &pack(&[(0b10011, 5)]),
+        );
+        assert_eq!(pixels, [0, 0, 0, 0, 0, 0, 0x00, 0x3C]);
+    }
+
+    #[test]
+    fn bc7_mode_1_uses_the_partition_and_shared_p_bits() {
+        let mut fields = vec![(0b10, 2), (0, 6)];
+        // Red endpoints for the first subset and blue for the second
+        fields.extend_from_slice(&[(63, 6), (63, 6), (0, 6), (0, 6)]);
+        fields.extend_from_slice(&[(0, 6), (0, 6), (0, 6), (0, 6)]);
+        fields.extend_from_slice(&[(0, 6), (0, 6), (63, 6), (63, 6)]);
+        // One p-bit for each subset
+        fields.extend_from_slice(&[(0, 1), (1, 1)]);
+        let texels = decode_bc7(&pack(&fields));
+        // The first partition puts the right half in the second subset
+        assert_eq!(texels[0], [253, 0, 0, 255]);
+        assert_eq!(texels[1], [253, 0, 0, 255]);
+        assert_eq!(texels[2], [2, 2, 255, 255]);
+        assert_eq!(texels[15], [2, 2, 255, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_5_rotates_channels() {
+        // Rotation 1 swaps red and alpha
+        let mut fields = vec![(1 << 5, 6), (1, 2)];
+        fields.extend_from_slice(&[(127, 7), (0, 7), (0, 7), (0, 7), (0, 7), (0, 7)]);
+        fields.extend_from_slice(&[(0, 8), (255, 8)]);
+        // Color indices, then alpha indices
+        fields.extend_from_slice(&[(0, 31), (1, 1), (0, 30)]);
+        let texels = decode_bc7(&pack(&fields));
+        assert_eq!(texels[0], [84, 0, 0, 255]);
+        assert_eq!(texels[1], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_6_uses_p_bits() {
+        let mut fields = vec![(1 << 6, 7)];
+        // Red, green, blue then alpha endpoints
+        fields.extend_from_slice(&[(127, 7), (0, 7), (0, 7), (64, 7), (0, 7), (0, 7)]);
+        fields.extend_from_slice(&[(127, 7), (0, 7)]);
+        // p-bits
+        fields.extend_from_slice(&[(1, 1), (0, 1)]);
+        // The first texel's index is a bit shorter
+        fields.extend_from_slice(&[(0, 3), (15, 4), (7, 4)]);
+        let texels = decode_bc7(&pack(&fields));
+        // The p-bit is the lowest bit of every channel
+        assert_eq!(texels[0], [255, 1, 1, 255]);
+        assert_eq!(texels[1], [0, 128, 0, 0]);
+        assert_eq!(texels[2], [135, 61, 1, 135]);
+        assert_eq!(texels[15], [255, 1, 1, 255]);
+    }
+
+    #[test]
+    fn bc7_reserved_mode_is_transparent() {
+        assert_eq!(decode_bc7(&[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn decompress_crops_partial_blocks() {
+        let block = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+        let pixels = decompress(BcFormat::Bc1, 5, 3, &[block, block].concat());
+        assert_eq!(pixels.len(), 5 * 3 * 4);
+        assert!(pixels.chunks(4).all(|texel| texel == [255, 0, 0, 255]));
+    }
+}
@@ -1,3 +1,4 @@
+use anyhow::*;
 use std::mem;
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 
@@ -12,6 +13,7 @@ where
 {
     pub buffer: wgpu::Buffer,
     pub data: Vec<R>,
+    size: wgpu::BufferAddress,
 }
 
 impl<R: Copy + bytemuck::Pod + bytemuck::Zeroable> RawBuffer<R> {
@@ -34,11 +36,23 @@ impl<R: Copy + bytemuck::Pod + bytemuck::Zeroable> RawBuffer<R> {
     }
 
     pub fn from_parts(buffer: wgpu::Buffer, data: Vec<R>, _usage: wgpu::BufferUsage) -> Self {
-        Self { buffer, data }
+        let size = (data.len() * mem::size_of::<R>()) as wgpu::BufferAddress;
+        Self { buffer, data, size }
     }
 
+    /// Wraps a buffer of `size` bytes that has no data on the CPU side,
+    /// like the ones [Buffer::staging] creates.
+    pub fn with_size(buffer: wgpu::Buffer, size: wgpu::BufferAddress) -> Self {
+        Self {
+            buffer,
+            data: Vec::new(),
+            size,
+        }
+    }
+
+    /// The size of the buffer on the GPU, in bytes.
     pub fn buffer_size(&self) -> wgpu::BufferAddress {
-        (self.data.len() * mem::size_of::<R>()) as wgpu::BufferAddress
+        self.size
     }
 }
 
@@ -55,23 +69,27 @@ impl<U: ToRaw<Output = R>, R: Copy + bytemuck::Pod + bytemuck::Zeroable> Buffer<
         Self::with_usage(device, data, usage)
     }
 
+    /// Storage buffers can be copied from, so that compute results can
+    /// be read back through a [Buffer::staging] buffer.
     pub fn storage(device: &wgpu::Device, data: Vec<U>) -> Self {
-        let usage = wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST;
+        let usage =
+            wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::COPY_SRC;
         Self::with_usage(device, data, usage)
     }
 
+    /// A buffer the same size as `other` that it can be copied into
+    /// and read back with [Buffer::read_raw]. wgpu only allows
+    /// `MAP_READ` together with `COPY_DST`.
     pub fn staging(device: &wgpu::Device, other: &Self) -> Self {
         let buffer_size = other.raw_buffer.buffer_size();
-        let usage = wgpu::BufferUsage::COPY_SRC
-            | wgpu::BufferUsage::MAP_READ
-            | wgpu::BufferUsage::MAP_WRITE;
+        let usage = wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ;
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             size: buffer_size,
             usage,
             label: None,
             mapped_at_creation: false,
         });
-        let raw_buffer = RawBuffer::from_parts(buffer, Vec::new(), usage);
+        let raw_buffer = RawBuffer::with_size(buffer, buffer_size);
         Self::from_parts(Vec::new(), raw_buffer, usage)
     }
 
@@ -87,4 +105,25 @@ impl<U: ToRaw<Output = R>, R: Copy + bytemuck::Pod + bytemuck::Zeroable> Buffer<
             usage,
         }
     }
+
+    /// Maps the buffer and copies its contents out. Only works for
+    /// buffers created with [Buffer::staging], and any copies into it
+    /// need to have been submitted first.
+    pub async fn read_raw(&self, device: &wgpu::Device) -> Result<Vec<R>> {
+        if !self.usage.contains(wgpu::BufferUsage::MAP_READ) {
+            bail!("Buffer can't be read back. Use Buffer::staging to create one that can.");
+        }
+
+        let buffer_slice = self.raw_buffer.buffer.slice(..);
+        let request = buffer_slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        request.await?;
+
+        let mapped = buffer_slice.get_mapped_range();
+        let data = bytemuck::cast_slice::<u8, R>(&mapped).to_vec();
+        // The mapped range has to be dropped before unmapping
+        drop(mapped);
+        self.raw_buffer.buffer.unmap();
+        Ok(data)
+    }
 }
@@ -4,6 +4,8 @@ use std::time::Duration;
 use winit::dpi::LogicalPosition;
 use winit::event::*;
 
+use crate::input::InputEvent;
+
 #[cfg_attr(rustfmt, rustfmt_skip)]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -76,6 +78,22 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    pub fn fovy(&self) -> Rad<f32> {
+        self.fovy
+    }
+
+    pub fn znear(&self) -> f32 {
+        self.znear
+    }
+
+    pub fn zfar(&self) -> f32 {
+        self.zfar
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
     }
@@ -94,6 +112,7 @@ pub struct CameraController {
     scroll: f32,
     speed: f32,
     sensitivity: f32,
+    is_rotating: bool,
 }
 
 impl CameraController {
@@ -110,6 +129,7 @@ impl CameraController {
             scroll: 0.0,
             speed,
             sensitivity,
+            is_rotating: false,
         }
     }
 
@@ -149,8 +169,9 @@ impl CameraController {
     }
 
     pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
-        self.rotate_horizontal = mouse_dx as f32;
-        self.rotate_vertical = mouse_dy as f32;
+        // Raw mouse motion can arrive several times a frame
+        self.rotate_horizontal += mouse_dx as f32;
+        self.rotate_vertical += mouse_dy as f32;
     }
 
     pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
@@ -161,6 +182,48 @@ impl CameraController {
         };
     }
 
+    /// Moves with the keys [CameraController::process_keyboard] knows,
+    /// zooms with the scroll wheel and looks around while the left mouse
+    /// button is held. Returns whether `event` was used.
+    pub fn process_input(&mut self, event: &InputEvent) -> bool {
+        match event {
+            InputEvent::Key {
+                key: Some(key),
+                state,
+                ..
+            } => self.process_keyboard(*key, *state),
+            InputEvent::Scroll(delta) => {
+                self.process_scroll(delta);
+                true
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                state,
+            } => {
+                self.is_rotating = *state == ElementState::Pressed;
+                true
+            }
+            InputEvent::MouseMotion { dx, dy } if self.is_rotating => {
+                self.process_mouse(*dx, *dy);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Stops moving, as if every key and button was released. Keys
+    /// released while the window is in the background are never seen.
+    pub fn release_all(&mut self) {
+        self.amount_left = 0.0;
+        self.amount_right = 0.0;
+        self.amount_forward = 0.0;
+        self.amount_backward = 0.0;
+        self.amount_up = 0.0;
+        self.amount_down = 0.0;
+        self.scroll = 0.0;
+        self.is_rotating = false;
+    }
+
     pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
         let dt = dt.as_secs_f32();
 
@@ -202,3 +265,77 @@ impl CameraController {
         }
     }
 }
+
+/// A [Camera] flown around by a [CameraController]. A [Demo](crate::Demo)
+/// that returns one from [Demo::fly_camera](crate::Demo::fly_camera) has
+/// it driven by input, resized and updated by the framework.
+#[derive(Debug)]
+pub struct FlyCamera {
+    pub camera: Camera,
+    pub projection: Projection,
+    pub controller: CameraController,
+}
+
+impl FlyCamera {
+    /// Uses the same defaults as [camera_setup].
+    pub fn new<V: Into<Point3<f32>>, Y: Into<Rad<f32>>, P: Into<Rad<f32>>>(
+        position: V,
+        yaw: Y,
+        pitch: P,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (camera, projection, controller) = camera_setup(position, yaw, pitch, width, height);
+        Self {
+            camera,
+            projection,
+            controller,
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        self.controller.update_camera(&mut self.camera, dt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fly_camera_moves_while_keys_are_held() {
+        let mut fly = FlyCamera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0), 800, 600);
+        let forward = |state| InputEvent::Key {
+            key: Some(VirtualKeyCode::W),
+            scancode: 17,
+            state,
+        };
+
+        assert!(fly
+            .controller
+            .process_input(&forward(ElementState::Pressed)));
+        fly.update(Duration::from_secs(1));
+        assert!(fly.camera.position.x > 0.0);
+
+        let x = fly.camera.position.x;
+        fly.controller
+            .process_input(&forward(ElementState::Released));
+        fly.update(Duration::from_secs(1));
+        assert_eq!(fly.camera.position.x, x);
+    }
+
+    #[test]
+    fn mouse_only_looks_around_while_dragging() {
+        let mut controller = CameraController::new(4.0, 0.4);
+        let motion = InputEvent::MouseMotion { dx: 10.0, dy: 0.0 };
+        assert!(!controller.process_input(&motion));
+
+        controller.process_input(&InputEvent::MouseButton {
+            button: MouseButton::Left,
+            state: ElementState::Pressed,
+        });
+        assert!(controller.process_input(&motion));
+        assert!(controller.process_input(&motion));
+        assert_eq!(controller.rotate_horizontal, 20.0);
+    }
+}
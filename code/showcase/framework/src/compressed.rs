@@ -0,0 +1,681 @@
+use anyhow::*;
+use std::convert::TryFrom;
+use std::path::Path;
+
+use crate::bcn::{self, BcFormat};
+
+/// The mip levels of a texture as they're stored on the GPU, read from a
+/// DDS or KTX2 file. These are usually block compressed, which
+/// [Texture::from_data](crate::Texture::from_data) uploads as is when
+/// the device supports it, and decompresses when it doesn't.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureData {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Array layers, including the six faces of each cube.
+    pub layers: u32,
+    pub is_cube: bool,
+    /// Largest first. Each level holds every layer back to back.
+    pub levels: Vec<Vec<u8>>,
+}
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const KTX2_IDENTIFIER: &[u8] = &[
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// A full mip chain of the largest texture a u32 can describe.
+const MAX_MIP_LEVELS: u32 = 32;
+
+// BC1 without alpha, in linear and sRGB
+const VK_FORMAT_BC1_RGB_UNORM: u32 = 131;
+const VK_FORMAT_BC1_RGB_SRGB: u32 = 132;
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let word = offset
+        .checked_add(4)
+        .and_then(|end| bytes.get(offset..end))
+        .context("Unexpected end of texture file")?;
+    Ok(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    let low = read_u32(bytes, offset)? as u64;
+    let high = read_u32(bytes, offset + 4)? as u64;
+    Ok(low | high << 32)
+}
+
+/// The size of a block in texels and in bytes, for the formats
+/// [TextureData] supports.
+fn block_layout(format: wgpu::TextureFormat) -> Option<(u32, usize)> {
+    use wgpu::TextureFormat::*;
+    Some(match format {
+        Bc1RgbaUnorm | Bc1RgbaUnormSrgb | Bc4RUnorm | Bc4RSnorm => (4, 8),
+        Bc2RgbaUnorm | Bc2RgbaUnormSrgb | Bc3RgbaUnorm | Bc3RgbaUnormSrgb | Bc5RgUnorm
+        | Bc5RgSnorm | Bc6hRgbUfloat | Bc6hRgbSfloat | Bc7RgbaUnorm | Bc7RgbaUnormSrgb => (4, 16),
+        Rgba8Unorm | Rgba8UnormSrgb | Rgba8Snorm | Bgra8Unorm | Bgra8UnormSrgb => (1, 4),
+        Rgba16Float => (1, 8),
+        _ => return None,
+    })
+}
+
+/// How to decode a block compressed format, and the format the result
+/// has.
+fn bc_format(format: wgpu::TextureFormat) -> Option<(BcFormat, wgpu::TextureFormat)> {
+    use wgpu::TextureFormat::*;
+    Some(match format {
+        Bc1RgbaUnorm => (BcFormat::Bc1, Rgba8Unorm),
+        Bc1RgbaUnormSrgb => (BcFormat::Bc1, Rgba8UnormSrgb),
+        Bc2RgbaUnorm => (BcFormat::Bc2, Rgba8Unorm),
+        Bc2RgbaUnormSrgb => (BcFormat::Bc2, Rgba8UnormSrgb),
+        Bc3RgbaUnorm => (BcFormat::Bc3, Rgba8Unorm),
+        Bc3RgbaUnormSrgb => (BcFormat::Bc3, Rgba8UnormSrgb),
+        Bc4RUnorm => (BcFormat::Bc4 { signed: false }, Rgba8Unorm),
+        Bc4RSnorm => (BcFormat::Bc4 { signed: true }, Rgba8Snorm),
+        Bc5RgUnorm => (BcFormat::Bc5 { signed: false }, Rgba8Unorm),
+        Bc5RgSnorm => (BcFormat::Bc5 { signed: true }, Rgba8Snorm),
+        Bc6hRgbUfloat => (BcFormat::Bc6h { signed: false }, Rgba16Float),
+        Bc6hRgbSfloat => (BcFormat::Bc6h { signed: true }, Rgba16Float),
+        Bc7RgbaUnorm => (BcFormat::Bc7, Rgba8Unorm),
+        Bc7RgbaUnormSrgb => (BcFormat::Bc7, Rgba8UnormSrgb),
+        _ => return None,
+    })
+}
+
+fn check_mip_levels(mip_levels: u32) -> Result<u32> {
+    if mip_levels > MAX_MIP_LEVELS {
+        bail!(
+            "Texture has {} mip levels, more than the {} any texture can have",
+            mip_levels,
+            MAX_MIP_LEVELS
+        );
+    }
+    Ok(mip_levels)
+}
+
+/// wgpu can't create textures without texels.
+fn check_size(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        bail!("Texture is {}x{}, which has no texels", width, height);
+    }
+    Ok(())
+}
+
+fn srgb_if(
+    srgb: bool,
+    linear: wgpu::TextureFormat,
+    srgb_format: wgpu::TextureFormat,
+) -> wgpu::TextureFormat {
+    if srgb {
+        srgb_format
+    } else {
+        linear
+    }
+}
+
+fn dxgi_format(dxgi: u32) -> Result<wgpu::TextureFormat> {
+    use wgpu::TextureFormat::*;
+    Ok(match dxgi {
+        10 => Rgba16Float,
+        28 => Rgba8Unorm,
+        29 => Rgba8UnormSrgb,
+        31 => Rgba8Snorm,
+        71 => Bc1RgbaUnorm,
+        72 => Bc1RgbaUnormSrgb,
+        74 => Bc2RgbaUnorm,
+        75 => Bc2RgbaUnormSrgb,
+        77 => Bc3RgbaUnorm,
+        78 => Bc3RgbaUnormSrgb,
+        80 => Bc4RUnorm,
+        81 => Bc4RSnorm,
+        83 => Bc5RgUnorm,
+        84 => Bc5RgSnorm,
+        87 => Bgra8Unorm,
+        91 => Bgra8UnormSrgb,
+        95 => Bc6hRgbUfloat,
+        96 => Bc6hRgbSfloat,
+        98 => Bc7RgbaUnorm,
+        99 => Bc7RgbaUnormSrgb,
+        _ => bail!("Unsupported DXGI format {}", dxgi),
+    })
+}
+
+fn vk_format(vk: u32) -> Result<wgpu::TextureFormat> {
+    use wgpu::TextureFormat::*;
+    Ok(match vk {
+        37 => Rgba8Unorm,
+        38 => Rgba8Snorm,
+        43 => Rgba8UnormSrgb,
+        44 => Bgra8Unorm,
+        50 => Bgra8UnormSrgb,
+        97 => Rgba16Float,
+        // BC1 without alpha is decoded by from_ktx2, as wgpu would
+        // make some of its texels transparent
+        VK_FORMAT_BC1_RGB_UNORM | 133 => Bc1RgbaUnorm,
+        VK_FORMAT_BC1_RGB_SRGB | 134 => Bc1RgbaUnormSrgb,
+        135 => Bc2RgbaUnorm,
+        136 => Bc2RgbaUnormSrgb,
+        137 => Bc3RgbaUnorm,
+        138 => Bc3RgbaUnormSrgb,
+        139 => Bc4RUnorm,
+        140 => Bc4RSnorm,
+        141 => Bc5RgUnorm,
+        142 => Bc5RgSnorm,
+        143 => Bc6hRgbUfloat,
+        144 => Bc6hRgbSfloat,
+        145 => Bc7RgbaUnorm,
+        146 => Bc7RgbaUnormSrgb,
+        _ => bail!("Unsupported Vulkan format {}", vk),
+    })
+}
+
+impl TextureData {
+    /// Whether `path` has the extension of a file [TextureData::load]
+    /// reads.
+    pub fn is_container<P: AsRef<Path>>(path: P) -> bool {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => ext.eq_ignore_ascii_case("dds") || ext.eq_ignore_ascii_case("ktx2"),
+            None => false,
+        }
+    }
+
+    /// Reads a DDS or KTX2 file. `srgb` is only used for old DDS files,
+    /// which don't say whether their colors are sRGB.
+    pub fn load<P: AsRef<Path>>(path: P, srgb: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        Self::from_bytes(&bytes, srgb).with_context(|| format!("Unable to load {}", path.display()))
+    }
+
+    /// Tells DDS and KTX2 files apart by their first bytes.
+    pub fn from_bytes(bytes: &[u8], srgb: bool) -> Result<Self> {
+        if bytes.starts_with(DDS_MAGIC) {
+            Self::from_dds(bytes, srgb)
+        } else if bytes.starts_with(KTX2_IDENTIFIER) {
+            Self::from_ktx2(bytes)
+        } else {
+            bail!("Not a DDS or KTX2 file")
+        }
+    }
+
+    pub fn from_dds(bytes: &[u8], srgb: bool) -> Result<Self> {
+        use wgpu::TextureFormat::*;
+
+        if !bytes.starts_with(DDS_MAGIC) {
+            bail!("Not a DDS file");
+        }
+        const DDPF_FOURCC: u32 = 0x4;
+        const DDPF_RGB: u32 = 0x40;
+        const DDSCAPS2_CUBEMAP: u32 = 0x200;
+        const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+        const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+        let header = 4;
+        let height = read_u32(bytes, header + 8)?;
+        let width = read_u32(bytes, header + 12)?;
+        check_size(width, height)?;
+        let mip_levels = check_mip_levels(read_u32(bytes, header + 24)?.max(1))?;
+        let pf_flags = read_u32(bytes, header + 76)?;
+        let four_cc = bytes
+            .get(header + 80..header + 84)
+            .context("Unexpected end of texture file")?;
+        let caps2 = read_u32(bytes, header + 108)?;
+        if caps2 & DDSCAPS2_VOLUME != 0 {
+            bail!("Volume textures aren't supported");
+        }
+        let mut is_cube = caps2 & DDSCAPS2_CUBEMAP != 0;
+        let mut layers = if is_cube { 6 } else { 1 };
+        let mut data_offset = header + 124;
+
+        let format = if pf_flags & DDPF_FOURCC != 0 {
+            match four_cc {
+                b"DXT1" => srgb_if(srgb, Bc1RgbaUnorm, Bc1RgbaUnormSrgb),
+                b"DXT2" | b"DXT3" => srgb_if(srgb, Bc2RgbaUnorm, Bc2RgbaUnormSrgb),
+                b"DXT4" | b"DXT5" => srgb_if(srgb, Bc3RgbaUnorm, Bc3RgbaUnormSrgb),
+                b"ATI1" | b"BC4U" => Bc4RUnorm,
+                b"BC4S" => Bc4RSnorm,
+                b"ATI2" | b"BC5U" => Bc5RgUnorm,
+                b"BC5S" => Bc5RgSnorm,
+                b"DX10" => {
+                    let dx10 = data_offset;
+                    data_offset += 20;
+                    let misc_flag = read_u32(bytes, dx10 + 8)?;
+                    let array_size = read_u32(bytes, dx10 + 12)?.max(1);
+                    is_cube = misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE != 0;
+                    // Cube arrays count cubes rather than faces
+                    layers = if is_cube {
+                        array_size
+                            .checked_mul(6)
+                            .context("DDS file has too many cubes")?
+                    } else {
+                        array_size
+                    };
+                    dxgi_format(read_u32(bytes, dx10)?)?
+                }
+                _ => bail!(
+                    "Unsupported DDS format {}",
+                    String::from_utf8_lossy(four_cc)
+                ),
+            }
+        } else if pf_flags & DDPF_RGB != 0 && read_u32(bytes, header + 84)? == 32 {
+            match read_u32(bytes, header + 88)? {
+                0xFF => srgb_if(srgb, Rgba8Unorm, Rgba8UnormSrgb),
+                0xFF_0000 => srgb_if(srgb, Bgra8Unorm, Bgra8UnormSrgb),
+                mask => bail!("Unsupported DDS red mask {:#x}", mask),
+            }
+        } else {
+            bail!("Unsupported DDS pixel format");
+        };
+
+        let mut data = Self {
+            format,
+            width,
+            height,
+            layers,
+            is_cube,
+            levels: vec![Vec::new(); mip_levels as usize],
+        };
+        // DDS stores each layer's mip chain in turn, so the levels
+        // have to be gathered from every layer
+        let mut offset = data_offset;
+        for _ in 0..layers {
+            for level in 0..data.levels.len() {
+                let size = data.layer_bytes(level)?;
+                let end = offset
+                    .checked_add(size)
+                    .context("DDS file is missing image data")?;
+                let image = bytes
+                    .get(offset..end)
+                    .context("DDS file is missing image data")?;
+                data.levels[level].extend_from_slice(image);
+                offset = end;
+            }
+        }
+        Ok(data)
+    }
+
+    pub fn from_ktx2(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(KTX2_IDENTIFIER) {
+            bail!("Not a KTX2 file");
+        }
+        let vk = read_u32(bytes, 12)?;
+        let format = vk_format(vk)?;
+        let width = read_u32(bytes, 20)?;
+        // 1D textures have a height of 0
+        let height = read_u32(bytes, 24)?.max(1);
+        check_size(width, height)?;
+        if read_u32(bytes, 28)? > 1 {
+            bail!("Volume textures aren't supported");
+        }
+        let layers = read_u32(bytes, 32)?.max(1);
+        let faces = read_u32(bytes, 36)?.max(1);
+        let mip_levels = check_mip_levels(read_u32(bytes, 40)?.max(1))?;
+        if read_u32(bytes, 44)? != 0 {
+            bail!("Supercompressed KTX2 files aren't supported");
+        }
+
+        let mut data = Self {
+            format,
+            width,
+            height,
+            layers: layers
+                .checked_mul(faces)
+                .context("KTX2 file has too many layers")?,
+            is_cube: faces == 6,
+            levels: Vec::with_capacity(mip_levels as usize),
+        };
+        // The level index comes right after the header
+        for level in 0..mip_levels as usize {
+            let entry = 80 + level * 24;
+            let offset = read_u64(bytes, entry)?;
+            let size = data.level_bytes(level)?;
+            let image = usize::try_from(offset)
+                .ok()
+                .and_then(|offset| Some(offset..offset.checked_add(size)?))
+                .and_then(|range| bytes.get(range))
+                .context("KTX2 file is missing image data")?;
+            data.levels.push(image.to_vec());
+        }
+
+        if vk == VK_FORMAT_BC1_RGB_UNORM || vk == VK_FORMAT_BC1_RGB_SRGB {
+            let (_, decoded) = bc_format(data.format).unwrap();
+            data = data.decode(BcFormat::Bc1Rgb, decoded)?;
+        }
+        Ok(data)
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        match block_layout(self.format) {
+            Some((block, _)) => block > 1,
+            None => false,
+        }
+    }
+
+    /// The width and height of a mip level, in texels.
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// The bytes in one row of blocks, and the number of rows, of a
+    /// single layer of a mip level. Loading checks that every level's
+    /// rows fit in a u32.
+    pub(crate) fn level_layout(&self, level: usize) -> (u32, u32) {
+        self.checked_level_layout(level).unwrap()
+    }
+
+    fn checked_level_layout(&self, level: usize) -> Result<(u32, u32)> {
+        let (block, block_bytes) = block_layout(self.format).unwrap();
+        let (width, height) = self.level_size(level);
+        // The sizes are at least 1, so this can't overflow
+        let blocks_x = (width - 1) / block + 1;
+        let blocks_y = (height - 1) / block + 1;
+        let bytes_per_row = blocks_x
+            .checked_mul(block_bytes as u32)
+            .with_context(|| format!("Rows of mip level {} are too large", level))?;
+        Ok((bytes_per_row, blocks_y))
+    }
+
+    fn layer_bytes(&self, level: usize) -> Result<usize> {
+        let (bytes_per_row, rows) = self.checked_level_layout(level)?;
+        (bytes_per_row as usize)
+            .checked_mul(rows as usize)
+            .with_context(|| format!("Mip level {} is too large", level))
+    }
+
+    fn level_bytes(&self, level: usize) -> Result<usize> {
+        self.layer_bytes(level)?
+            .checked_mul(self.layers as usize)
+            .with_context(|| format!("Mip level {} is too large", level))
+    }
+
+    /// How many levels, starting from the largest, are made of whole
+    /// blocks. wgpu only copies whole blocks, so smaller levels of
+    /// compressed textures can't be uploaded.
+    pub fn whole_block_levels(&self) -> u32 {
+        let (block, _) = block_layout(self.format).unwrap();
+        (0..self.levels.len())
+            .map(|level| self.level_size(level))
+            .take_while(|(width, height)| width % block == 0 && height % block == 0)
+            .count() as u32
+    }
+
+    /// Decodes block compressed textures to 8 bit RGBA, or to half float
+    /// RGBA for BC6H. Textures that aren't compressed are returned as
+    /// they are. Fails if a level is missing image data.
+    pub fn decompress(&self) -> Result<Self> {
+        match bc_format(self.format) {
+            Some((bc, format)) => self.decode(bc, format),
+            None => Ok(self.clone()),
+        }
+    }
+
+    fn decode(&self, bc: BcFormat, format: wgpu::TextureFormat) -> Result<Self> {
+        let mut levels = Vec::with_capacity(self.levels.len());
+        for (level, data) in self.levels.iter().enumerate() {
+            let (width, height) = self.level_size(level);
+            let layer_bytes = self.layer_bytes(level)?;
+            if data.len() < self.level_bytes(level)? {
+                bail!("Mip level {} is missing image data", level);
+            }
+            levels.push(
+                data.chunks(layer_bytes)
+                    .take(self.layers as usize)
+                    .flat_map(|layer| bcn::decompress(bc, width, height, layer))
+                    .collect(),
+            );
+        }
+        Ok(Self {
+            format,
+            levels,
+            ..*self
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dds_header(width: u32, height: u32, mip_levels: u32, four_cc: &[u8], caps2: u32) -> Vec<u8> {
+        let mut header = vec![0u32; 31];
+        header[0] = 124;
+        header[2] = height;
+        header[3] = width;
+        header[6] = mip_levels;
+        header[18] = 32;
+        header[19] = 0x4;
+        header[27] = caps2;
+        let mut bytes = DDS_MAGIC.to_vec();
+        for (i, word) in header.iter().enumerate() {
+            if i == 20 {
+                bytes.extend_from_slice(four_cc);
+            } else {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn ktx2_header(
+        vk: u32,
+        width: u32,
+        height: u32,
+        layers: u32,
+        faces: u32,
+        levels: u32,
+    ) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for word in &[
+            vk, 1, width, height, 0, layers, faces, levels, 0, 0, 0, 0, 0,
+        ] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0; 16]);
+        bytes
+    }
+
+    fn ktx2_level(bytes: &mut Vec<u8>, offset: u64, length: u64) {
+        for value in &[offset, length, length] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn dds_levels_are_gathered_from_every_layer() {
+        // An 8x8 BC1 cube with two levels, each face filled with its
+        // index
+        let mut bytes = dds_header(8, 8, 2, b"DXT1", 0x200);
+        for face in 0..6u8 {
+            bytes.extend(std::iter::repeat(face).take(32));
+            bytes.extend(std::iter::repeat(face).take(8));
+        }
+        let data = TextureData::from_bytes(&bytes, true).unwrap();
+        assert_eq!(data.format, wgpu::TextureFormat::Bc1RgbaUnormSrgb);
+        assert_eq!((data.width, data.height, data.layers), (8, 8, 6));
+        assert!(data.is_cube);
+        assert_eq!(data.levels.len(), 2);
+        assert_eq!(data.levels[0].len(), 32 * 6);
+        assert_eq!(data.levels[1].len(), 8 * 6);
+        assert_eq!(&data.levels[1][8..16], &[1; 8]);
+        assert_eq!(data.whole_block_levels(), 2);
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(TextureData::from_bytes(&bytes, true).is_err());
+    }
+
+    #[test]
+    fn dds_dx10_header_sets_the_format() {
+        let mut bytes = dds_header(4, 4, 1, b"DX10", 0);
+        for word in &[98u32, 3, 0, 2, 0] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0; 32]);
+        let data = TextureData::from_dds(&bytes, true).unwrap();
+        assert_eq!(data.format, wgpu::TextureFormat::Bc7RgbaUnorm);
+        assert_eq!(data.layers, 2);
+        assert!(!data.is_cube);
+    }
+
+    #[test]
+    fn ktx2_reads_the_level_index() {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        // BC4, 8x4, three levels
+        for word in &[139u32, 1, 8, 4, 0, 0, 1, 3, 0, 0, 0, 0, 0] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0; 16]);
+        // Smallest level first, as KTX2 files store them
+        let first = 80 + 3 * 24;
+        let levels = [(first + 16, 16), (first + 8, 8), (first, 8)];
+        for &(offset, length) in &levels {
+            for value in &[offset as u64, length, length] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&[3; 8]);
+        bytes.extend_from_slice(&[2; 8]);
+        bytes.extend_from_slice(&[1; 16]);
+
+        let data = TextureData::from_bytes(&bytes, false).unwrap();
+        assert_eq!(data.format, wgpu::TextureFormat::Bc4RUnorm);
+        assert_eq!(data.levels, vec![vec![1; 16], vec![2; 8], vec![3; 8]]);
+        assert_eq!(data.level_size(2), (2, 1));
+        // The 4x2 level is already smaller than a block
+        assert_eq!(data.whole_block_levels(), 1);
+    }
+
+    #[test]
+    fn decompressing_keeps_layers_and_levels() {
+        let red = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+        let blue = [0x1F, 0x00, 0x1F, 0x00, 0, 0, 0, 0];
+        let data = TextureData {
+            format: wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+            width: 4,
+            height: 4,
+            layers: 2,
+            is_cube: false,
+            levels: vec![[red, blue].concat(), [red, blue].concat()],
+        };
+        let decompressed = data.decompress().unwrap();
+        assert_eq!(decompressed.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(decompressed.levels[0].len(), 4 * 4 * 4 * 2);
+        assert_eq!(decompressed.levels[1].len(), 2 * 2 * 4 * 2);
+        assert_eq!(&decompressed.levels[1][..4], &[255, 0, 0, 255]);
+        assert_eq!(&decompressed.levels[1][16..20], &[0, 0, 255, 255]);
+        assert_eq!(decompressed.whole_block_levels(), 2);
+        assert!(!decompressed.is_compressed());
+    }
+
+    #[test]
+    fn malformed_headers_are_errors() {
+        let bytes = dds_header(4, 4, u32::MAX, b"DXT1", 0);
+        let message = TextureData::from_dds(&bytes, false)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("mip levels"));
+        let bytes = ktx2_header(139, 4, 4, 0, 1, u32::MAX);
+        assert!(TextureData::from_ktx2(&bytes).is_err());
+
+        // Rows of blocks too long to fit in a u32
+        let mut bytes = dds_header(u32::MAX, 4, 1, b"DXT1", 0);
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(TextureData::from_dds(&bytes, false).is_err());
+
+        // More cubes or layers than a u32 can count
+        let mut bytes = dds_header(4, 4, 1, b"DX10", 0);
+        for word in &[71u32, 3, 0x4, u32::MAX, 0] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        assert!(TextureData::from_dds(&bytes, false).is_err());
+        let bytes = ktx2_header(139, 4, 4, u32::MAX, 6, 1);
+        assert!(TextureData::from_ktx2(&bytes).is_err());
+
+        // A level that would end past the largest address
+        let mut bytes = ktx2_header(139, 4, 4, 0, 1, 1);
+        ktx2_level(&mut bytes, u64::MAX - 4, 8);
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(TextureData::from_ktx2(&bytes).is_err());
+    }
+
+    #[test]
+    fn ktx2_bc1_without_alpha_is_opaque() {
+        let mut bytes = ktx2_header(VK_FORMAT_BC1_RGB_SRGB, 4, 4, 0, 1, 1);
+        ktx2_level(&mut bytes, 80 + 24, 8);
+        // Three colors, with every texel using the index that BC1 with
+        // alpha makes transparent
+        bytes.extend_from_slice(&[0x1F, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let data = TextureData::from_ktx2(&bytes).unwrap();
+        assert_eq!(data.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(data.levels, vec![[0, 0, 0, 255].repeat(16)]);
+    }
+
+    #[test]
+    fn empty_textures_are_errors() {
+        let mut bytes = dds_header(0, 4, 1, b"DXT1", 0);
+        bytes.extend_from_slice(&[0; 8]);
+        let message = TextureData::from_dds(&bytes, false)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("no texels"));
+        let bytes = dds_header(4, 0, 1, b"DXT1", 0);
+        assert!(TextureData::from_dds(&bytes, false).is_err());
+
+        let mut bytes = ktx2_header(139, 0, 4, 0, 1, 1);
+        ktx2_level(&mut bytes, 80 + 24, 8);
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(TextureData::from_ktx2(&bytes).is_err());
+
+        // But a height of 0 is a 1D texture
+        let mut bytes = ktx2_header(139, 4, 0, 0, 1, 1);
+        ktx2_level(&mut bytes, 80 + 24, 8);
+        bytes.extend_from_slice(&[0; 8]);
+        let data = TextureData::from_ktx2(&bytes).unwrap();
+        assert_eq!((data.width, data.height), (4, 1));
+    }
+
+    #[test]
+    fn every_bc_format_is_decompressed() {
+        use wgpu::TextureFormat::*;
+        let formats = [
+            (Bc4RUnorm, Rgba8Unorm, 8),
+            (Bc4RSnorm, Rgba8Snorm, 8),
+            (Bc5RgUnorm, Rgba8Unorm, 16),
+            (Bc5RgSnorm, Rgba8Snorm, 16),
+            (Bc6hRgbUfloat, Rgba16Float, 16),
+            (Bc6hRgbSfloat, Rgba16Float, 16),
+            (Bc7RgbaUnorm, Rgba8Unorm, 16),
+            (Bc7RgbaUnormSrgb, Rgba8UnormSrgb, 16),
+        ];
+        for &(format, decoded, block_bytes) in &formats {
+            let data = TextureData {
+                format,
+                width: 4,
+                height: 4,
+                layers: 1,
+                is_cube: false,
+                levels: vec![vec![0; block_bytes]],
+            };
+            assert!(data.is_compressed());
+            let decompressed = data.decompress().unwrap();
+            assert_eq!(decompressed.format, decoded);
+            let (bytes_per_row, rows) = decompressed.level_layout(0);
+            assert_eq!(
+                decompressed.levels[0].len(),
+                (bytes_per_row * rows) as usize
+            );
+        }
+
+        // Missing data is an error rather than a panic
+        let data = TextureData {
+            format: Bc7RgbaUnorm,
+            width: 4,
+            height: 4,
+            layers: 1,
+            is_cube: false,
+            levels: vec![vec![0; 8]],
+        };
+        assert!(data.decompress().is_err());
+    }
+}
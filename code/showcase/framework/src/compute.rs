@@ -0,0 +1,96 @@
+use anyhow::*;
+use std::iter;
+
+use crate::buffer::{Buffer, ToRaw};
+
+/// How many workgroups of `workgroup_size` invocations are needed to
+/// cover `invocations`, ie. to process every element of a buffer.
+/// Panics if `workgroup_size` is 0.
+pub fn workgroup_count(invocations: u32, workgroup_size: u32) -> u32 {
+    assert!(
+        workgroup_size > 0,
+        "Workgroups need at least one invocation"
+    );
+    // Adding workgroup_size - 1 first would overflow near u32::MAX
+    invocations / workgroup_size + (invocations % workgroup_size != 0) as u32
+}
+
+/// Runs `pipeline` once and reads `output` back to the CPU using
+/// `staging`, which should be created with [Buffer::staging] so that
+/// it's as large as `output`.
+///
+/// `bind_groups` are bound in order, so `bind_groups[0]` is set 0.
+/// This waits for the GPU to finish, so it's meant for one off jobs
+/// rather than work that runs every frame.
+pub async fn dispatch<U, R>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pipeline: &wgpu::ComputePipeline,
+    bind_groups: &[&wgpu::BindGroup],
+    workgroups: (u32, u32, u32),
+    output: &Buffer<U, R>,
+    staging: &Buffer<U, R>,
+) -> Result<Vec<R>>
+where
+    U: ToRaw<Output = R>,
+    R: Copy + bytemuck::Pod + bytemuck::Zeroable,
+{
+    if !output.usage.contains(wgpu::BufferUsage::COPY_SRC) {
+        bail!("The output buffer needs COPY_SRC. Use Buffer::storage to create it.");
+    }
+    let size = output.raw_buffer.buffer_size();
+    if staging.raw_buffer.buffer_size() < size {
+        bail!(
+            "The staging buffer is {} bytes, but the output buffer is {}. Use \
+             Buffer::staging to create one that fits.",
+            staging.raw_buffer.buffer_size(),
+            size
+        );
+    }
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("dispatch"),
+    });
+    {
+        let mut pass = encoder.begin_compute_pass();
+        pass.set_pipeline(pipeline);
+        for (i, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_bind_group(i as u32, bind_group, &[]);
+        }
+        let (x, y, z) = workgroups;
+        pass.dispatch(x, y, z);
+    }
+    encoder.copy_buffer_to_buffer(
+        &output.raw_buffer.buffer,
+        0,
+        &staging.raw_buffer.buffer,
+        0,
+        size,
+    );
+    queue.submit(iter::once(encoder.finish()));
+
+    staging.read_raw(device).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn workgroup_count_rounds_up() {
+        assert_eq!(workgroup_count(0, 64), 0);
+        assert_eq!(workgroup_count(64, 64), 1);
+        assert_eq!(workgroup_count(65, 64), 2);
+        assert_eq!(workgroup_count(1, 1), 1);
+        // Rounding up can't overflow
+        assert_eq!(workgroup_count(u32::MAX, 64), u32::MAX / 64 + 1);
+        assert_eq!(workgroup_count(u32::MAX, u32::MAX), 1);
+        assert_eq!(workgroup_count(u32::MAX - 1, u32::MAX), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one invocation")]
+    fn workgroups_cant_be_empty() {
+        workgroup_count(64, 0);
+    }
+}
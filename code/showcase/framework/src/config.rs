@@ -0,0 +1,557 @@
+use anyhow::*;
+use std::ffi::OsString;
+
+/// Environment variables named this followed by an option's name in
+/// capitals, such as `FRAMEWORK_PRESENT_MODE`, set that option for
+/// [RunConfig::from_env]. Ones that aren't options, or have values the
+/// option doesn't accept, are logged and skipped.
+pub const CONFIG_ENV_PREFIX: &str = "FRAMEWORK_";
+
+/// How the window is shown.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    /// A window without decorations covering the monitor.
+    Borderless,
+    /// Takes over the monitor, switching to the video mode closest to
+    /// the requested size.
+    Fullscreen,
+}
+
+/// Everything [run_with_config](crate::run_with_config) needs to know
+/// before the demo starts: the window, and how to pick the adapter and
+/// device.
+///
+/// Besides being set in code, every option can come from an environment
+/// variable or a command line argument, through [RunConfig::set]:
+///
+/// | Option | Values |
+/// |---|---|
+/// | `title` | Any text |
+/// | `size` | `1280x720` |
+/// | `window` | `windowed`, `borderless` or `fullscreen` |
+/// | `backend` | `vulkan`, `metal`, `dx12`, `dx11`, `gl`, `primary`, `secondary` or `all`, separated by commas |
+/// | `power` | `default`, `low` or `high` |
+/// | `adapter` | Part of an adapter's name, in any case |
+/// | `present-mode` | `fifo`, `mailbox` or `immediate` |
+/// | `msaa` | `1`, `2`, `4`, `8` or `16`, see [Display::color_attachment](crate::Display::color_attachment) |
+/// | `features` | `depth-clamping`, `texture-compression-bc`, `push-constants`, ... separated by commas |
+/// | `max-bind-groups` | A number |
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    pub title: String,
+    /// The window's inner size. The platform picks one when this is
+    /// `None`.
+    pub size: Option<(u32, u32)>,
+    pub window_mode: WindowMode,
+    pub backends: wgpu::BackendBit,
+    pub power_preference: wgpu::PowerPreference,
+    /// Picks the first adapter whose name contains this, ignoring case,
+    /// instead of letting wgpu choose.
+    pub adapter_name: Option<String>,
+    pub present_mode: wgpu::PresentMode,
+    /// The sample count of the [Display](crate::Display)'s color target.
+    /// See [Display::color_attachment](crate::Display::color_attachment).
+    pub sample_count: u32,
+    /// Features the device must have. Adapters without them are an
+    /// error.
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+}
+
+impl Default for RunConfig {
+    /// A window titled after the executable, on whatever adapter wgpu
+    /// prefers.
+    fn default() -> Self {
+        let title = std::env::current_exe()
+            .ok()
+            .and_then(|path| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| String::from(env!("CARGO_PKG_NAME")));
+        Self {
+            title,
+            size: None,
+            window_mode: WindowMode::Windowed,
+            backends: wgpu::BackendBit::PRIMARY,
+            power_preference: wgpu::PowerPreference::Default,
+            adapter_name: None,
+            present_mode: wgpu::PresentMode::Fifo,
+            sample_count: 1,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::default(),
+        }
+    }
+}
+
+/// Every option [RunConfig::set] accepts.
+const OPTIONS: &[&str] = &[
+    "title",
+    "size",
+    "window",
+    "backend",
+    "power",
+    "adapter",
+    "present-mode",
+    "msaa",
+    "features",
+    "max-bind-groups",
+];
+
+const FEATURE_NAMES: &[(&str, wgpu::Features)] = &[
+    ("depth-clamping", wgpu::Features::DEPTH_CLAMPING),
+    (
+        "texture-compression-bc",
+        wgpu::Features::TEXTURE_COMPRESSION_BC,
+    ),
+    (
+        "mappable-primary-buffers",
+        wgpu::Features::MAPPABLE_PRIMARY_BUFFERS,
+    ),
+    (
+        "sampled-texture-binding-array",
+        wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY,
+    ),
+    (
+        "sampled-texture-array-dynamic-indexing",
+        wgpu::Features::SAMPLED_TEXTURE_ARRAY_DYNAMIC_INDEXING,
+    ),
+    (
+        "sampled-texture-array-non-uniform-indexing",
+        wgpu::Features::SAMPLED_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+    ),
+    (
+        "unsized-binding-array",
+        wgpu::Features::UNSIZED_BINDING_ARRAY,
+    ),
+    ("multi-draw-indirect", wgpu::Features::MULTI_DRAW_INDIRECT),
+    (
+        "multi-draw-indirect-count",
+        wgpu::Features::MULTI_DRAW_INDIRECT_COUNT,
+    ),
+    ("push-constants", wgpu::Features::PUSH_CONSTANTS),
+];
+
+/// The names [RunConfig::set] accepts for `features`.
+pub fn feature_names(features: wgpu::Features) -> Vec<&'static str> {
+    FEATURE_NAMES
+        .iter()
+        .filter(|(_, feature)| features.contains(*feature))
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+fn parse_list<T, F: Fn(&str) -> Option<T>>(value: &str, what: &str, parse: F) -> Result<Vec<T>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| parse(item).with_context(|| format!("Unknown {} {:?}", what, item)))
+        .collect()
+}
+
+impl RunConfig {
+    /// The defaults, overridden by the environment, overridden by the
+    /// command line. This is what [run](crate::run) uses.
+    ///
+    /// Arguments that aren't options from [RunConfig], such as the ones
+    /// cargo passes to the test harness, are skipped so demos can take
+    /// their own. Known options with bad values are still errors.
+    pub fn load() -> Result<Self> {
+        let mut config = Self::from_env();
+        config.apply_args(std::env::args_os().skip(1), false)?;
+        Ok(config)
+    }
+
+    /// The defaults, overridden by any `FRAMEWORK_*` environment
+    /// variables.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        config.apply_env(std::env::vars_os());
+        config
+    }
+
+    /// The defaults, overridden by `args` in the form `--option value`
+    /// or `--option=value`. `args` shouldn't include the program name.
+    /// Unlike [RunConfig::load], anything that isn't one of the options
+    /// is an error.
+    pub fn from_args<I, S>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let mut config = Self::default();
+        config.apply_args(args, true)?;
+        Ok(config)
+    }
+
+    fn apply_env<I: IntoIterator<Item = (OsString, OsString)>>(&mut self, vars: I) {
+        for (name, value) in vars {
+            // Variables that aren't unicode can't be ours
+            let (name, value) = match (name.into_string(), value.into_string()) {
+                (Ok(name), Ok(value)) => (name, value),
+                _ => continue,
+            };
+            if let Some(option) = name.strip_prefix(CONFIG_ENV_PREFIX) {
+                let option = option.to_lowercase().replace('_', "-");
+                if let Err(e) = self.set(&option, &value) {
+                    log::warn!("Ignoring environment variable {}: {:#}", name, e);
+                }
+            }
+        }
+    }
+
+    /// With `strict` off, arguments that aren't known options are
+    /// skipped. The value of an unknown `--option value` is skipped
+    /// too, as it doesn't start with `--`.
+    fn apply_args<I, S>(&mut self, args: I, strict: bool) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let mut args = args.into_iter().map(Into::<OsString>::into);
+        while let Some(arg) = args.next() {
+            let arg = match arg.into_string() {
+                Ok(arg) => arg,
+                Err(arg) if strict => bail!("Argument {:?} isn't valid unicode", arg),
+                Err(_) => continue,
+            };
+            let option = match arg.strip_prefix("--") {
+                Some(option) => option,
+                None if strict => bail!("Expected an option like --size, got {:?}", arg),
+                None => continue,
+            };
+            let name = option.split('=').next().unwrap_or(option);
+            if !strict && !OPTIONS.contains(&name) {
+                continue;
+            }
+            let (option, value) = match option.find('=') {
+                Some(i) => (&option[..i], option[i + 1..].to_owned()),
+                None => {
+                    let value = args
+                        .next()
+                        .with_context(|| format!("--{} needs a value", option))?;
+                    let value = value
+                        .into_string()
+                        .map_err(|value| anyhow!("Argument {:?} isn't valid unicode", value))?;
+                    (option, value)
+                }
+            };
+            self.set(option, &value)
+                .with_context(|| format!("Invalid argument --{}", option))?;
+        }
+        Ok(())
+    }
+
+    /// Sets the option called `option` from text, as it would be
+    /// written on the command line. See [RunConfig] for the options.
+    pub fn set(&mut self, option: &str, value: &str) -> Result<()> {
+        let lower = value.trim().to_lowercase();
+        match option {
+            "title" => self.title = value.to_owned(),
+            "size" => {
+                let (width, height) = parse_size(&lower)
+                    .with_context(|| format!("Expected a size like 1280x720, got {:?}", value))?;
+                self.size = Some((width, height));
+            }
+            "window" => {
+                self.window_mode = match lower.as_str() {
+                    "windowed" => WindowMode::Windowed,
+                    "borderless" => WindowMode::Borderless,
+                    "fullscreen" => WindowMode::Fullscreen,
+                    _ => bail!("Unknown window mode {:?}", value),
+                }
+            }
+            "backend" => {
+                self.backends = parse_list(&lower, "backend", |name| {
+                    Some(match name {
+                        "vulkan" => wgpu::BackendBit::VULKAN,
+                        "metal" => wgpu::BackendBit::METAL,
+                        "dx12" => wgpu::BackendBit::DX12,
+                        "dx11" => wgpu::BackendBit::DX11,
+                        "gl" => wgpu::BackendBit::GL,
+                        "primary" => wgpu::BackendBit::PRIMARY,
+                        "secondary" => wgpu::BackendBit::SECONDARY,
+                        "all" => wgpu::BackendBit::all(),
+                        _ => return None,
+                    })
+                })?
+                .into_iter()
+                .fold(wgpu::BackendBit::empty(), |all, backend| all | backend);
+                if self.backends.is_empty() {
+                    bail!("No backends given");
+                }
+            }
+            "power" => {
+                self.power_preference = match lower.as_str() {
+                    "default" => wgpu::PowerPreference::Default,
+                    "low" => wgpu::PowerPreference::LowPower,
+                    "high" => wgpu::PowerPreference::HighPerformance,
+                    _ => bail!("Unknown power preference {:?}", value),
+                }
+            }
+            "adapter" => self.adapter_name = Some(value.to_owned()),
+            "present-mode" => {
+                self.present_mode = match lower.as_str() {
+                    "fifo" => wgpu::PresentMode::Fifo,
+                    "mailbox" => wgpu::PresentMode::Mailbox,
+                    "immediate" => wgpu::PresentMode::Immediate,
+                    _ => bail!("Unknown present mode {:?}", value),
+                }
+            }
+            "msaa" => {
+                let count = lower.parse()?;
+                if ![1, 2, 4, 8, 16].contains(&count) {
+                    bail!("MSAA sample count must be 1, 2, 4, 8 or 16, got {}", count);
+                }
+                self.sample_count = count;
+            }
+            "features" => {
+                self.features = parse_list(&lower, "feature", |name| {
+                    FEATURE_NAMES
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, feature)| *feature)
+                })?
+                .into_iter()
+                .fold(wgpu::Features::empty(), |all, feature| all | feature);
+            }
+            "max-bind-groups" => self.limits.max_bind_groups = lower.parse()?,
+            _ => bail!("Unknown option {:?}", option),
+        }
+        Ok(())
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_owned();
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    pub fn window_mode(mut self, window_mode: WindowMode) -> Self {
+        self.window_mode = window_mode;
+        self
+    }
+
+    pub fn backends(mut self, backends: wgpu::BackendBit) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    pub fn adapter_name(mut self, name: &str) -> Self {
+        self.adapter_name = Some(name.to_owned());
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn features(mut self, features: wgpu::Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn limits(mut self, limits: wgpu::Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let mut parts = value.splitn(2, 'x');
+    let width = parts.next()?.trim().parse().ok()?;
+    let height = parts.next()?.trim().parse().ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((width, height))
+}
+
+/// The limits in `wanted` that are higher than `available` allows, as
+/// `name: wanted > available`.
+pub(crate) fn exceeded_limits(wanted: &wgpu::Limits, available: &wgpu::Limits) -> Vec<String> {
+    let limits = [
+        (
+            "max_bind_groups",
+            wanted.max_bind_groups,
+            available.max_bind_groups,
+        ),
+        (
+            "max_dynamic_uniform_buffers_per_pipeline_layout",
+            wanted.max_dynamic_uniform_buffers_per_pipeline_layout,
+            available.max_dynamic_uniform_buffers_per_pipeline_layout,
+        ),
+        (
+            "max_dynamic_storage_buffers_per_pipeline_layout",
+            wanted.max_dynamic_storage_buffers_per_pipeline_layout,
+            available.max_dynamic_storage_buffers_per_pipeline_layout,
+        ),
+        (
+            "max_sampled_textures_per_shader_stage",
+            wanted.max_sampled_textures_per_shader_stage,
+            available.max_sampled_textures_per_shader_stage,
+        ),
+        (
+            "max_samplers_per_shader_stage",
+            wanted.max_samplers_per_shader_stage,
+            available.max_samplers_per_shader_stage,
+        ),
+        (
+            "max_storage_buffers_per_shader_stage",
+            wanted.max_storage_buffers_per_shader_stage,
+            available.max_storage_buffers_per_shader_stage,
+        ),
+        (
+            "max_storage_textures_per_shader_stage",
+            wanted.max_storage_textures_per_shader_stage,
+            available.max_storage_textures_per_shader_stage,
+        ),
+        (
+            "max_uniform_buffers_per_shader_stage",
+            wanted.max_uniform_buffers_per_shader_stage,
+            available.max_uniform_buffers_per_shader_stage,
+        ),
+        (
+            "max_uniform_buffer_binding_size",
+            wanted.max_uniform_buffer_binding_size,
+            available.max_uniform_buffer_binding_size,
+        ),
+        (
+            "max_push_constant_size",
+            wanted.max_push_constant_size,
+            available.max_push_constant_size,
+        ),
+    ];
+    limits
+        .iter()
+        .filter(|(_, wanted, available)| wanted > available)
+        .map(|(name, wanted, available)| format!("{}: {} > {}", name, wanted, available))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn args_override_defaults() {
+        let config = RunConfig::from_args(vec![
+            "--size",
+            "800x600",
+            "--backend=vulkan,dx12",
+            "--power",
+            "high",
+            "--present-mode=Mailbox",
+            "--msaa",
+            "4",
+            "--features",
+            "push-constants,texture-compression-bc",
+            "--adapter",
+            "GeForce",
+        ])
+        .unwrap();
+        assert_eq!(config.size, Some((800, 600)));
+        assert_eq!(
+            config.backends,
+            wgpu::BackendBit::VULKAN | wgpu::BackendBit::DX12
+        );
+        assert_eq!(
+            config.power_preference,
+            wgpu::PowerPreference::HighPerformance
+        );
+        assert_eq!(config.present_mode, wgpu::PresentMode::Mailbox);
+        assert_eq!(config.sample_count, 4);
+        assert_eq!(
+            config.features,
+            wgpu::Features::PUSH_CONSTANTS | wgpu::Features::TEXTURE_COMPRESSION_BC
+        );
+        assert_eq!(config.adapter_name.as_deref(), Some("GeForce"));
+        assert_eq!(
+            feature_names(config.features),
+            vec!["texture-compression-bc", "push-constants"]
+        );
+    }
+
+    #[test]
+    fn bad_args_are_errors() {
+        for args in &[
+            vec!["--size", "800"],
+            vec!["--msaa", "3"],
+            vec!["--backend", "glide"],
+            vec!["--window"],
+            vec!["--vsync", "on"],
+            vec!["fullscreen"],
+        ] {
+            assert!(RunConfig::from_args(args.clone()).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn unknown_args_are_skipped_when_loading() {
+        let mut config = RunConfig::default();
+        config
+            .apply_args(
+                vec![
+                    "my_test",
+                    "--nocapture",
+                    "--test-threads",
+                    "1",
+                    "--size=800x600",
+                    "--format=json",
+                    "--msaa",
+                    "4",
+                ],
+                false,
+            )
+            .unwrap();
+        assert_eq!(config.size, Some((800, 600)));
+        assert_eq!(config.sample_count, 4);
+        assert!(config.apply_args(vec!["--msaa", "3"], false).is_err());
+    }
+
+    #[test]
+    fn bad_env_vars_are_skipped() {
+        let mut config = RunConfig::default();
+        config.apply_env(
+            vec![
+                ("FRAMEWORK_NOT_AN_OPTION", "1"),
+                ("FRAMEWORK_MSAA", "3"),
+                ("FRAMEWORK_PRESENT_MODE", "mailbox"),
+                ("PATH", "/bin"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (OsString::from(name), OsString::from(value))),
+        );
+        assert_eq!(config.sample_count, 1);
+        assert_eq!(config.present_mode, wgpu::PresentMode::Mailbox);
+    }
+
+    #[test]
+    fn limits_above_the_adapters_are_listed() {
+        let available = wgpu::Limits::default();
+        let mut wanted = available.clone();
+        assert!(exceeded_limits(&wanted, &available).is_empty());
+        wanted.max_bind_groups = available.max_bind_groups + 1;
+        assert_eq!(
+            exceeded_limits(&wanted, &available),
+            vec![format!(
+                "max_bind_groups: {} > {}",
+                wanted.max_bind_groups, available.max_bind_groups
+            )]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::*;
+use std::fs::File;
+use std::io::BufReader;
+use std::iter;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use crate::mipmap::{mip_level_count, MipmapGenerator};
+use crate::pipeline::RenderPipelineBuilder;
+use crate::post::HDR_FORMAT;
+use crate::sampler::{SamplerCache, SamplerConfig};
+use crate::texture::Texture;
+
+/// How many layers a cube map texture has.
+pub const CUBE_FACES: u32 = 6;
+
+/// Linear RGB, as read from Radiance `.hdr` files.
+pub type HdrImage = image::ImageBuffer<image::Rgb<f32>, Vec<f32>>;
+
+/// Reads a Radiance `.hdr` file, such as an equirectangular environment
+/// for [EquirectangularConverter].
+pub fn load_hdr<P: AsRef<Path>>(path: P) -> Result<HdrImage> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+    let decoder = image::codecs::hdr::HdrDecoder::new(BufReader::new(file))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+    HdrImage::from_raw(
+        metadata.width,
+        metadata.height,
+        pixels
+            .iter()
+            .flat_map(|pixel| pixel.0.iter().copied())
+            .collect(),
+    )
+    .context("Image data doesn't match its dimensions")
+}
+
+/// Rounds to the nearest half float. Values too big for one become the
+/// largest finite half rather than infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let sign = ((value.to_bits() >> 16) & 0x8000) as u16;
+    let value = value.abs();
+    if value.is_nan() {
+        return sign | 0x7E00;
+    }
+    if value >= 65504.0 {
+        return sign | 0x7BFF;
+    }
+    // Below the smallest normal half, count in steps of the smallest
+    // subnormal, 2^-24
+    if value < 6.103_515_6e-5 {
+        return sign | (value * 16_777_216.0).round() as u16;
+    }
+    let bits = value.to_bits();
+    let exponent = (bits >> 23) + 15 - 127;
+    let mantissa = bits & 0x7F_FFFF;
+    // Rounding up can carry into the exponent, which is still correct
+    let half = (exponent << 10 | mantissa >> 13) + ((mantissa >> 12) & 1);
+    sign | half as u16
+}
+
+const EQUIRECT_BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            multisampled: false,
+            dimension: wgpu::TextureViewDimension::D2,
+            component_type: wgpu::TextureComponentType::Float,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler { comparison: false },
+        count: None,
+    },
+];
+
+/// Turns equirectangular (latitude-longitude) HDR images into cube maps
+/// on the GPU, by rendering each face from the directions through its
+/// texels.
+pub struct EquirectangularConverter {
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl EquirectangularConverter {
+    pub fn new(device: &wgpu::Device) -> Result<Self> {
+        // Longitude wraps around, latitude stops at the poles
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("EquirectangularConverter::sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("EquirectangularConverter::layout"),
+            entries: EQUIRECT_BIND_GROUP_LAYOUT_ENTRIES,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("EquirectangularConverter::pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = RenderPipelineBuilder::new()
+            .layout(&pipeline_layout)
+            .vertex_shader(wgpu::include_spirv!("shaders/equirect_to_cube.vert.spv"))
+            .fragment_shader(wgpu::include_spirv!("shaders/equirect_to_cube.frag.spv"))
+            .primitive_topology(wgpu::PrimitiveTopology::TriangleStrip)
+            .color_solid(HDR_FORMAT)
+            .build(device)
+            .context("Unable to create the equirectangular conversion pipeline")?;
+        Ok(Self {
+            sampler,
+            layout,
+            pipeline,
+        })
+    }
+
+    /// Renders `img` onto a [HDR_FORMAT] cube map with `face_size`
+    /// texels along each edge, then fills in its mip levels with
+    /// `mipmaps`. Blurrier levels are what
+    /// [EnvironmentMap](crate::EnvironmentMap) uses for rough surfaces.
+    #[allow(clippy::too_many_arguments)]
+    pub fn convert(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &HdrImage,
+        face_size: u32,
+        mipmaps: &mut MipmapGenerator,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
+    ) -> Result<Texture<'static>> {
+        let (width, height) = img.dimensions();
+        let source = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("EquirectangularConverter::source"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        let texels = img
+            .pixels()
+            .flat_map(|pixel| {
+                let [r, g, b] = pixel.0;
+                vec![f32_to_f16(r), f32_to_f16(g), f32_to_f16(b), f32_to_f16(1.0)]
+            })
+            .collect::<Vec<u16>>();
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &source,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(&texels),
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 8 * width,
+                rows_per_image: height,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+        );
+        let source_view = source.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("EquirectangularConverter::bind_group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let desc = wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: face_size,
+                height: face_size,
+                depth: CUBE_FACES,
+            },
+            mip_level_count: mip_level_count(face_size, face_size),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("EquirectangularConverter::cube"),
+            ..desc
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("EquirectangularConverter::convert"),
+        });
+        for face in 0..CUBE_FACES {
+            let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("EquirectangularConverter::face"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                level_count: NonZeroU32::new(1),
+                base_array_layer: face,
+                array_layer_count: NonZeroU32::new(1),
+                ..Default::default()
+            });
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            // The shader renders the face matching the instance
+            pass.draw(0..4, face..face + 1);
+        }
+        mipmaps.generate(device, &mut encoder, &texture, &desc)?;
+        queue.submit(iter::once(encoder.finish()));
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = samplers.get(device, sampler);
+
+        Ok(Texture {
+            texture,
+            view,
+            sampler,
+            desc,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reflect::*;
+
+    #[test]
+    fn halves_round_and_clamp() {
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(1.0), 0x3C00);
+        assert_eq!(f32_to_f16(-2.0), 0xC000);
+        assert_eq!(f32_to_f16(0.1), 0x2E66);
+        assert_eq!(f32_to_f16(1e6), 0x7BFF);
+        // The smallest subnormal half
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 1);
+    }
+
+    #[test]
+    fn equirect_shader_matches_layout() {
+        let vs = ShaderReflection::from_bytes(include_bytes!("shaders/equirect_to_cube.vert.spv"))
+            .unwrap();
+        let fs = ShaderReflection::from_bytes(include_bytes!("shaders/equirect_to_cube.frag.spv"))
+            .unwrap();
+        let layouts = reflect_bind_group_layouts(&[&vs, &fs]).unwrap();
+        assert_eq!(layouts[0].entries, EQUIRECT_BIND_GROUP_LAYOUT_ENTRIES);
+    }
+}
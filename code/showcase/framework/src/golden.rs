@@ -0,0 +1,272 @@
+use anyhow::*;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::Demo;
+
+/// Set this environment variable to overwrite existing goldens with
+/// the images that were just rendered.
+pub const UPDATE_GOLDENS_VAR: &str = "UPDATE_GOLDENS";
+
+#[derive(Debug, Copy, Clone)]
+pub struct GoldenOptions {
+    /// Pixels whose channels are all within this distance of the
+    /// golden are considered identical.
+    pub pixel_tolerance: u8,
+    /// Largest perceptual difference (0 to 1) allowed for a single
+    /// pixel that is outside of `pixel_tolerance`.
+    pub perceptual_threshold: f32,
+    /// Fraction of pixels (0 to 1) that are allowed to be outside of
+    /// `pixel_tolerance`.
+    pub max_differing_ratio: f32,
+}
+
+impl Default for GoldenOptions {
+    fn default() -> Self {
+        Self {
+            pixel_tolerance: 2,
+            perceptual_threshold: 0.1,
+            max_differing_ratio: 0.001,
+        }
+    }
+}
+
+pub struct ImageDiff {
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    pub max_perceptual_delta: f32,
+    /// Differing pixels are red, brighter the more they differ. The
+    /// rest of the image is a faded grayscale copy of the golden.
+    pub diff_image: image::RgbaImage,
+}
+
+impl ImageDiff {
+    pub fn differing_ratio(&self) -> f32 {
+        if self.total_pixels == 0 {
+            0.0
+        } else {
+            self.differing_pixels as f32 / self.total_pixels as f32
+        }
+    }
+
+    pub fn passes(&self, options: &GoldenOptions) -> bool {
+        self.max_perceptual_delta <= options.perceptual_threshold
+            && self.differing_ratio() <= options.max_differing_ratio
+    }
+}
+
+pub fn compare_images(
+    expected: &image::RgbaImage,
+    actual: &image::RgbaImage,
+    options: &GoldenOptions,
+) -> Result<ImageDiff> {
+    if expected.dimensions() != actual.dimensions() {
+        bail!(
+            "Image sizes differ: expected {:?}, got {:?}",
+            expected.dimensions(),
+            actual.dimensions()
+        );
+    }
+
+    let (width, height) = expected.dimensions();
+    let mut diff_image = image::RgbaImage::new(width, height);
+    let mut differing_pixels = 0;
+    let mut max_perceptual_delta = 0.0f32;
+
+    for ((e, a), d) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff_image.pixels_mut())
+    {
+        let within_tolerance = (0..4)
+            .all(|i| (e[i] as i16 - a[i] as i16).abs() <= options.pixel_tolerance as i16);
+        if within_tolerance {
+            let gray = (luma(e.0) * 0.25 * 255.0 + 191.0) as u8;
+            *d = image::Rgba([gray, gray, gray, 255]);
+        } else {
+            let delta = perceptual_delta(e.0, a.0);
+            differing_pixels += 1;
+            max_perceptual_delta = max_perceptual_delta.max(delta);
+            let red = (128.0 + delta * 127.0) as u8;
+            *d = image::Rgba([red, 0, 0, 255]);
+        }
+    }
+
+    Ok(ImageDiff {
+        differing_pixels,
+        total_pixels: (width * height) as usize,
+        max_perceptual_delta,
+        diff_image,
+    })
+}
+
+/// Compares `actual` against the png at `path`.
+///
+/// When [UPDATE_GOLDENS_VAR] is set, `actual` is saved as the new
+/// golden instead. A missing golden is an error otherwise, so a test
+/// can't pass just because there was nothing to compare against. When
+/// the comparison fails the rendered image and a diff image are saved
+/// next to the golden as `*.actual.png` and `*.diff.png`.
+pub fn assert_golden<P: AsRef<Path>>(
+    path: P,
+    actual: &image::RgbaImage,
+    options: &GoldenOptions,
+) -> Result<()> {
+    let path = path.as_ref();
+    if std::env::var_os(UPDATE_GOLDENS_VAR).is_some() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        actual.save(path)?;
+        eprintln!("Updated golden {}", path.display());
+        return Ok(());
+    }
+    if !path.exists() {
+        let actual_path = sibling_path(path, "actual");
+        actual.save(&actual_path)?;
+        bail!(
+            "Missing golden {}. The rendered image was saved to {}. \
+             Set {}=1 to save it as the golden.",
+            path.display(),
+            actual_path.display(),
+            UPDATE_GOLDENS_VAR,
+        );
+    }
+
+    let expected = image::open(path)
+        .with_context(|| format!("Unable to load golden {}", path.display()))?
+        .to_rgba();
+    let diff = compare_images(&expected, actual, options)?;
+    if diff.passes(options) {
+        return Ok(());
+    }
+
+    let actual_path = sibling_path(path, "actual");
+    let diff_path = sibling_path(path, "diff");
+    actual.save(&actual_path)?;
+    diff.diff_image.save(&diff_path)?;
+    bail!(
+        "{} doesn't match: {} of {} pixels differ ({:.3}%), max perceptual delta {:.3}. \
+         See {}. Set {}=1 to accept the new image.",
+        path.display(),
+        diff.differing_pixels,
+        diff.total_pixels,
+        diff.differing_ratio() * 100.0,
+        diff.max_perceptual_delta,
+        diff_path.display(),
+        UPDATE_GOLDENS_VAR,
+    )
+}
+
+/// Renders `frames` frames of `D` without a window, and compares the
+/// last one against the golden at `path`.
+pub async fn assert_demo_golden<D: Demo, P: AsRef<Path>>(
+    path: P,
+    width: u32,
+    height: u32,
+    frames: usize,
+    options: &GoldenOptions,
+) -> Result<()> {
+    let dt = Duration::from_secs_f64(1.0 / 60.0);
+    let (mut display, mut demo) = crate::run_headless::<D>(width, height, frames, dt).await?;
+    let actual = display.capture_frame(&mut demo).await?;
+    assert_golden(path, &actual, options)
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+fn luma(c: [u8; 4]) -> f32 {
+    (0.299 * c[0] as f32 + 0.587 * c[1] as f32 + 0.114 * c[2] as f32) / 255.0
+}
+
+/// Color difference in YIQ space, which tracks how different two
+/// colors look better than comparing RGB does. Colors are blended
+/// with white based on alpha first. The result goes from 0 to 1.
+fn perceptual_delta(a: [u8; 4], b: [u8; 4]) -> f32 {
+    // The same normalization pixelmatch uses
+    const MAX_DELTA: f32 = 35215.0;
+
+    let blend = |c: [u8; 4]| {
+        let alpha = c[3] as f32 / 255.0;
+        let f = |v: u8| 255.0 + (v as f32 - 255.0) * alpha;
+        (f(c[0]), f(c[1]), f(c[2]))
+    };
+    let (r1, g1, b1) = blend(a);
+    let (r2, g2, b2) = blend(b);
+
+    let y = |r: f32, g: f32, b: f32| r * 0.298_895_31 + g * 0.586_622_47 + b * 0.114_482_23;
+    let i = |r: f32, g: f32, b: f32| r * 0.595_977_99 - g * 0.274_176_5 - b * 0.321_801_5;
+    let q = |r: f32, g: f32, b: f32| r * 0.211_470_17 - g * 0.522_617_4 + b * 0.311_147_23;
+
+    let dy = y(r1, g1, b1) - y(r2, g2, b2);
+    let di = i(r1, g1, b1) - i(r2, g2, b2);
+    let dq = q(r1, g1, b1) - q(r2, g2, b2);
+
+    ((0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / MAX_DELTA).min(1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba(color))
+    }
+
+    #[test]
+    fn identical_images_pass() {
+        let a = solid(4, 4, [10, 20, 30, 255]);
+        let diff = compare_images(&a, &a, &GoldenOptions::default()).unwrap();
+        assert_eq!(diff.differing_pixels, 0);
+        assert!(diff.passes(&GoldenOptions::default()));
+    }
+
+    #[test]
+    fn small_differences_are_tolerated() {
+        let a = solid(4, 4, [10, 20, 30, 255]);
+        let b = solid(4, 4, [12, 18, 31, 255]);
+        let diff = compare_images(&a, &b, &GoldenOptions::default()).unwrap();
+        assert_eq!(diff.differing_pixels, 0);
+    }
+
+    #[test]
+    fn black_and_white_fail() {
+        let a = solid(4, 4, [0, 0, 0, 255]);
+        let mut b = a.clone();
+        b.put_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        let diff = compare_images(&a, &b, &GoldenOptions::default()).unwrap();
+        assert_eq!(diff.differing_pixels, 1);
+        assert!(diff.max_perceptual_delta > 0.9);
+        assert!(!diff.passes(&GoldenOptions::default()));
+        assert!(diff.diff_image.get_pixel(1, 1)[0] > 200);
+        assert_eq!(diff.diff_image.get_pixel(0, 0)[1], 191);
+    }
+
+    #[test]
+    fn missing_goldens_fail() {
+        if std::env::var_os(UPDATE_GOLDENS_VAR).is_some() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("goldens-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = solid(4, 4, [0, 0, 0, 255]);
+        let result = assert_golden(dir.join("missing.png"), &image, &GoldenOptions::default());
+        assert!(!dir.join("missing.png").exists());
+        assert!(dir.join("missing.actual.png").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(result.unwrap_err().to_string().starts_with("Missing golden"));
+    }
+
+    #[test]
+    fn mismatched_sizes_are_an_error() {
+        let a = solid(4, 4, [0, 0, 0, 255]);
+        let b = solid(4, 5, [0, 0, 0, 255]);
+        assert!(compare_images(&a, &b, &GoldenOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use winit::dpi::PhysicalPosition;
+use winit::event::*;
+
+/// The input a [Demo](crate::Demo) receives, gathered from winit's
+/// window and device events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    /// A key was pressed or released. `key` is `None` for keys winit
+    /// doesn't have a [VirtualKeyCode] for.
+    Key {
+        key: Option<VirtualKeyCode>,
+        scancode: ScanCode,
+        state: ElementState,
+    },
+    /// Typed text, after the keyboard layout and any input method have
+    /// been applied.
+    Text(char),
+    ModifiersChanged(ModifiersState),
+    MouseButton {
+        button: MouseButton,
+        state: ElementState,
+    },
+    Scroll(MouseScrollDelta),
+    /// Where the cursor is in the window, in physical pixels.
+    CursorMoved(PhysicalPosition<f64>),
+    /// Raw mouse movement. Unlike [InputEvent::CursorMoved] this keeps
+    /// coming when the cursor reaches the edge of the window, which is
+    /// what a camera wants.
+    MouseMotion {
+        dx: f64,
+        dy: f64,
+    },
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode,
+                        scancode,
+                        state,
+                        ..
+                    },
+                ..
+            } => InputEvent::Key {
+                key: *virtual_keycode,
+                scancode: *scancode,
+                state: *state,
+            },
+            WindowEvent::ReceivedCharacter(c) => InputEvent::Text(*c),
+            WindowEvent::ModifiersChanged(modifiers) => InputEvent::ModifiersChanged(*modifiers),
+            WindowEvent::MouseInput { button, state, .. } => InputEvent::MouseButton {
+                button: *button,
+                state: *state,
+            },
+            WindowEvent::MouseWheel { delta, .. } => InputEvent::Scroll(*delta),
+            WindowEvent::CursorMoved { position, .. } => InputEvent::CursorMoved(*position),
+            _ => return None,
+        })
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match event {
+            DeviceEvent::MouseMotion { delta: (dx, dy) } => {
+                Some(InputEvent::MouseMotion { dx: *dx, dy: *dy })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[allow(deprecated)]
+    fn window_events_convert() {
+        let device_id = unsafe { DeviceId::dummy() };
+        let event = WindowEvent::KeyboardInput {
+            device_id,
+            input: KeyboardInput {
+                scancode: 17,
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::W),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        };
+        assert_eq!(
+            InputEvent::from_window_event(&event),
+            Some(InputEvent::Key {
+                key: Some(VirtualKeyCode::W),
+                scancode: 17,
+                state: ElementState::Pressed,
+            })
+        );
+        assert_eq!(
+            InputEvent::from_window_event(&WindowEvent::ReceivedCharacter('a')),
+            Some(InputEvent::Text('a'))
+        );
+        // Handled by the framework rather than passed on
+        assert_eq!(
+            InputEvent::from_window_event(&WindowEvent::Focused(true)),
+            None
+        );
+    }
+
+    #[test]
+    fn only_mouse_motion_is_read_from_devices() {
+        assert_eq!(
+            InputEvent::from_device_event(&DeviceEvent::MouseMotion { delta: (1.0, -2.0) }),
+            Some(InputEvent::MouseMotion { dx: 1.0, dy: -2.0 })
+        );
+        assert_eq!(InputEvent::from_device_event(&DeviceEvent::Added), None);
+    }
+}
@@ -1,82 +1,647 @@
+mod bcn;
 mod buffer;
 mod camera;
+mod compressed;
+mod compute;
+mod config;
+mod cubemap;
+mod golden;
+mod input;
 mod light;
+mod mipmap;
 mod model;
 mod pipeline;
+mod post;
 pub mod prelude;
+mod recorder;
+mod reflect;
+mod render_graph;
+mod sampler;
+mod shadow;
+mod skybox;
+mod tangent;
 mod texture;
 
 pub use buffer::*;
 pub use camera::*;
+pub use compressed::*;
+pub use compute::*;
+pub use config::*;
+pub use cubemap::*;
+pub use golden::*;
+pub use input::*;
 pub use light::*;
+pub use mipmap::*;
 pub use model::*;
 pub use pipeline::*;
+pub use post::*;
+pub use recorder::*;
+pub use reflect::*;
+pub use render_graph::*;
+pub use sampler::*;
+pub use shadow::*;
+pub use skybox::*;
+pub use tangent::*;
 pub use texture::*;
+pub use timestep::*;
 
 use anyhow::*;
 use cgmath::*;
+use std::panic::{self, AssertUnwindSafe};
 use std::time::{Duration, Instant};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use winit::dpi::PhysicalSize;
 use winit::event::*;
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::{Window, WindowBuilder};
+use winit::window::{Fullscreen, Window, WindowBuilder};
+
+/// Where a [Display] sends its frames.
+pub enum DisplayTarget {
+    /// Frames are presented to a window through a swap chain.
+    Window {
+        surface: wgpu::Surface,
+        swap_chain: wgpu::SwapChain,
+    },
+    /// Frames are rendered into an offscreen texture. Nothing is
+    /// presented, so this works on machines without a display.
+    Headless { texture: Texture<'static> },
+}
+
+/// The texture a demo should render the current frame into.
+pub enum Frame {
+    Window(wgpu::SwapChainFrame),
+    Texture(wgpu::TextureView),
+}
+
+impl Frame {
+    fn from_texture(texture: &Texture) -> Self {
+        Frame::Texture(texture.texture.create_view(&Default::default()))
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        match self {
+            Frame::Window(frame) => &frame.output.view,
+            Frame::Texture(view) => view,
+        }
+    }
+}
+
+/// What [Display::begin_frame] got ready.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameStatus {
+    /// The frame can be rendered, then finished with
+    /// [Display::end_frame].
+    Ready,
+    /// There's nothing to render to this frame, such as while the window
+    /// is minimised.
+    Skipped,
+    /// The device was lost and [Display::recreate_device] replaced it.
+    /// Nothing made with the old one can be used anymore.
+    DeviceRecreated,
+}
+
+/// What happened when asking the swap chain for a frame.
+enum Acquire {
+    Frame(wgpu::SwapChainFrame),
+    Failed(wgpu::SwapChainError),
+    DeviceLost,
+}
+
+/// How many devices in a row [Display::begin_frame] creates without
+/// getting a frame from any of them before giving up.
+pub const MAX_DEVICE_RECREATIONS: u32 = 3;
+
+/// What wgpu panics with when the device is lost while acquiring a
+/// frame.
+const DEVICE_LOST_MESSAGE: &str = "parent device is lost";
+
+/// wgpu panics rather than returning an error when the device is lost
+/// while acquiring a frame, so that panic is caught and reported as
+/// [Acquire::DeviceLost]. Other panics carry on unwinding. When panics
+/// abort the process there's nothing to catch, so a lost device ends
+/// the demo like any other panic.
+fn acquire_frame(swap_chain: &mut wgpu::SwapChain) -> Acquire {
+    match panic::catch_unwind(AssertUnwindSafe(|| swap_chain.get_current_frame())) {
+        Ok(Ok(frame)) => Acquire::Frame(frame),
+        Ok(Err(e)) => Acquire::Failed(e),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| payload.downcast_ref::<&str>().copied());
+            if message.map_or(false, |m| m.contains(DEVICE_LOST_MESSAGE)) {
+                Acquire::DeviceLost
+            } else {
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+}
+
+/// The [Attachments] name of the multisampled color target behind
+/// [Display::color_attachment].
+pub const MSAA_COLOR: &str = "msaa_color";
 
 pub struct Display {
-    surface: wgpu::Surface,
+    pub target: DisplayTarget,
     pub sc_desc: wgpu::SwapChainDescriptor,
-    pub swap_chain: wgpu::SwapChain,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    /// Transient textures shared by the passes of a [RenderGraph].
+    pub attachments: Attachments,
+    /// Pass to [Texture::from_image_with_mipmaps] as [Mipmaps::Gpu] so
+    /// every texture shares the same pipelines.
+    pub mipmaps: MipmapGenerator,
+    /// Pass to the [Texture] constructors so textures that sample the
+    /// same way share a sampler.
+    pub samplers: SamplerCache,
+    /// How far the demo has been simulated. [run] sets it up from
+    /// [Demo::timestep] when the demo starts.
+    pub clock: Clock,
+    /// The MSAA sample count from [RunConfig::sample_count]. Pipelines
+    /// that draw through [Display::color_attachment], and their depth
+    /// attachments, need to use it.
+    pub sample_count: u32,
+    capture_target: Option<Texture<'static>>,
+    /// Kept to create a new device if the current one is lost.
+    adapter: wgpu::Adapter,
+    config: RunConfig,
+    current_frame: Option<Frame>,
+    suboptimal: bool,
+    /// Devices created since a frame was last acquired.
+    device_recreations: u32,
 }
 
 impl Display {
+    pub const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
     pub async fn new(window: &Window) -> Result<Self, Error> {
+        Self::with_config(window, &RunConfig::default()).await
+    }
+
+    /// Creates a [Display] for `window`, on the adapter and device
+    /// `config` asks for.
+    pub async fn with_config(window: &Window, config: &RunConfig) -> Result<Self, Error> {
         let size = window.inner_size();
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+        let instance = wgpu::Instance::new(config.backends);
         let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::Default,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    shader_validation: true,
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let adapter = request_adapter(&instance, config, Some(&surface)).await?;
+        let (device, queue) = request_device(&adapter, config).await?;
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
             format: wgpu::TextureFormat::Bgra8UnormSrgb,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: config.present_mode,
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        let samplers = SamplerCache::new();
 
-        Ok(Self {
-            surface,
+        let mut display = Self {
+            target: DisplayTarget::Window {
+                surface,
+                swap_chain,
+            },
+            attachments: Attachments::new(sc_desc.width, sc_desc.height, samplers.clone()),
+            mipmaps: MipmapGenerator::new(&device),
+            samplers,
+            clock: Clock::default(),
+            sample_count: config.sample_count,
             sc_desc,
-            swap_chain,
             device,
             queue,
-        })
+            capture_target: None,
+            adapter,
+            config: config.clone(),
+            current_frame: None,
+            suboptimal: false,
+            device_recreations: 0,
+        };
+        display.declare_msaa_target();
+        Ok(display)
+    }
+
+    /// Creates a [Display] that renders into an offscreen texture
+    /// instead of a window. Any backend will do, software renderers
+    /// included.
+    pub async fn headless(width: u32, height: u32) -> Result<Self, Error> {
+        let config = RunConfig::default()
+            .size(width, height)
+            .backends(wgpu::BackendBit::all());
+        Self::headless_with_config(&config).await
+    }
+
+    /// Like [Display::headless], but with the size, adapter and device
+    /// from `config`. Window and present options are ignored.
+    pub async fn headless_with_config(config: &RunConfig) -> Result<Self, Error> {
+        let (width, height) = config
+            .size
+            .context("Headless displays need a size in their RunConfig")?;
+        let instance = wgpu::Instance::new(config.backends);
+        let adapter = request_adapter(&instance, config, None).await?;
+        let (device, queue) = request_device(&adapter, config).await?;
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format: Self::HEADLESS_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let samplers = SamplerCache::new();
+        let texture = create_target_texture(&device, &sc_desc, &samplers);
+
+        let mut display = Self {
+            target: DisplayTarget::Headless { texture },
+            attachments: Attachments::new(sc_desc.width, sc_desc.height, samplers.clone()),
+            mipmaps: MipmapGenerator::new(&device),
+            samplers,
+            clock: Clock::default(),
+            sample_count: config.sample_count,
+            sc_desc,
+            device,
+            queue,
+            capture_target: None,
+            adapter,
+            config: config.clone(),
+            current_frame: None,
+            suboptimal: false,
+            device_recreations: 0,
+        };
+        display.declare_msaa_target();
+        Ok(display)
+    }
+
+    fn declare_msaa_target(&mut self) {
+        if self.sample_count > 1 {
+            self.attachments.declare(
+                &self.device,
+                MSAA_COLOR,
+                AttachmentDesc::color(self.sc_desc.format)
+                    .usage(wgpu::TextureUsage::OUTPUT_ATTACHMENT)
+                    .sample_count(self.sample_count),
+            );
+        }
+    }
+
+    /// The color attachment for a pass that draws into `view`, usually
+    /// the view of the current [Frame]. With [Display::sample_count]
+    /// above 1 the pass draws into the [MSAA_COLOR] attachment instead,
+    /// which is resolved into `view` when the pass ends.
+    pub fn color_attachment<'a>(
+        &'a self,
+        view: &'a wgpu::TextureView,
+        ops: wgpu::Operations<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachmentDescriptor<'a> {
+        match self.attachments.get(MSAA_COLOR) {
+            Some(target) if self.sample_count > 1 => wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: &target.view,
+                resolve_target: Some(view),
+                ops,
+            },
+            _ => wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: view,
+                resolve_target: None,
+                ops,
+            },
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        match self.target {
+            DisplayTarget::Headless { .. } => true,
+            DisplayTarget::Window { .. } => false,
+        }
+    }
+
+    /// Whether there's nothing to render to, such as when the window is
+    /// minimised.
+    pub fn is_minimised(&self) -> bool {
+        self.sc_desc.width == 0 || self.sc_desc.height == 0
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         self.sc_desc.width = width;
         self.sc_desc.height = height;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        // Swap chains and textures can't be empty, so they're left alone
+        // until the window is restored
+        if self.is_minimised() {
+            return;
+        }
+        self.recreate_target();
+        self.attachments.resize(&self.device, width, height);
+    }
+
+    fn recreate_target(&mut self) {
+        self.current_frame = None;
+        self.suboptimal = false;
+        match &mut self.target {
+            DisplayTarget::Window {
+                surface,
+                swap_chain,
+            } => *swap_chain = self.device.create_swap_chain(surface, &self.sc_desc),
+            DisplayTarget::Headless { texture } => {
+                *texture = create_target_texture(&self.device, &self.sc_desc, &self.samplers)
+            }
+        }
+    }
+
+    /// Replaces a lost device with a new one from the same adapter, and
+    /// recreates everything the [Display] made with the old one. What
+    /// the demo made is up to [Demo::recreate_resources].
+    ///
+    /// [Display::samplers] is replaced by a cache for the new device,
+    /// so clones of the old one shouldn't be used anymore.
+    ///
+    /// This blocks until the device is ready. Native backends hand it
+    /// over straight away, but this can't be used where blocking isn't
+    /// allowed, such as on the web.
+    pub fn recreate_device(&mut self) -> Result<(), Error> {
+        self.current_frame = None;
+        self.capture_target = None;
+        let (device, queue) =
+            futures::executor::block_on(request_device(&self.adapter, &self.config))
+                .context("Unable to replace the lost device")?;
+        self.device = device;
+        self.queue = queue;
+        self.mipmaps = MipmapGenerator::new(&self.device);
+        self.samplers = self.samplers.for_new_device();
+        self.attachments
+            .recreate(&self.device, self.samplers.clone());
+        if !self.is_minimised() {
+            self.recreate_target();
+        }
+        Ok(())
+    }
+
+    /// Acquires the next frame for [Display::get_current_frame],
+    /// recovering from whatever happened to the swap chain since the
+    /// last one. Outdated and lost swap chains are recreated, a timeout
+    /// is retried once before the frame is skipped, and a device that
+    /// was lost is replaced. Fails when the display can't recover,
+    /// including when [MAX_DEVICE_RECREATIONS] devices in a row were
+    /// lost before one gave a frame.
+    pub fn begin_frame(&mut self) -> Result<FrameStatus, Error> {
+        if self.is_minimised() {
+            return Ok(FrameStatus::Skipped);
+        }
+        self.current_frame = None;
+        let mut retried = false;
+        let mut recreated = false;
+        loop {
+            let acquired = match &mut self.target {
+                DisplayTarget::Window { swap_chain, .. } => acquire_frame(swap_chain),
+                DisplayTarget::Headless { .. } => return Ok(FrameStatus::Ready),
+            };
+            match acquired {
+                Acquire::Frame(frame) => {
+                    self.device_recreations = 0;
+                    self.suboptimal = frame.suboptimal;
+                    self.current_frame = Some(Frame::Window(frame));
+                    return Ok(FrameStatus::Ready);
+                }
+                Acquire::Failed(wgpu::SwapChainError::Timeout) if !retried => retried = true,
+                Acquire::Failed(wgpu::SwapChainError::Timeout) => {
+                    log::warn!("Timed out acquiring a frame, skipping it");
+                    return Ok(FrameStatus::Skipped);
+                }
+                Acquire::Failed(wgpu::SwapChainError::Outdated)
+                | Acquire::Failed(wgpu::SwapChainError::Lost)
+                    if !recreated =>
+                {
+                    recreated = true;
+                    self.recreate_target();
+                }
+                // Outdated again straight away means the window is still
+                // changing, and a resize will follow
+                Acquire::Failed(wgpu::SwapChainError::Outdated) => return Ok(FrameStatus::Skipped),
+                // A new swap chain didn't help, so the device went with it
+                Acquire::Failed(wgpu::SwapChainError::Lost) | Acquire::DeviceLost => {
+                    if self.device_recreations >= MAX_DEVICE_RECREATIONS {
+                        bail!(
+                            "The device was lost {} times without presenting a frame",
+                            self.device_recreations + 1
+                        );
+                    }
+                    self.device_recreations += 1;
+                    log::warn!("The device was lost, creating a new one");
+                    self.recreate_device()?;
+                    return Ok(FrameStatus::DeviceRecreated);
+                }
+                Acquire::Failed(wgpu::SwapChainError::OutOfMemory) => {
+                    bail!("Out of memory acquiring a frame")
+                }
+            }
+        }
+    }
+
+    /// Presents the frame from [Display::begin_frame] if the demo didn't
+    /// take it, and recreates the swap chain if it no longer matches the
+    /// surface well.
+    pub fn end_frame(&mut self) {
+        self.current_frame = None;
+        if self.suboptimal && !self.is_minimised() {
+            self.recreate_target();
+        }
+    }
+
+    /// Gets the texture to render the next frame into. For a window
+    /// the frame is presented when the returned [Frame] is dropped.
+    /// Between [Display::begin_frame] and [Display::end_frame] this is
+    /// the frame `begin_frame` acquired.
+    pub fn get_current_frame(&mut self) -> Result<Frame, wgpu::SwapChainError> {
+        if let Some(texture) = &self.capture_target {
+            return Ok(Frame::from_texture(texture));
+        }
+        if let Some(frame) = self.current_frame.take() {
+            return Ok(frame);
+        }
+        match &mut self.target {
+            DisplayTarget::Window { swap_chain, .. } => {
+                swap_chain.get_current_frame().map(Frame::Window)
+            }
+            DisplayTarget::Headless { texture } => Ok(Frame::from_texture(texture)),
+        }
+    }
+
+    /// Renders a frame of `demo` and returns it as an image.
+    ///
+    /// The contents of a swap chain can't be read back, so the frame
+    /// is rendered into an offscreen texture with the same size and
+    /// format as the swap chain instead.
+    pub async fn capture_frame<D: Demo>(&mut self, demo: &mut D) -> Result<image::RgbaImage> {
+        self.capture_target = Some(create_target_texture(
+            &self.device,
+            &self.sc_desc,
+            &self.samplers,
+        ));
+        let alpha = self.clock.alpha();
+        demo.render(self, alpha);
+        let texture = self
+            .capture_target
+            .take()
+            .context("Capture target was removed while rendering")?;
+        texture.read_pixels(&self.device, &self.queue).await
+    }
+}
+
+/// The adapters wgpu picks for `surface` when asked for each power
+/// preference. wgpu can't say whether a particular adapter can draw to
+/// a surface, only pick one that can, so this is as close as it gets to
+/// listing all of them.
+async fn compatible_adapters(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+) -> Vec<wgpu::Adapter> {
+    let mut adapters: Vec<wgpu::Adapter> = Vec::new();
+    for &power_preference in &[
+        wgpu::PowerPreference::LowPower,
+        wgpu::PowerPreference::HighPerformance,
+    ] {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference,
+                compatible_surface: Some(surface),
+            })
+            .await;
+        if let Some(adapter) = adapter {
+            let info = adapter.get_info();
+            if adapters.iter().all(|a| a.get_info() != info) {
+                adapters.push(adapter);
+            }
+        }
+    }
+    adapters
+}
+
+/// Picks the adapter named by [RunConfig::adapter_name], or the one wgpu
+/// prefers. Either way, with a `surface` the adapter has to be able to
+/// draw to it.
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    config: &RunConfig,
+    surface: Option<&wgpu::Surface>,
+) -> Result<wgpu::Adapter, Error> {
+    if let Some(name) = &config.adapter_name {
+        let wanted = name.to_lowercase();
+        let candidates = match surface {
+            Some(surface) => compatible_adapters(instance, surface).await,
+            None => instance.enumerate_adapters(config.backends).collect(),
+        };
+        let mut others = Vec::new();
+        for adapter in candidates {
+            let info = adapter.get_info();
+            if info.name.to_lowercase().contains(&wanted) {
+                return Ok(adapter);
+            }
+            others.push(format!("{} ({:?})", info.name, info.backend));
+        }
+        bail!(
+            "No adapter {}matches {:?}. Adapters for {:?}: {}",
+            if surface.is_some() {
+                "that can draw to the window "
+            } else {
+                ""
+            },
+            name,
+            config.backends,
+            if others.is_empty() {
+                String::from("none")
+            } else {
+                others.join(", ")
+            }
+        );
+    }
+
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: config.power_preference,
+            compatible_surface: surface,
+        })
+        .await;
+    match (adapter, surface) {
+        (Some(adapter), _) => Ok(adapter),
+        // request_adapter can skip adapters that it doesn't consider
+        // "real" GPUs. Without a window we don't care what we render
+        // with, so take whatever wgpu can find, software renderers
+        // included.
+        (None, None) => instance
+            .enumerate_adapters(config.backends)
+            .next()
+            .with_context(|| format!("No adapters found for {:?}", config.backends)),
+        (None, Some(_)) => bail!(
+            "No adapter for {:?} can draw to the window",
+            config.backends
+        ),
     }
 }
 
+async fn request_device(
+    adapter: &wgpu::Adapter,
+    config: &RunConfig,
+) -> Result<(wgpu::Device, wgpu::Queue), Error> {
+    let info = adapter.get_info();
+    let missing = config.features - adapter.features();
+    if !missing.is_empty() {
+        bail!("{} doesn't support the features {:?}", info.name, missing);
+    }
+    let exceeded = exceeded_limits(&config.limits, &adapter.limits());
+    if !exceeded.is_empty() {
+        bail!(
+            "{} doesn't support the limits {}",
+            info.name,
+            exceeded.join(", ")
+        );
+    }
+
+    let device_and_queue = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                // Compressed textures are decompressed on the CPU
+                // without this, so only ask for it when it's there
+                features: config.features
+                    | (adapter.features() & wgpu::Features::TEXTURE_COMPRESSION_BC),
+                limits: config.limits.clone(),
+                shader_validation: true,
+            },
+            None,
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "Unable to create a device on {} ({:?})",
+                info.name, info.backend
+            )
+        })?;
+    Ok(device_and_queue)
+}
+
+fn create_target_texture(
+    device: &wgpu::Device,
+    sc_desc: &wgpu::SwapChainDescriptor,
+    samplers: &SamplerCache,
+) -> Texture<'static> {
+    Texture::from_descriptor(
+        device,
+        wgpu::TextureDescriptor {
+            label: Some("Display Target Texture"),
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: sc_desc.format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT
+                | wgpu::TextureUsage::COPY_SRC
+                | wgpu::TextureUsage::SAMPLED,
+        },
+        samplers,
+        &SamplerConfig::default(),
+    )
+}
+
 /**
  * Holds the camera data to be passed to wgpu.
  */
@@ -180,71 +745,303 @@ impl UniformBinding {
 
 pub trait Demo: 'static + Sized {
     fn init(display: &Display) -> Result<Self, Error>;
-    fn process_mouse(&mut self, dx: f64, dy: f64);
+
+    /// Without a [FixedTimestep], [Demo::update] is called once a frame
+    /// with however long the frame took.
+    fn timestep(&self) -> Option<FixedTimestep> {
+        None
+    }
+
+    /// How fast time passes for [Demo::update]. 0 pauses the demo, 0.5
+    /// runs it at half speed. Checked every frame. Scales that aren't
+    /// positive, including NaN, pause too, and scales above
+    /// [MAX_TIME_SCALE] are clamped to it.
+    fn time_scale(&self) -> f64 {
+        1.0
+    }
+
+    /// The camera the framework should fly around for this demo. It's
+    /// sent every [InputEvent] the demo doesn't use itself, has its
+    /// projection resized with the window and is moved once a frame
+    /// before [Demo::update]. It ignores [Demo::time_scale], so it can
+    /// still look around a paused demo.
+    fn fly_camera(&mut self) -> Option<&mut FlyCamera> {
+        None
+    }
+
+    /// Returns whether `event` was used. Unused events go to
+    /// [Demo::fly_camera].
+    fn input(&mut self, _event: &InputEvent) -> bool {
+        false
+    }
+
     fn resize(&mut self, display: &Display);
     fn update(&mut self, display: &Display, dt: Duration);
-    fn render(&mut self, display: &mut Display);
+
+    /// `alpha` is how far this frame is between the last update and the
+    /// next one, from [Clock::alpha].
+    fn render(&mut self, display: &mut Display, alpha: f32);
+
+    /// Called when the window gains or loses keyboard focus. The
+    /// demo isn't updated or rendered while it's unfocused.
+    fn on_focus(&mut self, _focused: bool) {}
+
+    /// Called when the app is suspended or resumed, such as when an
+    /// Android app goes to the background.
+    fn on_suspend(&mut self, _suspended: bool) {}
+
+    /// Called once before the event loop exits, while the [Display]
+    /// is still around.
+    fn on_exit(&mut self, _display: &Display) {}
+
+    /// Called after the device was lost and [Display::recreate_device]
+    /// replaced it, when every buffer, texture and pipeline the demo has
+    /// is unusable. There's no default, as only the demo knows which of
+    /// its state to keep. One that has nothing worth keeping can start
+    /// over with `*self = Self::init(display)?`.
+    fn recreate_resources(&mut self, display: &Display) -> Result<(), Error>;
 }
 
+fn dispatch_input<D: Demo>(demo: &mut D, event: &InputEvent) {
+    if !demo.input(event) {
+        if let Some(fly_camera) = demo.fly_camera() {
+            fly_camera.controller.process_input(event);
+        }
+    }
+}
+
+fn resize_demo<D: Demo>(display: &mut Display, demo: &mut D, width: u32, height: u32) {
+    display.resize(width, height);
+    // Nothing is rendered while minimised, and a projection with no
+    // height has no aspect ratio
+    if display.is_minimised() {
+        return;
+    }
+    if let Some(fly_camera) = demo.fly_camera() {
+        fly_camera.projection.resize(width, height);
+    }
+    demo.resize(display);
+}
+
+pub(crate) fn init_demo<D: Demo>(display: &mut Display) -> Result<D, Error> {
+    let demo = D::init(display)?;
+    display.clock = Clock::new(demo.timestep());
+    Ok(demo)
+}
+
+/// Runs however many updates `frame_time` is worth.
+pub(crate) fn advance_demo<D: Demo>(display: &mut Display, demo: &mut D, frame_time: Duration) {
+    if let Some(fly_camera) = demo.fly_camera() {
+        fly_camera.update(frame_time);
+    }
+    let (steps, dt) = display.clock.advance(frame_time, demo.time_scale());
+    for _ in 0..steps {
+        demo.update(display, dt);
+    }
+}
+
+pub(crate) fn render_demo<D: Demo>(display: &mut Display, demo: &mut D) {
+    let alpha = display.clock.alpha();
+    demo.render(display, alpha);
+}
+
+/// Renders and presents a frame of `demo`, if there's anything to
+/// present to.
+fn present_demo<D: Demo>(display: &mut Display, demo: &mut D) -> Result<(), Error> {
+    match display.begin_frame()? {
+        FrameStatus::Ready => {
+            render_demo(display, demo);
+            display.end_frame();
+        }
+        FrameStatus::Skipped => {}
+        FrameStatus::DeviceRecreated => demo
+            .recreate_resources(display)
+            .context("Unable to recreate the demo's resources")?,
+    }
+    Ok(())
+}
+
+/// Pressing this key while a demo is running saves a screenshot to
+/// the current working directory.
+pub const SCREENSHOT_KEY: VirtualKeyCode = VirtualKeyCode::F12;
+
+fn save_screenshot<D: Demo>(display: &mut Display, demo: &mut D) {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = format!("screenshot-{}.png", timestamp);
+    let result = futures::executor::block_on(display.capture_frame(demo))
+        .and_then(|image| image.save(&path).map_err(Error::from));
+    match result {
+        Ok(()) => log::info!("Saved screenshot to {}", path),
+        Err(e) => log::error!("Unable to save screenshot: {}", e),
+    }
+}
+
+fn build_window(event_loop: &EventLoop<()>, config: &RunConfig) -> Result<Window, Error> {
+    let mut builder = WindowBuilder::new().with_title(&config.title);
+    if let Some((width, height)) = config.size {
+        builder = builder.with_inner_size(PhysicalSize::new(width, height));
+    }
+    let monitor = event_loop.primary_monitor();
+    let fullscreen = match config.window_mode {
+        WindowMode::Windowed => None,
+        WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        WindowMode::Fullscreen => {
+            let size = monitor.size();
+            let (width, height) = config.size.unwrap_or((size.width, size.height));
+            let video_mode = monitor
+                .video_modes()
+                .min_by_key(|mode| {
+                    let size = mode.size();
+                    (size.width as i64 - width as i64).abs()
+                        + (size.height as i64 - height as i64).abs()
+                })
+                .context("The primary monitor has no video modes to go fullscreen with")?;
+            Some(Fullscreen::Exclusive(video_mode))
+        }
+    };
+    builder
+        .with_fullscreen(fullscreen)
+        .build(event_loop)
+        .context("Unable to create the window")
+}
+
+/// Runs `D` in a window set up by [RunConfig::load], so it can be
+/// configured from the command line and the environment.
 pub async fn run<D: Demo>() -> Result<(), Error> {
+    run_with_config::<D>(RunConfig::load()?).await
+}
+
+pub async fn run_with_config<D: Demo>(config: RunConfig) -> Result<(), Error> {
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_title(env!("CARGO_PKG_NAME"))
-        .build(&event_loop)?;
-    let mut display = Display::new(&window).await?;
-    let mut demo = D::init(&mut display)?;
+    let window = build_window(&event_loop, &config)?;
+    let mut display = Display::with_config(&window, &config).await?;
+    let mut demo = init_demo::<D>(&mut display)?;
     let mut last_update = Instant::now();
     let mut is_resumed = true;
     let mut is_focused = true;
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = if is_resumed && is_focused {
+        *control_flow = if is_resumed && is_focused && !display.is_minimised() {
             ControlFlow::Poll
         } else {
             ControlFlow::Wait
         };
 
         match event {
-            Event::Resumed => is_resumed = true,
-            Event::Suspended => is_resumed = false,
+            Event::Resumed => {
+                is_resumed = true;
+                demo.on_suspend(false);
+            }
+            Event::Suspended => {
+                is_resumed = false;
+                demo.on_suspend(true);
+            }
             Event::RedrawRequested(wid) => {
                 if wid == window.id() {
                     let now = Instant::now();
                     let dt = now - last_update;
                     last_update = now;
 
-                    demo.update(&mut display, dt);
-                    demo.render(&mut display);
+                    advance_demo(&mut display, &mut demo, dt);
+                    if let Err(e) = present_demo(&mut display, &mut demo) {
+                        log::error!("{:#}", e);
+                        *control_flow = ControlFlow::Exit;
+                    }
                 }
             }
             Event::MainEventsCleared => {
-                if is_focused && is_resumed {
+                if is_focused && is_resumed && !display.is_minimised() {
                     window.request_redraw();
                 } else {
                     // Freeze time while the demo is not in the foreground
                     last_update = Instant::now();
                 }
             }
+            Event::DeviceEvent { event, .. } => {
+                // Device events arrive whichever window has focus
+                if is_focused {
+                    if let Some(input) = InputEvent::from_device_event(&event) {
+                        dispatch_input(&mut demo, &input);
+                    }
+                }
+            }
             Event::WindowEvent {
                 event, window_id, ..
             } => {
                 if window_id == window.id() {
                     match event {
                         WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Focused(f) => is_focused = f,
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(SCREENSHOT_KEY),
+                                    ..
+                                },
+                            ..
+                        } => save_screenshot(&mut display, &mut demo),
+                        WindowEvent::Focused(f) => {
+                            is_focused = f;
+                            if !f {
+                                // The key releases will go to whatever
+                                // has focus now
+                                if let Some(fly_camera) = demo.fly_camera() {
+                                    fly_camera.controller.release_all();
+                                }
+                            }
+                            demo.on_focus(f);
+                        }
                         WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            display.resize(new_inner_size.width, new_inner_size.height);
-                            demo.resize(&mut display);
+                            resize_demo(
+                                &mut display,
+                                &mut demo,
+                                new_inner_size.width,
+                                new_inner_size.height,
+                            );
                         }
                         WindowEvent::Resized(new_inner_size) => {
-                            display.resize(new_inner_size.width, new_inner_size.height);
-                            demo.resize(&mut display);
+                            resize_demo(
+                                &mut display,
+                                &mut demo,
+                                new_inner_size.width,
+                                new_inner_size.height,
+                            );
+                        }
+                        event => {
+                            if let Some(input) = InputEvent::from_window_event(&event) {
+                                dispatch_input(&mut demo, &input);
+                            }
                         }
-                        _ => {}
                     }
                 }
             }
+            Event::LoopDestroyed => demo.on_exit(&display),
             _ => {}
         }
     });
 }
+
+/// Drives a [Demo] for `frames` frames without opening a window.
+/// Each frame advances time by `dt`, so the results don't depend on
+/// how fast the machine is. The [Display] and the [Demo] are handed
+/// back so that the caller can inspect what was rendered.
+pub async fn run_headless<D: Demo>(
+    width: u32,
+    height: u32,
+    frames: usize,
+    dt: Duration,
+) -> Result<(Display, D), Error> {
+    let mut display = Display::headless(width, height).await?;
+    let mut demo = init_demo::<D>(&mut display)?;
+
+    for _ in 0..frames {
+        advance_demo(&mut display, &mut demo, dt);
+        present_demo(&mut display, &mut demo)?;
+    }
+    display.device.poll(wgpu::Maintain::Wait);
+
+    Ok((display, demo))
+}
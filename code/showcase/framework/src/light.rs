@@ -1,40 +1,559 @@
 use cgmath::*;
+use std::{iter, mem};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 
+use crate::buffer::ToRaw;
+
+/// How quickly a light fades with distance. The light is divided by
+/// `constant + linear * d + quadratic * d * d`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    /// The light doesn't fade at all.
+    pub const NONE: Self = Self {
+        constant: 1.0,
+        linear: 0.0,
+        quadratic: 0.0,
+    };
+
+    /// Physically correct inverse square falloff.
+    pub const INVERSE_SQUARE: Self = Self {
+        constant: 0.0,
+        linear: 0.0,
+        quadratic: 1.0,
+    };
+
+    /// Falls off to roughly 1% of its brightness at `range`.
+    pub fn range(range: f32) -> Self {
+        Self {
+            constant: 1.0,
+            linear: 4.5 / range,
+            quadratic: 75.0 / (range * range),
+        }
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Shines in every direction from `position`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PointLight {
+    pub position: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub attenuation: Attenuation,
+}
+
+/// Light from infinitely far away, like the sun. `direction` is the
+/// way the light travels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DirectionalLight {
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+}
+
+/// A cone of light. Inside `inner_angle` the light is at full
+/// brightness, and it fades out towards `outer_angle`. Both angles are
+/// measured from `direction` to the edge of the cone.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpotLight {
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub attenuation: Attenuation,
+    pub inner_angle: Rad<f32>,
+    pub outer_angle: Rad<f32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Light {
+    Point(PointLight),
+    Directional(DirectionalLight),
+    Spot(SpotLight),
+}
+
+impl Light {
+    /// Creates a white-ish point light the way the old uniform buffer
+    /// light was created. The device isn't used anymore, as lights are
+    /// uploaded by a [LightManager].
+    #[deprecated(note = "Create a PointLight and add it to a LightManager")]
+    pub fn new(_device: &wgpu::Device, position: Vector3<f32>, color: Vector3<f32>) -> Self {
+        Light::Point(PointLight {
+            position,
+            color,
+            intensity: 1.0,
+            attenuation: Attenuation::NONE,
+        })
+    }
+
+    /// `None` for directional lights.
+    pub fn position(&self) -> Option<Vector3<f32>> {
+        match self {
+            Light::Point(l) => Some(l.position),
+            Light::Directional(_) => None,
+            Light::Spot(l) => Some(l.position),
+        }
+    }
+
+    /// `None` for point lights.
+    pub fn direction(&self) -> Option<Vector3<f32>> {
+        match self {
+            Light::Point(_) => None,
+            Light::Directional(l) => Some(l.direction),
+            Light::Spot(l) => Some(l.direction),
+        }
+    }
+
+    pub fn color(&self) -> Vector3<f32> {
+        match self {
+            Light::Point(l) => l.color,
+            Light::Directional(l) => l.color,
+            Light::Spot(l) => l.color,
+        }
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Self {
+        Light::Directional(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Light::Spot(light)
+    }
+}
+
+pub const LIGHT_KIND_POINT: u32 = 0;
+pub const LIGHT_KIND_DIRECTIONAL: u32 = 1;
+pub const LIGHT_KIND_SPOT: u32 = 2;
+
+/// A [Light] as the shaders see it:
+///
+/// ```glsl
+/// struct Light {
+///     vec4 position; // w is the kind of light
+///     vec4 direction;
+///     vec4 color; // w is the intensity
+///     vec4 attenuation; // constant, linear, quadratic
+///     vec4 cone; // cosines of the inner and outer angles, then the
+///                // first shadow map layer and the number of layers
+/// };
+///
+/// layout(set=2, binding=0) readonly buffer Lights {
+///     uint light_count;
+///     Light lights[];
+/// };
+/// ```
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct LightData {
-    pub position: Vector4<f32>,
-    pub color: Vector4<f32>,
+    pub position: [f32; 4],
+    pub direction: [f32; 4],
+    pub color: [f32; 4],
+    pub attenuation: [f32; 4],
+    pub cone: [f32; 4],
 }
 
 unsafe impl bytemuck::Pod for LightData {}
 unsafe impl bytemuck::Zeroable for LightData {}
 
-pub struct Light {
-    #[allow(dead_code)]
-    data: LightData,
-    #[allow(dead_code)]
-    buffer: wgpu::Buffer,
-}
-
-impl Light {
-    pub fn new(device: &wgpu::Device, position: Vector3<f32>, color: Vector3<f32>) -> Self {
-        let data = LightData {
-            position: Vector4::new(position.x, position.y, position.z, 1.0),
-            color: Vector4::new(color.x, color.y, color.z, 1.0),
+impl ToRaw for Light {
+    type Output = LightData;
+    fn to_raw(&self) -> LightData {
+        let (kind, attenuation, cone) = match self {
+            Light::Point(l) => (LIGHT_KIND_POINT, l.attenuation, [1.0, 1.0]),
+            Light::Directional(_) => (LIGHT_KIND_DIRECTIONAL, Attenuation::NONE, [1.0, 1.0]),
+            Light::Spot(l) => (
+                LIGHT_KIND_SPOT,
+                l.attenuation,
+                [l.inner_angle.cos(), l.outer_angle.cos()],
+            ),
         };
-        let buffer = device.create_buffer_init(&BufferInitDescriptor {
-            contents: bytemuck::cast_slice(&[data]),
-            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::UNIFORM,
-            label: Some("Light Buffer"),
-        });
+        let intensity = match self {
+            Light::Point(l) => l.intensity,
+            Light::Directional(l) => l.intensity,
+            Light::Spot(l) => l.intensity,
+        };
+        let position = self.position().unwrap_or_else(Vector3::zero);
+        // A zero vector would turn into NaNs in the shader
+        let direction = self
+            .direction()
+            .filter(|d| d.magnitude2() > 0.0)
+            .map(|d| d.normalize())
+            .unwrap_or_else(|| -Vector3::unit_y());
+        let color = self.color();
 
-        Self { data, buffer }
+        LightData {
+            position: [position.x, position.y, position.z, kind as f32],
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            color: [color.x, color.y, color.z, intensity],
+            attenuation: [
+                attenuation.constant,
+                attenuation.linear,
+                attenuation.quadratic,
+                0.0,
+            ],
+            cone: [cone[0], cone[1], 0.0, 0.0],
+        }
     }
 }
 
+/// The layers of the shadow map array a light's shadows are rendered
+/// to. Directional lights have one layer per cascade.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ShadowLayers {
+    pub first: u32,
+    pub count: u32,
+}
+
+/// Identifies a light in a [LightManager]. Ids stay valid until the
+/// light is removed. Its slot is reused afterwards, but with a new
+/// generation, so the old id doesn't refer to the new light.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LightId {
+    index: usize,
+    generation: u32,
+}
+
+/// The bind group lights used to be read from. [LightManager] has the
+/// same `layout` and `bind_group` fields.
+#[deprecated(note = "Use LightManager's layout and bind_group")]
 pub struct LightBinding {
     pub layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
+
+/// The header in front of the lights in the storage buffer. The array
+/// of lights is aligned to 16 bytes, so the count is padded.
+const HEADER_SIZE: usize = mem::size_of::<[u32; 4]>();
+
+/// Lays the lights out the way the `Lights` buffer in the shaders
+/// expects them.
+fn light_buffer_contents(lights: impl Iterator<Item = LightData>, capacity: usize) -> Vec<u8> {
+    let data = lights.collect::<Vec<_>>();
+    let mut contents = Vec::with_capacity(HEADER_SIZE + capacity * mem::size_of::<LightData>());
+    contents.extend_from_slice(bytemuck::cast_slice(&[data.len() as u32, 0, 0, 0]));
+    contents.extend_from_slice(bytemuck::cast_slice(&data));
+    // Binding sizes have to stay the same, and an empty runtime array
+    // still needs room for one element
+    contents.resize(
+        HEADER_SIZE + capacity.max(1) * mem::size_of::<LightData>(),
+        0,
+    );
+    contents
+}
+
+/// Holds every light in a scene, and keeps them in a storage buffer
+/// bound to the group the shaders read lights from.
+///
+/// Changes are only uploaded by [LightManager::update_buffer], so lights
+/// can be moved any number of times a frame.
+pub struct LightManager {
+    lights: Vec<Option<Light>>,
+    shadows: Vec<Option<ShadowLayers>>,
+    /// Bumped every time a slot's light is removed
+    generations: Vec<u32>,
+    capacity: usize,
+    dirty: bool,
+    buffer: wgpu::Buffer,
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl LightManager {
+    /// `capacity` is how many lights the buffer starts with room for.
+    /// It grows when more lights are added.
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::StorageBuffer {
+                    dynamic: false,
+                    min_binding_size: None,
+                    readonly: true,
+                },
+                count: None,
+            }],
+            label: Some("LightManager::layout"),
+        });
+        let capacity = capacity.max(1);
+        let buffer = Self::create_buffer(device, &light_buffer_contents(iter::empty(), capacity));
+        let bind_group = Self::create_bind_group(device, &layout, &buffer);
+
+        Self {
+            lights: Vec::new(),
+            shadows: Vec::new(),
+            generations: Vec::new(),
+            capacity,
+            dirty: false,
+            buffer,
+            layout,
+            bind_group,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, contents: &[u8]) -> wgpu::Buffer {
+        device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+            }],
+            label: Some("LightManager::bind_group"),
+        })
+    }
+
+    pub fn add<L: Into<Light>>(&mut self, light: L) -> LightId {
+        self.dirty = true;
+        let light = Some(light.into());
+        let index = match self.lights.iter().position(Option::is_none) {
+            Some(i) => {
+                self.lights[i] = light;
+                self.shadows[i] = None;
+                i
+            }
+            None => {
+                self.lights.push(light);
+                self.shadows.push(None);
+                self.generations.push(0);
+                self.lights.len() - 1
+            }
+        };
+        self.id(index)
+    }
+
+    fn id(&self, index: usize) -> LightId {
+        LightId {
+            index,
+            generation: self.generations[index],
+        }
+    }
+
+    /// The slot of the light `id` refers to, if it hasn't been removed.
+    fn index(&self, id: LightId) -> Option<usize> {
+        match self.generations.get(id.index) {
+            Some(generation) if *generation == id.generation => Some(id.index),
+            _ => None,
+        }
+    }
+
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        let index = self.index(id)?;
+        let light = self.lights[index].take();
+        self.shadows[index] = None;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.dirty |= light.is_some();
+        light
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.lights[self.index(id)?].as_ref()
+    }
+
+    /// The light will be uploaded on the next
+    /// [LightManager::update_buffer], whether it was changed or not.
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        let index = self.index(id)?;
+        let light = self.lights[index].as_mut();
+        self.dirty |= light.is_some();
+        light
+    }
+
+    pub fn set<L: Into<Light>>(&mut self, id: LightId, light: L) {
+        if let Some(l) = self.get_mut(id) {
+            *l = light.into();
+        }
+    }
+
+    /// Does nothing for directional lights.
+    pub fn set_position(&mut self, id: LightId, position: Vector3<f32>) {
+        match self.get_mut(id) {
+            Some(Light::Point(l)) => l.position = position,
+            Some(Light::Spot(l)) => l.position = position,
+            _ => {}
+        }
+    }
+
+    /// Does nothing for point lights.
+    pub fn set_direction(&mut self, id: LightId, direction: Vector3<f32>) {
+        match self.get_mut(id) {
+            Some(Light::Directional(l)) => l.direction = direction,
+            Some(Light::Spot(l)) => l.direction = direction,
+            _ => {}
+        }
+    }
+
+    pub fn set_color(&mut self, id: LightId, color: Vector3<f32>) {
+        match self.get_mut(id) {
+            Some(Light::Point(l)) => l.color = color,
+            Some(Light::Directional(l)) => l.color = color,
+            Some(Light::Spot(l)) => l.color = color,
+            None => {}
+        }
+    }
+
+    pub fn shadow_layers(&self, id: LightId) -> Option<ShadowLayers> {
+        self.shadows[self.index(id)?]
+    }
+
+    /// Tells the shaders which shadow maps to sample for the light.
+    /// `ShadowMaps` calls this when a light starts or stops casting
+    /// shadows.
+    pub fn set_shadow_layers(&mut self, id: LightId, layers: Option<ShadowLayers>) {
+        if let Some(index) = self.index(id) {
+            self.shadows[index] = layers;
+            self.dirty = true;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.lights
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, l)| l.as_ref().map(|l| (self.id(i), l)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn raw_lights(&self) -> impl Iterator<Item = LightData> + '_ {
+        self.lights
+            .iter()
+            .zip(&self.shadows)
+            .filter_map(|(light, shadow)| {
+                let mut data = light.as_ref()?.to_raw();
+                if let Some(shadow) = shadow {
+                    data.cone[2] = shadow.first as f32;
+                    data.cone[3] = shadow.count as f32;
+                }
+                Some(data)
+            })
+    }
+
+    /// Uploads the lights if any of them changed. When there are more
+    /// lights than the buffer has room for, it's replaced with a bigger
+    /// one and `bind_group` changes.
+    pub fn update_buffer(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let count = self.len();
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            let contents = light_buffer_contents(self.raw_lights(), self.capacity);
+            self.buffer = Self::create_buffer(device, &contents);
+            self.bind_group = Self::create_bind_group(device, &self.layout, &self.buffer);
+            return;
+        }
+
+        let contents = light_buffer_contents(self.raw_lights(), self.capacity);
+        let staging_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Light Update Buffer"),
+            contents: &contents,
+            usage: wgpu::BufferUsage::COPY_SRC,
+        });
+        encoder.copy_buffer_to_buffer(
+            &staging_buffer,
+            0,
+            &self.buffer,
+            0,
+            contents.len() as wgpu::BufferAddress,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn point() -> PointLight {
+        PointLight {
+            position: Vector3::new(1.0, 2.0, 3.0),
+            color: Vector3::new(1.0, 0.5, 0.25),
+            intensity: 2.0,
+            attenuation: Attenuation::range(10.0),
+        }
+    }
+
+    #[test]
+    fn lights_are_packed_for_the_shader() {
+        let spot = Light::Spot(SpotLight {
+            position: Vector3::zero(),
+            direction: Vector3::new(0.0, 0.0, -2.0),
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            attenuation: Attenuation::INVERSE_SQUARE,
+            inner_angle: Rad(0.0),
+            outer_angle: Deg(60.0).into(),
+        })
+        .to_raw();
+        assert_eq!(spot.position[3], LIGHT_KIND_SPOT as f32);
+        assert_eq!(spot.direction, [0.0, 0.0, -1.0, 0.0]);
+        assert_eq!(spot.attenuation, [0.0, 0.0, 1.0, 0.0]);
+        assert!((spot.cone[0] - 1.0).abs() < 1e-6);
+        assert!((spot.cone[1] - 0.5).abs() < 1e-6);
+
+        let point = Light::from(point()).to_raw();
+        assert_eq!(point.position, [1.0, 2.0, 3.0, LIGHT_KIND_POINT as f32]);
+        assert_eq!(point.color, [1.0, 0.5, 0.25, 2.0]);
+    }
+
+    #[test]
+    fn buffer_starts_with_the_light_count() {
+        let lights = [Light::from(point()), Light::from(point())];
+        let contents = light_buffer_contents(lights.iter().map(ToRaw::to_raw), 4);
+        assert_eq!(
+            contents.len(),
+            HEADER_SIZE + 4 * mem::size_of::<LightData>()
+        );
+        assert_eq!(contents[..4], 2u32.to_ne_bytes());
+        let first = &contents[HEADER_SIZE..HEADER_SIZE + mem::size_of::<LightData>()];
+        assert_eq!(first, bytemuck::bytes_of(&lights[0].to_raw()));
+    }
+
+    #[test]
+    fn empty_buffer_still_has_room_for_one_light() {
+        let contents = light_buffer_contents(iter::empty(), 0);
+        assert_eq!(contents.len(), HEADER_SIZE + mem::size_of::<LightData>());
+        assert_eq!(contents[..4], 0u32.to_ne_bytes());
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::*;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+
+use crate::pipeline::RenderPipelineBuilder;
+
+/// How a texture gets its mip levels when it's loaded.
+pub enum Mipmaps<'a> {
+    /// Only the full size image.
+    None,
+    /// Rendered on the GPU, each level from the one before it.
+    Gpu(&'a mut MipmapGenerator),
+    /// Downsampled on the CPU before uploading, for when there's no
+    /// [MipmapGenerator] at hand.
+    Cpu,
+}
+
+/// How many levels a full mip chain for a texture this size has, down to
+/// and including 1x1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn mip_size(size: u32, level: u32) -> u32 {
+    (size >> level).max(1)
+}
+
+const MIPMAP_BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            multisampled: false,
+            dimension: wgpu::TextureViewDimension::D2,
+            component_type: wgpu::TextureComponentType::Float,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler { comparison: false },
+        count: None,
+    },
+];
+
+/// Formats that can be both rendered to and sampled with the linear
+/// filter the generator uses. 32 bit float formats can't be filtered.
+fn is_renderable(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat::*;
+    matches!(
+        format,
+        R8Unorm
+            | R16Float
+            | Rg8Unorm
+            | Rg16Float
+            | Rgba8Unorm
+            | Rgba8UnormSrgb
+            | Bgra8Unorm
+            | Bgra8UnormSrgb
+            | Rgb10a2Unorm
+            | Rgba16Float
+    )
+}
+
+/// Fills in the mip levels of textures by rendering each level from the
+/// one above it. The pipeline for a format is built the first time a
+/// texture of that format comes along, then reused.
+pub struct MipmapGenerator {
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("MipmapGenerator::sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("MipmapGenerator::layout"),
+            entries: MIPMAP_BIND_GROUP_LAYOUT_ENTRIES,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("MipmapGenerator::pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        Self {
+            sampler,
+            layout,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Whether [MipmapGenerator::generate] can handle a format. The
+    /// others, such as compressed formats, need their mips made ahead
+    /// of time.
+    pub fn supports(format: wgpu::TextureFormat) -> bool {
+        is_renderable(format)
+    }
+
+    fn create_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Result<()> {
+        if !self.pipelines.contains_key(&format) {
+            let pipeline = RenderPipelineBuilder::new()
+                .layout(&self.pipeline_layout)
+                .vertex_shader(wgpu::include_spirv!("shaders/blit.vert.spv"))
+                .fragment_shader(wgpu::include_spirv!("shaders/mipmap.frag.spv"))
+                .primitive_topology(wgpu::PrimitiveTopology::TriangleStrip)
+                .color_solid(format)
+                .build(device)
+                .with_context(|| {
+                    format!("Unable to create the mipmap pipeline for {:?}", format)
+                })?;
+            self.pipelines.insert(format, pipeline);
+        }
+        Ok(())
+    }
+
+    /// Renders every mip level after the first of every layer of
+    /// `texture`. The texture needs [wgpu::TextureUsage::OUTPUT_ATTACHMENT]
+    /// and [wgpu::TextureUsage::SAMPLED].
+    pub fn generate(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        desc: &wgpu::TextureDescriptor,
+    ) -> Result<()> {
+        if !Self::supports(desc.format) {
+            bail!("Mipmaps can't be rendered for {:?} textures", desc.format);
+        }
+        let usage = wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED;
+        if !desc.usage.contains(usage) {
+            bail!("Generating mipmaps needs {:?}, got {:?}", usage, desc.usage);
+        }
+        if desc.dimension != wgpu::TextureDimension::D2 {
+            bail!("Only 2D textures can have their mipmaps generated");
+        }
+
+        self.create_pipeline(device, desc.format)?;
+        let pipeline = &self.pipelines[&desc.format];
+        for layer in 0..desc.size.depth {
+            let views = (0..desc.mip_level_count)
+                .map(|mip| {
+                    texture.create_view(&wgpu::TextureViewDescriptor {
+                        label: Some("MipmapGenerator::view"),
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_mip_level: mip,
+                        level_count: NonZeroU32::new(1),
+                        base_array_layer: layer,
+                        array_layer_count: NonZeroU32::new(1),
+                        ..Default::default()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for target in 1..views.len() {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&views[target - 1]),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                        attachment: &views[target],
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..4, 0..1);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round().max(0.0).min(255.0) as u8
+}
+
+/// The mip levels after the first, made by averaging 2x2 blocks of
+/// the level above. When `srgb` is set the color channels are averaged
+/// in linear space, like the GPU would.
+pub fn generate_mipmaps_cpu(img: &image::RgbaImage, srgb: bool) -> Vec<image::RgbaImage> {
+    let decode = |c: u8| {
+        if srgb {
+            srgb_to_linear(c)
+        } else {
+            c as f32 / 255.0
+        }
+    };
+    let encode = |c: f32| {
+        if srgb {
+            linear_to_srgb(c)
+        } else {
+            (c * 255.0).round().max(0.0).min(255.0) as u8
+        }
+    };
+
+    let (width, height) = img.dimensions();
+    let mut levels: Vec<image::RgbaImage> = Vec::new();
+    for level in 1..mip_level_count(width, height) {
+        let source = levels.last().unwrap_or(img);
+        let (source_width, source_height) = source.dimensions();
+        let next =
+            image::RgbaImage::from_fn(mip_size(width, level), mip_size(height, level), |x, y| {
+                // Odd sizes lose their last row or column, and 1 texel
+                // wide levels reuse the same texel
+                let xs = [
+                    (x * 2).min(source_width - 1),
+                    (x * 2 + 1).min(source_width - 1),
+                ];
+                let ys = [
+                    (y * 2).min(source_height - 1),
+                    (y * 2 + 1).min(source_height - 1),
+                ];
+                let mut sum = [0.0; 4];
+                for &sy in &ys {
+                    for &sx in &xs {
+                        let texel = source.get_pixel(sx, sy);
+                        for (s, &c) in sum.iter_mut().zip(&texel.0[..3]) {
+                            *s += decode(c);
+                        }
+                        // Alpha is always linear
+                        sum[3] += texel[3] as f32 / 255.0;
+                    }
+                }
+                image::Rgba([
+                    encode(sum[0] / 4.0),
+                    encode(sum[1] / 4.0),
+                    encode(sum[2] / 4.0),
+                    (sum[3] / 4.0 * 255.0).round() as u8,
+                ])
+            });
+        levels.push(next);
+    }
+    levels
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reflect::*;
+
+    #[test]
+    fn chains_go_down_to_one_texel() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(300, 20), 9);
+        assert_eq!(mip_level_count(0, 0), 1);
+        assert_eq!(mip_size(300, 8), 1);
+    }
+
+    #[test]
+    fn unfilterable_formats_are_not_supported() {
+        assert!(MipmapGenerator::supports(wgpu::TextureFormat::Rgba16Float));
+        assert!(!MipmapGenerator::supports(wgpu::TextureFormat::Rgba32Float));
+        assert!(!MipmapGenerator::supports(wgpu::TextureFormat::R32Float));
+    }
+
+    #[test]
+    fn cpu_mipmaps_average_in_linear_space() {
+        let img = image::RgbaImage::from_fn(4, 2, |x, _| {
+            if x % 2 == 0 {
+                image::Rgba([255, 255, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        });
+
+        let srgb = generate_mipmaps_cpu(&img, true);
+        assert_eq!(srgb.len(), 2);
+        assert_eq!(srgb[0].dimensions(), (2, 1));
+        assert_eq!(srgb[1].dimensions(), (1, 1));
+        // Half as much light is 188 in sRGB, not 128
+        assert_eq!(srgb[0].get_pixel(0, 0).0, [188, 188, 188, 128]);
+
+        let linear = generate_mipmaps_cpu(&img, false);
+        assert_eq!(linear[0].get_pixel(0, 0).0, [128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn mipmap_shader_matches_layout() {
+        let vs = ShaderReflection::from_bytes(include_bytes!("shaders/blit.vert.spv")).unwrap();
+        let fs = ShaderReflection::from_bytes(include_bytes!("shaders/mipmap.frag.spv")).unwrap();
+        let layouts = reflect_bind_group_layouts(&[&vs, &fs]).unwrap();
+        assert_eq!(layouts[0].entries, MIPMAP_BIND_GROUP_LAYOUT_ENTRIES);
+    }
+}
@@ -1,8 +1,13 @@
 use anyhow::*;
+use cgmath::prelude::*;
+use cgmath::{Matrix3, Matrix4, Vector2, Vector3};
+use std::fmt;
 use std::ops::Range;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use wgpu::util::DeviceExt;
 
+use crate::sampler::{SamplerCache, SamplerConfig};
+use crate::tangent::{generate_tangents, TangentOptions};
 use crate::texture;
 
 pub trait Vertex {
@@ -60,10 +65,51 @@ impl Vertex for ModelVertex {
     }
 }
 
+/// The metallic-roughness parameters from the glTF spec. The factors
+/// are multiplied with the matching textures.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MaterialParameters {
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: [f32; 3],
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    /// Fragments with less alpha than this are discarded. `None` if
+    /// the material is opaque or blended.
+    pub alpha_cutoff: Option<f32>,
+    pub double_sided: bool,
+}
+
+impl Default for MaterialParameters {
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0; 4],
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            emissive_factor: [0.0; 3],
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            alpha_cutoff: None,
+            double_sided: false,
+        }
+    }
+}
+
+/// Color of the 1x1 texture used when a material has no diffuse map.
+pub const FALLBACK_DIFFUSE: [u8; 4] = [255, 255, 255, 255];
+/// Color of the 1x1 texture used when a material has no normal map. It
+/// encodes a normal pointing straight out of the surface.
+pub const FALLBACK_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
 pub struct Material<'a> {
     pub name: String,
     pub diffuse_texture: texture::Texture<'a>,
     pub normal_texture: texture::Texture<'a>,
+    /// Metalness in the blue channel and roughness in the green
+    /// channel. OBJ materials don't have one.
+    pub metallic_roughness_texture: Option<texture::Texture<'a>>,
+    pub parameters: MaterialParameters,
     pub bind_group: wgpu::BindGroup,
 }
 
@@ -102,11 +148,269 @@ impl<'a> Material<'a> {
             name: String::from(name),
             diffuse_texture,
             normal_texture,
+            metallic_roughness_texture: None,
+            parameters: MaterialParameters::default(),
             bind_group,
         }
     }
 }
 
+/// The layout of the uniform `pbr.frag` reads a [PbrMaterial]'s factors
+/// from. It's padded to a multiple of 16 bytes as std140 requires.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct PbrMaterialUniform {
+    base_color_factor: [f32; 4],
+    emissive_factor: [f32; 3],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    double_sided: u32,
+    _padding: [u32; 3],
+}
+
+unsafe impl bytemuck::Zeroable for PbrMaterialUniform {}
+unsafe impl bytemuck::Pod for PbrMaterialUniform {}
+
+impl From<&MaterialParameters> for PbrMaterialUniform {
+    fn from(p: &MaterialParameters) -> Self {
+        Self {
+            base_color_factor: p.base_color_factor,
+            emissive_factor: p.emissive_factor,
+            metallic_factor: p.metallic_factor,
+            roughness_factor: p.roughness_factor,
+            normal_scale: p.normal_scale,
+            occlusion_strength: p.occlusion_strength,
+            // No fragment has less than 0 alpha, so nothing is discarded
+            alpha_cutoff: p.alpha_cutoff.unwrap_or(0.0),
+            double_sided: p.double_sided as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+const fn pbr_texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            multisampled: false,
+            dimension: wgpu::TextureViewDimension::D2,
+            component_type: wgpu::TextureComponentType::Float,
+        },
+        count: None,
+    }
+}
+
+const fn pbr_sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler { comparison: false },
+        count: None,
+    }
+}
+
+const PBR_BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    pbr_texture_entry(0),
+    pbr_sampler_entry(1),
+    pbr_texture_entry(2),
+    pbr_sampler_entry(3),
+    pbr_texture_entry(4),
+    pbr_sampler_entry(5),
+    pbr_texture_entry(6),
+    pbr_sampler_entry(7),
+    pbr_texture_entry(8),
+    pbr_sampler_entry(9),
+    wgpu::BindGroupLayoutEntry {
+        binding: 10,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::UniformBuffer {
+            dynamic: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+/// The textures of a [PbrMaterial]. Each one is multiplied with the
+/// matching factor in [MaterialParameters].
+pub struct PbrTextures<'a> {
+    /// sRGB base color, with alpha in the alpha channel.
+    pub albedo: texture::Texture<'a>,
+    /// Roughness in the green channel and metalness in the blue
+    /// channel, like glTF.
+    pub metallic_roughness: texture::Texture<'a>,
+    pub normal: texture::Texture<'a>,
+    /// Ambient occlusion in the red channel.
+    pub occlusion: texture::Texture<'a>,
+    /// sRGB emitted light.
+    pub emissive: texture::Texture<'a>,
+}
+
+impl<'a> PbrTextures<'a> {
+    /// 1x1 textures that leave the factors unchanged.
+    pub fn fallback(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        name: &str,
+    ) -> Result<Self> {
+        let color = |kind: &str, color: [u8; 4], linear: bool| {
+            let label = format!("{} {} fallback", name, kind);
+            texture::Texture::from_color(device, queue, color, Some(&label), linear, samplers)
+        };
+        Ok(Self {
+            albedo: color("albedo", FALLBACK_DIFFUSE, false)?,
+            metallic_roughness: color("metallic roughness", FALLBACK_DIFFUSE, true)?,
+            normal: color("normal", FALLBACK_NORMAL, true)?,
+            occlusion: color("occlusion", FALLBACK_DIFFUSE, true)?,
+            emissive: color("emissive", FALLBACK_DIFFUSE, false)?,
+        })
+    }
+}
+
+/// A metallic-roughness material for the Cook-Torrance shaders from
+/// [PbrMaterial::vertex_shader] and [PbrMaterial::fragment_shader].
+///
+/// The shaders use the same bind groups as [Material]: the material is
+/// group 0, `framework::Uniforms` group 1 and `LightManager` group 2.
+pub struct PbrMaterial<'a> {
+    pub name: String,
+    pub textures: PbrTextures<'a>,
+    pub parameters: MaterialParameters,
+    pub uniform_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl<'a> PbrMaterial<'a> {
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        textures: PbrTextures<'a>,
+        parameters: MaterialParameters,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(name),
+            contents: bytemuck::cast_slice(&[PbrMaterialUniform::from(&parameters)]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let t = &textures;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&t.albedo.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&t.albedo.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&t.metallic_roughness.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&t.metallic_roughness.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&t.normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&t.normal.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&t.occlusion.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::Sampler(&t.occlusion.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(&t.emissive.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::Sampler(&t.emissive.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+                },
+            ],
+            label: Some(name),
+        });
+
+        Self {
+            name: String::from(name),
+            textures,
+            parameters,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    pub fn bind_group_layout_desc() -> wgpu::BindGroupLayoutDescriptor<'static> {
+        wgpu::BindGroupLayoutDescriptor {
+            label: Some("PbrMaterial"),
+            entries: PBR_BIND_GROUP_LAYOUT_ENTRIES,
+        }
+    }
+
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&Self::bind_group_layout_desc())
+    }
+
+    /// Expects [ModelVertex]es in world space.
+    pub fn vertex_shader() -> wgpu::ShaderModuleSource<'static> {
+        wgpu::include_spirv!("shaders/pbr.vert.spv")
+    }
+
+    pub fn fragment_shader() -> wgpu::ShaderModuleSource<'static> {
+        wgpu::include_spirv!("shaders/pbr.frag.spv")
+    }
+
+    /// The fragment shader, but with the lights dimmed by the shadow
+    /// maps bound to group 3. See `ShadowMaps`.
+    pub fn fragment_shader_with_shadows() -> wgpu::ShaderModuleSource<'static> {
+        wgpu::include_spirv!("shaders/pbr.shadows.frag.spv")
+    }
+
+    /// The fragment shader, but with ambient light from the
+    /// [EnvironmentMap](crate::EnvironmentMap) bound to group 3 instead
+    /// of a constant.
+    pub fn fragment_shader_with_environment() -> wgpu::ShaderModuleSource<'static> {
+        wgpu::include_spirv!("shaders/pbr.environment.frag.spv")
+    }
+
+    /// Both the shadow maps in group 3 and the environment map in
+    /// group 4. wgpu only guarantees 4 bind groups, so set
+    /// [RunConfig::limits](crate::RunConfig) to allow 5.
+    pub fn fragment_shader_with_shadows_and_environment() -> wgpu::ShaderModuleSource<'static> {
+        wgpu::include_spirv!("shaders/pbr.shadows_environment.frag.spv")
+    }
+
+    /// Uploads new factors. The textures stay the same.
+    pub fn set_parameters(&mut self, queue: &wgpu::Queue, parameters: MaterialParameters) {
+        self.parameters = parameters;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[PbrMaterialUniform::from(&parameters)]),
+        );
+    }
+}
+
 pub struct Mesh {
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
@@ -115,32 +419,233 @@ pub struct Mesh {
     pub material: usize,
 }
 
+#[derive(Debug, Clone)]
+pub struct ModelLoadOptions {
+    /// Calculate a tangent and bitangent for every vertex, which
+    /// normal mapping needs. When this is `None` they are left zeroed.
+    pub tangents: Option<TangentOptions>,
+    /// How the model's textures are sampled.
+    pub sampler: SamplerConfig,
+}
+
+impl Default for ModelLoadOptions {
+    fn default() -> Self {
+        Self {
+            tangents: Some(TangentOptions::default()),
+            sampler: SamplerConfig::default(),
+        }
+    }
+}
+
+/// What a model's textures are created with.
+struct TextureLoader<'a> {
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+    samplers: &'a SamplerCache,
+    sampler: SamplerConfig,
+}
+
+impl<'a> TextureLoader<'a> {
+    fn load<'b>(&self, path: &Path, is_normal_map: bool) -> Result<texture::Texture<'b>> {
+        texture::Texture::load(
+            self.device,
+            self.queue,
+            path,
+            is_normal_map,
+            self.samplers,
+            &self.sampler,
+        )
+    }
+
+    fn image<'b>(
+        &self,
+        img: &image::DynamicImage,
+        label: &str,
+        linear: bool,
+    ) -> Result<texture::Texture<'b>> {
+        texture::Texture::from_image(
+            self.device,
+            self.queue,
+            img,
+            Some(label),
+            linear,
+            self.samplers,
+            &self.sampler,
+        )
+    }
+
+    fn color<'b>(&self, color: [u8; 4], label: &str, linear: bool) -> Result<texture::Texture<'b>> {
+        texture::Texture::from_color(
+            self.device,
+            self.queue,
+            color,
+            Some(label),
+            linear,
+            self.samplers,
+        )
+    }
+}
+
+impl Mesh {
+    fn new(
+        device: &wgpu::Device,
+        name: String,
+        vertices: &[ModelVertex],
+        indices: &[u32],
+        material: usize,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", name)),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsage::INDEX,
+        });
+
+        Self {
+            name,
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            material,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureKind {
+    Diffuse,
+    Normal,
+    MetallicRoughness,
+    Occlusion,
+    Emissive,
+}
+
+impl TextureKind {
+    /// The color of the 1x1 texture used in place of a missing one.
+    fn fallback(self) -> [u8; 4] {
+        match self {
+            TextureKind::Normal => FALLBACK_NORMAL,
+            _ => FALLBACK_DIFFUSE,
+        }
+    }
+
+    /// Whether the texture holds data rather than colors, so it
+    /// shouldn't be treated as sRGB.
+    fn is_linear(self) -> bool {
+        !matches!(self, TextureKind::Diffuse | TextureKind::Emissive)
+    }
+}
+
+/// Something that was wrong with a model, but that loading worked
+/// around instead of failing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelWarning {
+    /// The material's texture wasn't specified or couldn't be loaded,
+    /// so a 1x1 fallback is used instead.
+    MissingTexture {
+        material: String,
+        kind: TextureKind,
+        path: Option<PathBuf>,
+        reason: String,
+    },
+    /// The mesh has no normals, so they were computed from its faces.
+    MissingNormals { mesh: String },
+    /// The mesh has no texture coordinates, so they were set to zero.
+    MissingTexCoords { mesh: String },
+    /// The mesh's material doesn't exist, so a default one is used.
+    MissingMaterial { mesh: String },
+}
+
+impl fmt::Display for ModelWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelWarning::MissingTexture {
+                material,
+                kind,
+                path: Some(path),
+                reason,
+            } => write!(
+                f,
+                "Material {:?}: unable to load {:?} texture {}: {}",
+                material,
+                kind,
+                path.display(),
+                reason
+            ),
+            ModelWarning::MissingTexture {
+                material,
+                kind,
+                path: None,
+                reason,
+            } => write!(f, "Material {:?}: {:?} texture {}", material, kind, reason),
+            ModelWarning::MissingNormals { mesh } => {
+                write!(
+                    f,
+                    "Mesh {:?} has no normals, computed them from faces",
+                    mesh
+                )
+            }
+            ModelWarning::MissingTexCoords { mesh } => {
+                write!(f, "Mesh {:?} has no texture coordinates", mesh)
+            }
+            ModelWarning::MissingMaterial { mesh } => {
+                write!(f, "Mesh {:?} has no material, using a default one", mesh)
+            }
+        }
+    }
+}
+
 pub struct Model<'a> {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material<'a>>,
+    /// Problems that were worked around while loading. Each one has
+    /// already been logged.
+    pub warnings: Vec<ModelWarning>,
 }
 
 impl<'a> Model<'a> {
     pub fn load<P: AsRef<Path>>(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        samplers: &SamplerCache,
         layout: &wgpu::BindGroupLayout,
         path: P,
+        options: &ModelLoadOptions,
     ) -> Result<Self> {
+        let loader = TextureLoader {
+            device,
+            queue,
+            samplers,
+            sampler: options.sampler,
+        };
         let (obj_models, obj_materials) = tobj::load_obj(path.as_ref(), true)?;
 
         // We're assuming that the texture files are stored with the obj file
         let containing_folder = path.as_ref().parent().context("Directory has no parent")?;
 
+        let mut warnings = Vec::new();
         let mut materials = Vec::new();
         for mat in obj_materials {
-            let diffuse_path = mat.diffuse_texture;
-            let diffuse_texture =
-                texture::Texture::load(device, queue, containing_folder.join(diffuse_path), false)?;
-
-            let normal_path = mat.normal_texture;
-            let normal_texture =
-                texture::Texture::load(device, queue, containing_folder.join(normal_path), true)?;
+            let diffuse_texture = load_texture_or_fallback(
+                &loader,
+                containing_folder,
+                &mat.name,
+                &mat.diffuse_texture,
+                TextureKind::Diffuse,
+                &mut warnings,
+            )?;
+            let normal_texture = load_texture_or_fallback(
+                &loader,
+                containing_folder,
+                &mat.name,
+                &mat.normal_texture,
+                TextureKind::Normal,
+                &mut warnings,
+            )?;
 
             materials.push(Material::new(
                 device,
@@ -150,100 +655,626 @@ impl<'a> Model<'a> {
                 layout,
             ));
         }
+        let default_material = materials.len();
+        let mut uses_default_material = false;
 
         let mut meshes = Vec::new();
         for m in obj_models {
-            let mut vertices = Vec::new();
-            for i in 0..m.mesh.positions.len() / 3 {
-                vertices.push(ModelVertex {
-                    position: [
-                        m.mesh.positions[i * 3],
-                        m.mesh.positions[i * 3 + 1],
-                        m.mesh.positions[i * 3 + 2],
-                    ]
-                    .into(),
-                    tex_coords: [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]].into(),
-                    normal: [
-                        m.mesh.normals[i * 3],
-                        m.mesh.normals[i * 3 + 1],
-                        m.mesh.normals[i * 3 + 2],
-                    ]
-                    .into(),
-                    // We'll calculate these later
-                    tangent: [0.0; 3].into(),
-                    bitangent: [0.0; 3].into(),
+            let indices = &m.mesh.indices;
+            let positions = m
+                .mesh
+                .positions
+                .chunks_exact(3)
+                .map(|p| Vector3::new(p[0], p[1], p[2]))
+                .collect::<Vec<_>>();
+
+            let normals = if m.mesh.normals.len() == positions.len() * 3 {
+                m.mesh
+                    .normals
+                    .chunks_exact(3)
+                    .map(|n| Vector3::new(n[0], n[1], n[2]))
+                    .collect()
+            } else {
+                warnings.push(ModelWarning::MissingNormals {
+                    mesh: m.name.clone(),
                 });
+                compute_normals(&positions, indices)
+            };
+
+            let tex_coords = if m.mesh.texcoords.len() == positions.len() * 2 {
+                m.mesh
+                    .texcoords
+                    .chunks_exact(2)
+                    .map(|t| Vector2::new(t[0], t[1]))
+                    .collect()
+            } else {
+                warnings.push(ModelWarning::MissingTexCoords {
+                    mesh: m.name.clone(),
+                });
+                vec![Vector2::zero(); positions.len()]
+            };
+
+            let mut vertices = positions
+                .iter()
+                .zip(normals)
+                .zip(tex_coords)
+                .map(|((&position, normal), tex_coords)| ModelVertex {
+                    position,
+                    tex_coords,
+                    normal,
+                    // We'll calculate these later
+                    tangent: Vector3::zero(),
+                    bitangent: Vector3::zero(),
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(tangent_options) = &options.tangents {
+                apply_tangents(&mut vertices, indices, tangent_options);
             }
 
-            let indices = &m.mesh.indices;
+            let material = match m.mesh.material_id {
+                Some(id) if id < default_material => id,
+                _ => {
+                    warnings.push(ModelWarning::MissingMaterial {
+                        mesh: m.name.clone(),
+                    });
+                    uses_default_material = true;
+                    default_material
+                }
+            };
+            meshes.push(Mesh::new(device, m.name, &vertices, indices, material));
+        }
 
-            // Calculate tangents and bitangets. We're going to
-            // use the triangles, so we need to loop through the
-            // indices in chunks of 3
-            for c in indices.chunks(3) {
-                let v0 = vertices[c[0] as usize];
-                let v1 = vertices[c[1] as usize];
-                let v2 = vertices[c[2] as usize];
-
-                let pos0 = v0.position;
-                let pos1 = v1.position;
-                let pos2 = v2.position;
-
-                let uv0 = v0.tex_coords;
-                let uv1 = v1.tex_coords;
-                let uv2 = v2.tex_coords;
-
-                // Calculate the edges of the triangle
-                let delta_pos1 = pos1 - pos0;
-                let delta_pos2 = pos2 - pos0;
-
-                // This will give us a direction to calculate the
-                // tangent and bitangent
-                let delta_uv1 = uv1 - uv0;
-                let delta_uv2 = uv2 - uv0;
-
-                // Solving the following system of equations will
-                // give us the tangent and bitangent.
-                //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
-                //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
-                // Luckily, the place I found this equation provided
-                // the solution!
-                let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-                let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
-                let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r;
-
-                // We'll use the same tangent/bitangent for each vertex in the triangle
-                vertices[c[0] as usize].tangent = tangent;
-                vertices[c[1] as usize].tangent = tangent;
-                vertices[c[2] as usize].tangent = tangent;
-
-                vertices[c[0] as usize].bitangent = bitangent;
-                vertices[c[1] as usize].bitangent = bitangent;
-                vertices[c[2] as usize].bitangent = bitangent;
+        if uses_default_material {
+            materials.push(default_material_for(&loader, layout)?);
+        }
+
+        for warning in &warnings {
+            log::warn!("{}: {}", path.as_ref().display(), warning);
+        }
+
+        Ok(Self {
+            meshes,
+            materials,
+            warnings,
+        })
+    }
+
+    /// Loads a `.gltf` or `.glb` file. Buffers and images can either be
+    /// embedded or stored next to the file.
+    ///
+    /// The nodes of the default scene are flattened, so every primitive
+    /// becomes a [Mesh] with its node's transform baked into the
+    /// vertices. Textures a material doesn't have are replaced with 1x1
+    /// textures that leave the material's factors unchanged.
+    pub fn load_gltf<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+        options: &ModelLoadOptions,
+    ) -> Result<Self> {
+        let loader = TextureLoader {
+            device,
+            queue,
+            samplers,
+            sampler: options.sampler,
+        };
+        let mut scene = load_gltf_scene(device, path.as_ref(), options)?;
+
+        let mut materials = Vec::new();
+        for (i, material) in scene.document.materials().enumerate() {
+            let name = gltf_material_name(&material, i);
+            materials.push(load_gltf_material(
+                &loader,
+                layout,
+                &name,
+                &material,
+                &scene.images,
+                &mut scene.warnings,
+            )?);
+        }
+        if scene.uses_default_material {
+            materials.push(default_material_for(&loader, layout)?);
+        }
+
+        for warning in &scene.warnings {
+            log::warn!("{}: {}", path.as_ref().display(), warning);
+        }
+
+        Ok(Self {
+            meshes: scene.meshes,
+            materials,
+            warnings: scene.warnings,
+        })
+    }
+}
+
+/// A glTF model with every material turned into a [PbrMaterial].
+pub struct PbrModel<'a> {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<PbrMaterial<'a>>,
+    pub warnings: Vec<ModelWarning>,
+}
+
+impl<'a> PbrModel<'a> {
+    /// Loads a `.gltf` or `.glb` file the same way as
+    /// [Model::load_gltf], but keeps the occlusion and emissive maps.
+    /// `layout` should come from [PbrMaterial::create_bind_group_layout].
+    pub fn load_gltf<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+        options: &ModelLoadOptions,
+    ) -> Result<Self> {
+        let loader = TextureLoader {
+            device,
+            queue,
+            samplers,
+            sampler: options.sampler,
+        };
+        let GltfScene {
+            document,
+            images,
+            meshes,
+            mut warnings,
+            uses_default_material,
+        } = load_gltf_scene(device, path.as_ref(), options)?;
+
+        let mut materials = Vec::new();
+        for (i, material) in document.materials().enumerate() {
+            let name = gltf_material_name(&material, i);
+            let pbr = material.pbr_metallic_roughness();
+            let mut load = |kind, texture| {
+                load_gltf_texture(&loader, &name, kind, texture, &images, &mut warnings)
+            };
+            let textures = PbrTextures {
+                albedo: load(
+                    TextureKind::Diffuse,
+                    pbr.base_color_texture().map(|info| info.texture()),
+                )?,
+                metallic_roughness: load(
+                    TextureKind::MetallicRoughness,
+                    pbr.metallic_roughness_texture().map(|info| info.texture()),
+                )?,
+                normal: load(
+                    TextureKind::Normal,
+                    material.normal_texture().map(|n| n.texture()),
+                )?,
+                occlusion: load(
+                    TextureKind::Occlusion,
+                    material.occlusion_texture().map(|o| o.texture()),
+                )?,
+                emissive: load(
+                    TextureKind::Emissive,
+                    material.emissive_texture().map(|info| info.texture()),
+                )?,
+            };
+            materials.push(PbrMaterial::new(
+                device,
+                &name,
+                textures,
+                gltf_material_parameters(&material),
+                layout,
+            ));
+        }
+        if uses_default_material {
+            let textures = PbrTextures::fallback(device, queue, samplers, "default")?;
+            materials.push(PbrMaterial::new(
+                device,
+                "default",
+                textures,
+                MaterialParameters::default(),
+                layout,
+            ));
+        }
+
+        for warning in &warnings {
+            log::warn!("{}: {}", path.as_ref().display(), warning);
+        }
+
+        Ok(Self {
+            meshes,
+            materials,
+            warnings,
+        })
+    }
+}
+
+/// The parts of a glTF file that don't depend on the material type.
+struct GltfScene {
+    document: gltf::Document,
+    images: Vec<gltf::image::Data>,
+    meshes: Vec<Mesh>,
+    /// Problems with the meshes. They haven't been logged yet, so the
+    /// materials' problems can be added first.
+    warnings: Vec<ModelWarning>,
+    /// Whether a mesh refers to the default material, which comes
+    /// after the file's own materials.
+    uses_default_material: bool,
+}
+
+fn load_gltf_scene(
+    device: &wgpu::Device,
+    path: &Path,
+    options: &ModelLoadOptions,
+) -> Result<GltfScene> {
+    let (document, buffers, images) =
+        gltf::import(path).with_context(|| format!("Unable to load {}", path.display()))?;
+
+    // Primitives without a material use the default one from the spec
+    let default_material = document.materials().len();
+    let mut uses_default_material = false;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .context("glTF file has no scenes")?;
+    let mut nodes = scene
+        .nodes()
+        .map(|node| (node, Matrix4::identity()))
+        .collect::<Vec<_>>();
+
+    let mut warnings = Vec::new();
+    let mut meshes = Vec::new();
+    while let Some((node, parent_transform)) = nodes.pop() {
+        let transform = parent_transform * Matrix4::from(node.transform().matrix());
+        nodes.extend(node.children().map(|child| (child, transform)));
+
+        let mesh = match node.mesh() {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        for primitive in mesh.primitives() {
+            let name = format!(
+                "{} {}",
+                mesh.name()
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("mesh{}", mesh.index())),
+                primitive.index()
+            );
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!("Skipping {}: only triangle lists are supported", name);
+                continue;
             }
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", path.as_ref())),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsage::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", path.as_ref())),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsage::INDEX,
+            let (vertices, indices) = read_gltf_primitive(
+                &name,
+                &primitive,
+                &buffers,
+                transform,
+                options,
+                &mut warnings,
+            )
+            .with_context(|| format!("Unable to read {}", name))?;
+            let material = match primitive.material().index() {
+                Some(index) => index,
+                None => {
+                    uses_default_material = true;
+                    default_material
+                }
+            };
+            meshes.push(Mesh::new(device, name, &vertices, &indices, material));
+        }
+    }
+
+    Ok(GltfScene {
+        document,
+        images,
+        meshes,
+        warnings,
+        uses_default_material,
+    })
+}
+
+/// Loads `file` from `folder`, or a 1x1 fallback if the material
+/// doesn't specify one or it can't be loaded.
+fn load_texture_or_fallback<'a>(
+    loader: &TextureLoader,
+    folder: &Path,
+    material: &str,
+    file: &str,
+    kind: TextureKind,
+    warnings: &mut Vec<ModelWarning>,
+) -> Result<texture::Texture<'a>> {
+    let is_normal_map = kind == TextureKind::Normal;
+    if file.is_empty() {
+        warnings.push(ModelWarning::MissingTexture {
+            material: material.to_string(),
+            kind,
+            path: None,
+            reason: "isn't specified".to_string(),
+        });
+    } else {
+        let path = folder.join(file);
+        match loader.load(&path, is_normal_map) {
+            Ok(texture) => return Ok(texture),
+            Err(e) => warnings.push(ModelWarning::MissingTexture {
+                material: material.to_string(),
+                kind,
+                path: Some(path),
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    let label = format!("{} {:?} fallback", material, kind);
+    loader.color(kind.fallback(), &label, is_normal_map)
+}
+
+/// A material with fallback textures, for meshes that don't have one.
+fn default_material_for<'a>(
+    loader: &TextureLoader,
+    layout: &wgpu::BindGroupLayout,
+) -> Result<Material<'a>> {
+    let diffuse_texture = loader.color(FALLBACK_DIFFUSE, "default diffuse", false)?;
+    let normal_texture = loader.color(FALLBACK_NORMAL, "default normal", true)?;
+    Ok(Material::new(
+        loader.device,
+        "default",
+        diffuse_texture,
+        normal_texture,
+        layout,
+    ))
+}
+
+fn apply_tangents(vertices: &mut [ModelVertex], indices: &[u32], options: &TangentOptions) {
+    let positions = vertices.iter().map(|v| v.position).collect::<Vec<_>>();
+    let normals = vertices.iter().map(|v| v.normal).collect::<Vec<_>>();
+    let tex_coords = vertices.iter().map(|v| v.tex_coords).collect::<Vec<_>>();
+    let tangents = generate_tangents(&positions, &normals, &tex_coords, indices, options);
+    for (v, t) in vertices.iter_mut().zip(tangents) {
+        v.tangent = t.tangent;
+        v.bitangent = t.bitangent;
+    }
+}
+
+/// Smooth normals, made by averaging the normals of the triangles
+/// around each vertex weighted by their area.
+fn compute_normals(positions: &[Vector3<f32>], indices: &[u32]) -> Vec<Vector3<f32>> {
+    let mut normals = vec![Vector3::zero(); positions.len()];
+    for c in indices.chunks_exact(3) {
+        let i = [c[0] as usize, c[1] as usize, c[2] as usize];
+        if i.iter().any(|&v| v >= positions.len()) {
+            continue;
+        }
+        // The length of the cross product is twice the triangle's
+        // area, which gives us the weighting for free
+        let normal = (positions[i[1]] - positions[i[0]]).cross(positions[i[2]] - positions[i[0]]);
+        for &v in &i {
+            normals[v] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|n| {
+            if n.magnitude2() > 0.0 {
+                n.normalize()
+            } else {
+                Vector3::unit_z()
+            }
+        })
+        .collect()
+}
+
+fn read_gltf_primitive(
+    name: &str,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    transform: Matrix4<f32>,
+    options: &ModelLoadOptions,
+    warnings: &mut Vec<ModelWarning>,
+) -> Result<(Vec<ModelVertex>, Vec<u32>)> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()][..]));
+
+    let positions = reader
+        .read_positions()
+        .context("Primitive has no positions")?
+        .map(|p| (transform * Vector3::from(p).extend(1.0)).truncate())
+        .collect::<Vec<_>>();
+    let vertex_count = positions.len();
+
+    let mut indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+        None => (0..vertex_count as u32).collect(),
+    };
+    // Mirroring transforms turn the triangles inside out
+    let upper_left = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    if upper_left.determinant() < 0.0 {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    // Normals need the inverse transpose so non-uniform scaling
+    // doesn't skew them
+    let normal_matrix = upper_left
+        .invert()
+        .map(|m| m.transpose())
+        .unwrap_or(upper_left);
+    let normals = match reader.read_normals() {
+        Some(normals) => normals
+            .map(|n| (normal_matrix * Vector3::from(n)).normalize())
+            .collect(),
+        None => {
+            warnings.push(ModelWarning::MissingNormals {
+                mesh: name.to_string(),
             });
+            compute_normals(&positions, &indices)
+        }
+    };
 
-            meshes.push(Mesh {
-                name: m.name,
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
+    let tex_coords = match reader.read_tex_coords(0) {
+        Some(tex_coords) => tex_coords.into_f32().map(Vector2::from).collect(),
+        None => {
+            warnings.push(ModelWarning::MissingTexCoords {
+                mesh: name.to_string(),
             });
+            vec![Vector2::zero(); vertex_count]
         }
+    };
+
+    let mut vertices = (0..vertex_count)
+        .map(|i| ModelVertex {
+            position: positions[i],
+            tex_coords: tex_coords.get(i).copied().unwrap_or_else(Vector2::zero),
+            normal: normals.get(i).copied().unwrap_or_else(Vector3::unit_z),
+            tangent: Vector3::zero(),
+            bitangent: Vector3::zero(),
+        })
+        .collect::<Vec<_>>();
 
-        Ok(Self { meshes, materials })
+    match reader.read_tangents() {
+        Some(tangents) => {
+            for (v, t) in vertices.iter_mut().zip(tangents) {
+                let tangent = (upper_left * Vector3::new(t[0], t[1], t[2])).normalize();
+                v.tangent = tangent;
+                v.bitangent = v.normal.cross(tangent) * t[3];
+            }
+        }
+        None => {
+            if let Some(tangent_options) = &options.tangents {
+                apply_tangents(&mut vertices, &indices, tangent_options);
+            }
+        }
     }
+
+    Ok((vertices, indices))
+}
+
+fn gltf_material_name(material: &gltf::Material, index: usize) -> String {
+    material
+        .name()
+        .map(String::from)
+        .unwrap_or_else(|| format!("material{}", index))
+}
+
+/// Uploads `texture`'s image, or a 1x1 fallback texture if the material
+/// doesn't have one or its image can't be used. Like with OBJ files,
+/// a missing diffuse or normal texture is reported. The other maps are
+/// optional, so only the ones that fail to load are.
+fn load_gltf_texture<'a>(
+    loader: &TextureLoader,
+    material: &str,
+    kind: TextureKind,
+    texture: Option<gltf::texture::Texture>,
+    images: &[gltf::image::Data],
+    warnings: &mut Vec<ModelWarning>,
+) -> Result<texture::Texture<'a>> {
+    let label = format!("{} {:?}", material, kind);
+    let reason = match texture {
+        Some(texture) => {
+            let index = texture.source().index();
+            let img = images
+                .get(index)
+                .with_context(|| format!("image {} doesn't exist", index))
+                .and_then(gltf_image);
+            match img {
+                Ok(img) => return loader.image(&img, &label, kind.is_linear()),
+                Err(e) => Some(e.to_string()),
+            }
+        }
+        None if kind == TextureKind::Diffuse || kind == TextureKind::Normal => {
+            Some("isn't specified".to_string())
+        }
+        None => None,
+    };
+    if let Some(reason) = reason {
+        warnings.push(ModelWarning::MissingTexture {
+            material: material.to_string(),
+            kind,
+            path: None,
+            reason,
+        });
+    }
+    loader.color(kind.fallback(), &label, kind.is_linear())
+}
+
+fn gltf_material_parameters(material: &gltf::Material) -> MaterialParameters {
+    let pbr = material.pbr_metallic_roughness();
+    MaterialParameters {
+        base_color_factor: pbr.base_color_factor(),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        emissive_factor: material.emissive_factor(),
+        normal_scale: material.normal_texture().map(|n| n.scale()).unwrap_or(1.0),
+        occlusion_strength: material
+            .occlusion_texture()
+            .map(|o| o.strength())
+            .unwrap_or(1.0),
+        alpha_cutoff: match material.alpha_mode() {
+            gltf::material::AlphaMode::Mask => Some(material.alpha_cutoff()),
+            _ => None,
+        },
+        double_sided: material.double_sided(),
+    }
+}
+
+fn load_gltf_material<'a>(
+    loader: &TextureLoader,
+    layout: &wgpu::BindGroupLayout,
+    name: &str,
+    material: &gltf::Material,
+    images: &[gltf::image::Data],
+    warnings: &mut Vec<ModelWarning>,
+) -> Result<Material<'a>> {
+    let pbr = material.pbr_metallic_roughness();
+    let mut load = |kind, texture| load_gltf_texture(loader, name, kind, texture, images, warnings);
+
+    let diffuse_texture = load(
+        TextureKind::Diffuse,
+        pbr.base_color_texture().map(|info| info.texture()),
+    )?;
+    let normal_texture = load(
+        TextureKind::Normal,
+        material.normal_texture().map(|n| n.texture()),
+    )?;
+    // White leaves the metallic and roughness factors as they are
+    let metallic_roughness_texture = load(
+        TextureKind::MetallicRoughness,
+        pbr.metallic_roughness_texture().map(|info| info.texture()),
+    )?;
+
+    let mut result = Material::new(loader.device, name, diffuse_texture, normal_texture, layout);
+    result.metallic_roughness_texture = Some(metallic_roughness_texture);
+    result.parameters = gltf_material_parameters(material);
+    Ok(result)
+}
+
+/// Converts image data decoded by the gltf crate back into an image
+/// the texture code can upload.
+fn gltf_image(data: &gltf::image::Data) -> Result<image::DynamicImage> {
+    use gltf::image::Format;
+    use image::{DynamicImage, ImageBuffer};
+
+    let (w, h) = (data.width, data.height);
+    let pixels = data.pixels.clone();
+    let wide = || {
+        data.pixels
+            .chunks_exact(2)
+            .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>()
+    };
+    let img = match data.format {
+        Format::R8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageLuma8),
+        Format::R8G8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageLumaA8),
+        Format::R8G8B8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageRgb8),
+        Format::R8G8B8A8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageRgba8),
+        Format::B8G8R8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageBgr8),
+        Format::B8G8R8A8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageBgra8),
+        Format::R16 => ImageBuffer::from_raw(w, h, wide()).map(DynamicImage::ImageLuma16),
+        Format::R16G16 => ImageBuffer::from_raw(w, h, wide()).map(DynamicImage::ImageLumaA16),
+        Format::R16G16B16 => ImageBuffer::from_raw(w, h, wide()).map(DynamicImage::ImageRgb16),
+        Format::R16G16B16A16 => ImageBuffer::from_raw(w, h, wide()).map(DynamicImage::ImageRgba16),
+    };
+    img.context("Image data doesn't match its dimensions")
 }
 
 pub trait DrawModel<'a, 'b>
@@ -355,6 +1386,94 @@ where
     }
 }
 
+pub trait DrawPbrModel<'a, 'b>
+where
+    'b: 'a,
+{
+    fn draw_pbr_mesh(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b PbrMaterial,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    );
+    fn draw_pbr_mesh_instanced(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b PbrMaterial,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    );
+
+    fn draw_pbr_model(
+        &mut self,
+        model: &'b PbrModel,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    );
+    fn draw_pbr_model_instanced(
+        &mut self,
+        model: &'b PbrModel,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    );
+}
+
+impl<'a, 'b> DrawPbrModel<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_pbr_mesh(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b PbrMaterial,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    ) {
+        self.draw_pbr_mesh_instanced(mesh, material, 0..1, uniforms, light);
+    }
+
+    fn draw_pbr_mesh_instanced(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b PbrMaterial,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..));
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_bind_group(1, &uniforms, &[]);
+        self.set_bind_group(2, &light, &[]);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+
+    fn draw_pbr_model(
+        &mut self,
+        model: &'b PbrModel,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    ) {
+        self.draw_pbr_model_instanced(model, 0..1, uniforms, light);
+    }
+
+    fn draw_pbr_model_instanced(
+        &mut self,
+        model: &'b PbrModel,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    ) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            self.draw_pbr_mesh_instanced(mesh, material, instances.clone(), uniforms, light);
+        }
+    }
+}
+
 pub trait DrawLight<'a, 'b>
 where
     'b: 'a,
@@ -436,3 +1555,66 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reflect::*;
+
+    #[test]
+    fn computed_normals_face_outwards() {
+        let positions = vec![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        ];
+        let normals = compute_normals(&positions, &[0, 1, 2, 0, 2, 3]);
+        for n in normals {
+            assert!((n - Vector3::unit_z()).magnitude() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn computed_normals_are_averaged_across_faces() {
+        // Two faces meeting at a right angle along the X axis
+        let positions = vec![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        ];
+        let normals = compute_normals(&positions, &[0, 1, 2, 0, 1, 3]);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((normals[0] - Vector3::new(0.0, half, half)).magnitude() < 1e-6);
+        assert!((normals[2] - Vector3::unit_z()).magnitude() < 1e-6);
+        assert!((normals[3] - Vector3::unit_y()).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn pbr_shaders_match_layout_and_vertices() {
+        let vs = ShaderReflection::from_bytes(include_bytes!("shaders/pbr.vert.spv")).unwrap();
+        let fs = ShaderReflection::from_bytes(include_bytes!("shaders/pbr.frag.spv")).unwrap();
+        validate_vertex_inputs(&vs.inputs, &[ModelVertex::desc()]).unwrap();
+
+        let layouts = reflect_bind_group_layouts(&[&vs, &fs]).unwrap();
+        assert_eq!(layouts.len(), 3);
+        assert_eq!(layouts[0].entries, PBR_BIND_GROUP_LAYOUT_ENTRIES);
+        assert_eq!(
+            layouts[2].entries[0].ty,
+            wgpu::BindingType::StorageBuffer {
+                dynamic: false,
+                min_binding_size: None,
+                readonly: true,
+            }
+        );
+        assert_eq!(std::mem::size_of::<PbrMaterialUniform>() % 16, 0);
+    }
+
+    #[test]
+    fn unused_vertices_and_bad_indices_dont_panic() {
+        let positions = vec![Vector3::new(0.0, 0.0, 0.0); 2];
+        let normals = compute_normals(&positions, &[0, 1, 5]);
+        assert_eq!(normals, vec![Vector3::unit_z(); 2]);
+    }
+}
@@ -1,10 +1,22 @@
 use crate::model::Vertex;
+use crate::reflect::*;
 use anyhow::*;
+#[cfg(feature = "hot-reload")]
+use std::fs::metadata;
+#[cfg(feature = "hot-reload")]
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(feature = "hot-reload")]
+use std::time::SystemTime;
 
 pub struct RenderPipelineBuilder<'a> {
     layout: Option<&'a wgpu::PipelineLayout>,
     vertex_shader: Option<wgpu::ShaderModuleSource<'a>>,
     fragment_shader: Option<wgpu::ShaderModuleSource<'a>>,
+    vertex_shader_path: Option<PathBuf>,
+    fragment_shader_path: Option<PathBuf>,
+    vertex_entry_point: &'a str,
+    fragment_entry_point: &'a str,
     front_face: wgpu::FrontFace,
     cull_mode: wgpu::CullMode,
     depth_bias: i32,
@@ -26,6 +38,10 @@ impl<'a> RenderPipelineBuilder<'a> {
             layout: None,
             vertex_shader: None,
             fragment_shader: None,
+            vertex_shader_path: None,
+            fragment_shader_path: None,
+            vertex_entry_point: "main",
+            fragment_entry_point: "main",
             front_face: wgpu::FrontFace::Ccw,
             cull_mode: wgpu::CullMode::None,
             depth_bias: 0,
@@ -57,6 +73,34 @@ impl<'a> RenderPipelineBuilder<'a> {
         self
     }
 
+    /// Compiles the GLSL file at `path` with shaderc when the pipeline
+    /// is built, instead of using precompiled SPIR-V.
+    #[cfg(feature = "hot-reload")]
+    pub fn vertex_shader_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.vertex_shader_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Compiles the GLSL file at `path` with shaderc when the pipeline
+    /// is built, instead of using precompiled SPIR-V.
+    #[cfg(feature = "hot-reload")]
+    pub fn fragment_shader_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.fragment_shader_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The function the vertex stage starts in. Defaults to `main`.
+    pub fn vertex_entry_point(&mut self, name: &'a str) -> &mut Self {
+        self.vertex_entry_point = name;
+        self
+    }
+
+    /// The function the fragment stage starts in. Defaults to `main`.
+    pub fn fragment_entry_point(&mut self, name: &'a str) -> &mut Self {
+        self.fragment_entry_point = name;
+        self
+    }
+
     #[allow(dead_code)]
     pub fn front_face(&mut self, ff: wgpu::FrontFace) -> &mut Self {
         self.front_face = ff;
@@ -167,12 +211,74 @@ impl<'a> RenderPipelineBuilder<'a> {
         self
     }
 
+    /// Derives the bind group layouts from the `layout(set=..., binding=...)`
+    /// declarations of the shaders supplied so far, ie. to create the
+    /// pipeline layout with. Shaders supplied as WGSL are skipped.
+    pub fn bind_group_layouts(&self) -> Result<Vec<ReflectedBindGroupLayout>> {
+        let vs_spv = compile_path(&self.vertex_shader_path, &mut Vec::new())?;
+        let fs_spv = compile_path(&self.fragment_shader_path, &mut Vec::new())?;
+        let shaders = [
+            reflect_shader(vs_spv.as_deref(), self.vertex_shader.as_ref())?,
+            reflect_shader(fs_spv.as_deref(), self.fragment_shader.as_ref())?,
+        ];
+        reflect_bind_group_layouts(&shaders.iter().flatten().collect::<Vec<_>>())
+    }
+
     pub fn build(&mut self, device: &wgpu::Device) -> Result<wgpu::RenderPipeline> {
         // We need a layout
         if self.layout.is_none() {
             bail!("No pipeline layout supplied!");
         }
         let layout = self.layout.unwrap();
+        let (pipeline, _) = self.build_with_layout(device, layout)?;
+        Ok(pipeline)
+    }
+
+    /// Builds the pipeline, and keeps watching the shader files and the
+    /// files they `#include` so that it can be rebuilt when they
+    /// change. Both shaders need to
+    /// have been supplied with [RenderPipelineBuilder::vertex_shader_path]
+    /// and [RenderPipelineBuilder::fragment_shader_path].
+    ///
+    /// The [HotReloadPipeline] needs to rebuild the pipeline long after
+    /// this builder is gone, so it takes ownership of the layout
+    /// instead of borrowing it.
+    #[cfg(feature = "hot-reload")]
+    pub fn build_hot_reload(
+        &mut self,
+        device: &wgpu::Device,
+        layout: wgpu::PipelineLayout,
+    ) -> Result<HotReloadPipeline<'a>> {
+        let vs_path = self
+            .vertex_shader_path
+            .clone()
+            .context("Hot reloading requires a vertex shader path")?;
+        let fs_path = self
+            .fragment_shader_path
+            .clone()
+            .context("Hot reloading requires a fragment shader path")?;
+
+        let mut builder = std::mem::replace(self, Self::new());
+        let (pipeline, includes) = builder.build_with_layout(device, &layout)?;
+        let mut watched = Vec::new();
+        watch(&mut watched, vec![vs_path, fs_path], includes);
+
+        Ok(HotReloadPipeline {
+            builder,
+            layout,
+            pipeline,
+            watched,
+        })
+    }
+
+    /// Builds the pipeline, and returns it with every file the shader
+    /// files included.
+    fn build_with_layout(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+    ) -> Result<(wgpu::RenderPipeline, Vec<PathBuf>)> {
+        let mut includes = Vec::new();
 
         // Render pipelines always have a vertex shader, but due
         // to the way the builder pattern works, we can't
@@ -182,36 +288,63 @@ impl<'a> RenderPipelineBuilder<'a> {
         // We could supply a default one, but a "default" vertex
         // could take on many forms. An error is much more
         // explicit.
-        if self.vertex_shader.is_none() {
-            bail!("No vertex shader supplied!")
+        let vs_spv = compile_path(&self.vertex_shader_path, &mut includes)?;
+
+        // Catch vertex buffers that don't line up with the shader here,
+        // rather than with a validation error when drawing.
+        let vs_reflection = reflect_shader(vs_spv.as_deref(), self.vertex_shader.as_ref())?;
+        if let Some(reflection) = vs_reflection {
+            validate_entry_point(
+                &reflection,
+                self.vertex_entry_point,
+                wgpu::ShaderStage::VERTEX,
+            )?;
+            validate_vertex_inputs(&reflection.inputs, &self.vertex_buffers)?;
         }
-        let vs = create_shader_module(
-            device,
-            self.vertex_shader
-                .take()
-                .context("Please include a vertex shader")?,
-        );
+
+        let vs = match &vs_spv {
+            Some(spv) => create_shader_module(device, wgpu::util::make_spirv(spv)),
+            None => create_shader_module(
+                device,
+                self.vertex_shader
+                    .take()
+                    .context("Please include a vertex shader")?,
+            ),
+        };
 
         // The fragment shader is optional (IDK why, but it is).
         // Having the shader be optional is giving me issues with
         // the borrow checker so I'm going to use a default shader
         // if the user doesn't supply one.
-        let fs_spv = self
-            .fragment_shader
-            .take()
-            .context("Please include a fragment shader")?;
-        let fs = create_shader_module(device, fs_spv);
+        let fs_spv = compile_path(&self.fragment_shader_path, &mut includes)?;
+        let fs_reflection = reflect_shader(fs_spv.as_deref(), self.fragment_shader.as_ref())?;
+        if let Some(reflection) = fs_reflection {
+            validate_entry_point(
+                &reflection,
+                self.fragment_entry_point,
+                wgpu::ShaderStage::FRAGMENT,
+            )?;
+        }
+        let fs = match &fs_spv {
+            Some(spv) => create_shader_module(device, wgpu::util::make_spirv(spv)),
+            None => create_shader_module(
+                device,
+                self.fragment_shader
+                    .take()
+                    .context("Please include a fragment shader")?,
+            ),
+        };
 
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
-            layout: Some(&layout),
+            layout: Some(layout),
             vertex_stage: wgpu::ProgrammableStageDescriptor {
                 module: &vs,
-                entry_point: "main",
+                entry_point: self.vertex_entry_point,
             },
             fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
                 module: &fs,
-                entry_point: "main",
+                entry_point: self.fragment_entry_point,
             }),
             rasterization_state: Some(wgpu::RasterizationStateDescriptor {
                 front_face: self.front_face,
@@ -232,7 +365,7 @@ impl<'a> RenderPipelineBuilder<'a> {
             sample_mask: self.sample_mask,
             alpha_to_coverage_enabled: self.alpha_to_coverage_enabled,
         });
-        Ok(pipeline)
+        Ok((pipeline, includes))
     }
 }
 
@@ -242,3 +375,381 @@ fn create_shader_module(
 ) -> wgpu::ShaderModule {
     device.create_shader_module(spirv)
 }
+
+pub struct ComputePipelineBuilder<'a> {
+    layout: Option<&'a wgpu::PipelineLayout>,
+    shader: Option<wgpu::ShaderModuleSource<'a>>,
+    shader_path: Option<PathBuf>,
+    entry_point: &'a str,
+}
+
+impl<'a> ComputePipelineBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            layout: None,
+            shader: None,
+            shader_path: None,
+            entry_point: "main",
+        }
+    }
+
+    pub fn layout(&mut self, layout: &'a wgpu::PipelineLayout) -> &mut Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    pub fn shader(&mut self, src: wgpu::ShaderModuleSource<'a>) -> &mut Self {
+        self.shader = Some(src);
+        self
+    }
+
+    /// Compiles the GLSL file at `path` with shaderc when the pipeline
+    /// is built, instead of using precompiled SPIR-V.
+    #[cfg(feature = "hot-reload")]
+    pub fn shader_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.shader_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The function the compute stage starts in. Defaults to `main`.
+    pub fn entry_point(&mut self, name: &'a str) -> &mut Self {
+        self.entry_point = name;
+        self
+    }
+
+    /// See [RenderPipelineBuilder::bind_group_layouts]
+    pub fn bind_group_layouts(&self) -> Result<Vec<ReflectedBindGroupLayout>> {
+        let spv = compile_path(&self.shader_path, &mut Vec::new())?;
+        let shaders = [reflect_shader(spv.as_deref(), self.shader.as_ref())?];
+        reflect_bind_group_layouts(&shaders.iter().flatten().collect::<Vec<_>>())
+    }
+
+    pub fn build(&mut self, device: &wgpu::Device) -> Result<wgpu::ComputePipeline> {
+        let layout = self.layout.context("No pipeline layout supplied!")?;
+
+        let spv = compile_path(&self.shader_path, &mut Vec::new())?;
+        if let Some(reflection) = reflect_shader(spv.as_deref(), self.shader.as_ref())? {
+            validate_entry_point(&reflection, self.entry_point, wgpu::ShaderStage::COMPUTE)?;
+        }
+        let module = match &spv {
+            Some(spv) => create_shader_module(device, wgpu::util::make_spirv(spv)),
+            None => create_shader_module(
+                device,
+                self.shader
+                    .take()
+                    .context("Please include a compute shader")?,
+            ),
+        };
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline"),
+            layout: Some(layout),
+            compute_stage: wgpu::ProgrammableStageDescriptor {
+                module: &module,
+                entry_point: self.entry_point,
+            },
+        });
+        Ok(pipeline)
+    }
+}
+
+/// Makes sure the module has an entry point called `name` for
+/// `stage`, as wgpu only reports this as a validation error.
+fn validate_entry_point(
+    reflection: &ShaderReflection,
+    name: &str,
+    stage: wgpu::ShaderStage,
+) -> Result<()> {
+    let found = reflection
+        .entry_points
+        .iter()
+        .any(|e| e.name == name && e.stage == stage);
+    if !found {
+        let available = reflection
+            .entry_points
+            .iter()
+            .filter(|e| e.stage == stage)
+            .map(|e| e.name.as_str())
+            .collect::<Vec<_>>();
+        bail!(
+            "No {:?} entry point called {:?}. Available: {:?}",
+            stage,
+            name,
+            available
+        );
+    }
+    Ok(())
+}
+
+/// Reflects compiled SPIR-V if there is any, preferring the output of
+/// a shader path over a supplied source.
+fn reflect_shader(
+    spv: Option<&[u8]>,
+    src: Option<&wgpu::ShaderModuleSource>,
+) -> Result<Option<ShaderReflection>> {
+    match (spv, src) {
+        (Some(spv), _) => ShaderReflection::from_bytes(spv).map(Some),
+        (None, Some(wgpu::ShaderModuleSource::SpirV(words))) => {
+            ShaderReflection::from_spirv(words).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Compiles the shader at `path`, if one was supplied, and adds the
+/// files it included to `includes`.
+#[cfg(feature = "hot-reload")]
+fn compile_path(path: &Option<PathBuf>, includes: &mut Vec<PathBuf>) -> Result<Option<Vec<u8>>> {
+    match path {
+        Some(path) => {
+            let compiled = compile_shader_file(path)?;
+            includes.extend(compiled.includes.into_iter().map(|(include, _)| include));
+            Ok(Some(compiled.spirv))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Shader paths can only be supplied with the `hot-reload` feature.
+#[cfg(not(feature = "hot-reload"))]
+fn compile_path(_path: &Option<PathBuf>, _includes: &mut Vec<PathBuf>) -> Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+/// Compiles a GLSL file to SPIR-V. Like the build scripts, the shader
+/// kind is picked using the file extension, and `#include` is
+/// resolved relative to the file. The result lists every included
+/// file, which need watching too when hot reloading.
+#[cfg(feature = "hot-reload")]
+pub fn compile_shader_file<P: AsRef<Path>>(path: P) -> Result<shader_build::CompiledShader> {
+    let path = path.as_ref();
+    let mut compiler = shaderc::Compiler::new().context("Unable to create shader compiler")?;
+    match shader_build::compile_file(&mut compiler, path, &[], &[]) {
+        Ok(compiled) => {
+            if !compiled.warnings.is_empty() {
+                log::warn!("{}", compiled.warnings);
+            }
+            Ok(compiled)
+        }
+        Err(errors) => {
+            let messages = errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            bail!("Unable to compile {}:\n{}", path.display(), messages)
+        }
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+#[cfg(feature = "hot-reload")]
+impl WatchedFile {
+    fn new(path: PathBuf) -> Self {
+        let modified = Self::modified_time(&path);
+        Self { path, modified }
+    }
+
+    fn modified_time(path: &Path) -> Option<SystemTime> {
+        metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Returns true if the file was modified since the last check.
+    fn check(&mut self) -> bool {
+        let modified = Self::modified_time(&self.path);
+        if modified != self.modified {
+            self.modified = modified;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Watches `shaders` and `includes`, dropping files that aren't either
+/// any more. Files that were already watched keep their modification
+/// time, so edits made while the pipeline was rebuilding aren't missed.
+#[cfg(feature = "hot-reload")]
+fn watch(watched: &mut Vec<WatchedFile>, shaders: Vec<PathBuf>, includes: Vec<PathBuf>) {
+    let paths = shaders.into_iter().chain(includes).collect::<Vec<_>>();
+    watched.retain(|file| paths.contains(&file.path));
+    for path in paths {
+        if watched.iter().all(|file| file.path != path) {
+            watched.push(WatchedFile::new(path));
+        }
+    }
+}
+
+/// A render pipeline built from GLSL files that are recompiled when
+/// they change on disk. See [RenderPipelineBuilder::build_hot_reload].
+#[cfg(feature = "hot-reload")]
+pub struct HotReloadPipeline<'a> {
+    builder: RenderPipelineBuilder<'a>,
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    watched: Vec<WatchedFile>,
+}
+
+#[cfg(feature = "hot-reload")]
+impl<'a> HotReloadPipeline<'a> {
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    pub fn layout(&self) -> &wgpu::PipelineLayout {
+        &self.layout
+    }
+
+    /// Rebuilds the pipeline if any of its shaders, or the files they
+    /// include, changed since the last call. Call this once per frame. If a shader doesn't
+    /// compile the error is logged and the last working pipeline is
+    /// kept. Returns true if the pipeline was replaced.
+    pub fn reload_if_changed(&mut self, device: &wgpu::Device) -> bool {
+        // Check every file so each one's timestamp gets updated
+        let changed = self
+            .watched
+            .iter_mut()
+            .fold(false, |changed, file| file.check() || changed);
+        if !changed {
+            return false;
+        }
+
+        match self.builder.build_with_layout(device, &self.layout) {
+            Ok((pipeline, includes)) => {
+                log::info!("Reloaded shaders");
+                self.pipeline = pipeline;
+                // The shaders may include different files now
+                let shaders = self
+                    .builder
+                    .vertex_shader_path
+                    .iter()
+                    .chain(&self.builder.fragment_shader_path)
+                    .cloned()
+                    .collect();
+                watch(&mut self.watched, shaders, includes);
+                true
+            }
+            Err(e) => {
+                log::error!(
+                    "Unable to reload shaders, keeping the last pipeline: {:?}",
+                    e
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "hot-reload"))]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    f_color = vec4(1.0);
+}
+"#;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes `contents` to `path`, waiting until the file system
+    /// reports a new modification time, as some only store seconds.
+    fn rewrite(path: &Path, contents: &str) {
+        let before = WatchedFile::modified_time(path);
+        loop {
+            fs::write(path, contents).unwrap();
+            if WatchedFile::modified_time(path) != before {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn watched_files_notice_edits() {
+        let dir = temp_dir("watched-files");
+        let path = dir.join("shader.frag");
+        fs::write(&path, FRAGMENT_SHADER).unwrap();
+
+        let mut file = WatchedFile::new(path.clone());
+        assert!(!file.check());
+        rewrite(&path, FRAGMENT_SHADER);
+        assert!(file.check());
+        assert!(!file.check());
+        fs::remove_file(&path).unwrap();
+        assert!(file.check());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn included_files_are_watched() {
+        let dir = temp_dir("shader-includes");
+        let header = dir.join("color.glsl");
+        fs::write(&header, "vec4 color() { return vec4(1.0); }").unwrap();
+        let path = dir.join("shader.frag");
+        fs::write(
+            &path,
+            FRAGMENT_SHADER
+                .replace("void main", "#include \"color.glsl\"\n\nvoid main")
+                .replace("vec4(1.0);\n}", "color();\n}"),
+        )
+        .unwrap();
+        let compiled = compile_shader_file(&path).unwrap();
+        let includes = compiled
+            .includes
+            .into_iter()
+            .map(|(include, _)| include)
+            .collect::<Vec<_>>();
+        assert_eq!(includes, vec![header.clone()]);
+
+        let mut watched = Vec::new();
+        watch(&mut watched, vec![path.clone()], includes);
+        let paths = |watched: &[WatchedFile]| {
+            watched
+                .iter()
+                .map(|file| file.path.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(&watched), vec![path.clone(), header.clone()]);
+        rewrite(&header, "vec4 color() { return vec4(0.5); }");
+        assert!(watched.iter_mut().any(|file| file.check()));
+
+        // Includes that are no longer used stop being watched, and the
+        // shader keeps its modification time
+        rewrite(&path, FRAGMENT_SHADER);
+        watch(&mut watched, vec![path.clone()], Vec::new());
+        assert_eq!(paths(&watched), vec![path.clone()]);
+        assert!(watched[0].check());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn broken_shader_files_report_their_errors() {
+        let dir = temp_dir("shader-files");
+        let path = dir.join("shader.frag");
+        fs::write(&path, FRAGMENT_SHADER).unwrap();
+        assert!(!compile_shader_file(&path).unwrap().spirv.is_empty());
+
+        fs::write(&path, FRAGMENT_SHADER.replace("vec4(1.0)", "undefined")).unwrap();
+        let error = compile_shader_file(&path).unwrap_err().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(error.contains("undefined"), "{}", error);
+    }
+}
@@ -0,0 +1,734 @@
+use anyhow::*;
+use std::mem;
+use std::sync::Arc;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::pipeline::RenderPipelineBuilder;
+use crate::render_graph::{GraphPass, PassContext, SWAP_CHAIN};
+use crate::sampler::{SamplerCache, SamplerConfig};
+use crate::texture::Texture;
+
+/// The format scenes should render into before post-processing, so
+/// lighting isn't clamped to 1.0 until it's tonemapped.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The attachment a [PostProcess] reads by default.
+pub const HDR_ATTACHMENT: &str = "hdr";
+
+/// The tonemapped image FXAA runs on. Being sRGB, it keeps more
+/// precision in the darks than a linear 8 bit texture would.
+const LDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// How HDR colors are squeezed into what the display can show.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tonemapper {
+    /// Colors are clamped to 1.0.
+    None,
+    Reinhard,
+    /// A fit of the ACES filmic curve, which desaturates highlights
+    /// the way film does.
+    Aces,
+}
+
+impl Tonemapper {
+    /// The values `tonemap.frag` switches on.
+    fn to_raw(self) -> u32 {
+        match self {
+            Tonemapper::None => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::Aces => 2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BloomConfig {
+    /// Only colors brighter than this bloom.
+    pub threshold: f32,
+    /// How far below the threshold bloom fades in.
+    pub knee: f32,
+    /// How much of the bloom is added to the image.
+    pub intensity: f32,
+    /// Each pass blurs horizontally then vertically. More passes
+    /// spread the bloom further.
+    pub blur_passes: u32,
+}
+
+impl Default for BloomConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.1,
+            blur_passes: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostProcessConfig {
+    /// Colors are multiplied by this before they're tonemapped.
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    pub bloom: Option<BloomConfig>,
+    pub fxaa: bool,
+    /// Used to encode the output when the target isn't sRGB. sRGB
+    /// targets are encoded by the hardware.
+    pub gamma: f32,
+}
+
+impl Default for PostProcessConfig {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tonemapper: Tonemapper::Aces,
+            bloom: Some(BloomConfig::default()),
+            fxaa: true,
+            gamma: 2.2,
+        }
+    }
+}
+
+/// The `Tonemap` uniform from `tonemap.frag`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct TonemapUniform {
+    exposure: f32,
+    bloom_intensity: f32,
+    tonemapper: u32,
+    lut_size: f32,
+    gamma: f32,
+    _padding: [f32; 3],
+}
+
+unsafe impl bytemuck::Zeroable for TonemapUniform {}
+unsafe impl bytemuck::Pod for TonemapUniform {}
+
+/// The `Bloom` uniform from `bloom_prefilter.frag`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    _padding: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for BloomUniform {}
+unsafe impl bytemuck::Pod for BloomUniform {}
+
+/// The `Blur` uniform from `bloom_blur.frag`, and the `Fxaa` one from
+/// `fxaa.frag`, which only adds the gamma.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct TexelUniform {
+    texel: [f32; 2],
+    gamma: f32,
+    _padding: f32,
+}
+
+unsafe impl bytemuck::Zeroable for TexelUniform {}
+unsafe impl bytemuck::Pod for TexelUniform {}
+
+fn is_srgb(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format,
+        wgpu::TextureFormat::Rgba8UnormSrgb | wgpu::TextureFormat::Bgra8UnormSrgb
+    )
+}
+
+/// A grading LUT is a strip of `size` slices of `size` x `size`
+/// texels, one slice per blue value, so a 16 entry LUT is 256x16.
+fn lut_size(width: u32, height: u32) -> Result<u32> {
+    if height < 2 || width != height * height {
+        bail!(
+            "A LUT needs to be a strip of square slices, like 256x16, got {}x{}",
+            width,
+            height
+        );
+    }
+    Ok(height)
+}
+
+fn uniform_buffer<T: bytemuck::Pod>(device: &wgpu::Device, label: &str, data: T) -> wgpu::Buffer {
+    device.create_buffer_init(&BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(&[data]),
+        usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+    })
+}
+
+/// A fragment shader drawn over the whole target with `blit.vert`, with
+/// a pipeline for every format it renders to.
+struct FullscreenPass {
+    layout: wgpu::BindGroupLayout,
+    pipelines: Vec<(wgpu::TextureFormat, wgpu::RenderPipeline)>,
+}
+
+impl FullscreenPass {
+    fn new(
+        device: &wgpu::Device,
+        label: &str,
+        fragment_shader: fn() -> wgpu::ShaderModuleSource<'static>,
+        formats: &[wgpu::TextureFormat],
+    ) -> Result<Self> {
+        let mut builder = RenderPipelineBuilder::new();
+        builder
+            .vertex_shader(wgpu::include_spirv!("shaders/blit.vert.spv"))
+            .fragment_shader(fragment_shader());
+        let layouts = builder.bind_group_layouts()?;
+        let layout = layouts
+            .first()
+            .with_context(|| format!("The {} shader has no bindings", label))?
+            .create(device, Some(label));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let mut pipelines = Vec::with_capacity(formats.len());
+        for &format in formats {
+            let pipeline = RenderPipelineBuilder::new()
+                .layout(&pipeline_layout)
+                .vertex_shader(wgpu::include_spirv!("shaders/blit.vert.spv"))
+                .fragment_shader(fragment_shader())
+                // The blit shader makes a quad out of 4 vertices
+                .primitive_topology(wgpu::PrimitiveTopology::TriangleStrip)
+                .color_solid(format)
+                .build(device)
+                .with_context(|| format!("Unable to create the {} pipeline", label))?;
+            pipelines.push((format, pipeline));
+        }
+        Ok(Self { layout, pipelines })
+    }
+
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        resources: &[wgpu::BindingResource],
+    ) -> wgpu::BindGroup {
+        let entries = resources
+            .iter()
+            .enumerate()
+            .map(|(binding, resource)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: resource.clone(),
+            })
+            .collect::<Vec<_>>();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        // Only the formats given to new have pipelines, and this is
+        // only called with those
+        let pipeline = self
+            .pipelines
+            .iter()
+            .find(|(f, _)| *f == format)
+            .map(|(_, pipeline)| pipeline)
+            .unwrap();
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.draw(0..4, 0..1);
+    }
+}
+
+/// Textures that depend on the size of the image being processed.
+struct Targets {
+    width: u32,
+    height: u32,
+    /// Half resolution, ping-ponged between by the blur passes. The
+    /// finished bloom ends up in the first one.
+    bloom: [Texture<'static>; 2],
+    ldr: Texture<'static>,
+    blur_bind_groups: [wgpu::BindGroup; 2],
+    fxaa_bind_group: wgpu::BindGroup,
+}
+
+/// Turns an HDR image into what's presented: bloom, exposure and
+/// tonemapping, color grading with a LUT, then FXAA.
+///
+/// Use it as a pass of a [RenderGraph](crate::RenderGraph) that reads
+/// [HDR_ATTACHMENT] and writes [SWAP_CHAIN], or call
+/// [PostProcess::render] directly. Wrap it in an `Rc<RefCell<_>>` to
+/// change its config after adding it to a graph.
+pub struct PostProcess {
+    config: PostProcessConfig,
+    input: String,
+    output_format: wgpu::TextureFormat,
+    lut: Option<(Texture<'static>, u32)>,
+    black: Texture<'static>,
+    samplers: SamplerCache,
+    sampler: Arc<wgpu::Sampler>,
+    tonemap_buffer: wgpu::Buffer,
+    bloom_buffer: wgpu::Buffer,
+    blur_buffers: [wgpu::Buffer; 2],
+    fxaa_buffer: wgpu::Buffer,
+    prefilter: FullscreenPass,
+    blur: FullscreenPass,
+    tonemap: FullscreenPass,
+    fxaa: FullscreenPass,
+    targets: Option<Targets>,
+}
+
+impl PostProcess {
+    /// `output_format` is the format of the texture the result is
+    /// rendered to, usually the swap chain's.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        output_format: wgpu::TextureFormat,
+        config: PostProcessConfig,
+    ) -> Result<Self> {
+        let black = Texture::from_color(
+            device,
+            queue,
+            [0, 0, 0, 255],
+            Some("PostProcess::black"),
+            true,
+            samplers,
+        )?;
+        let sampler = samplers.get(device, &SamplerConfig::default());
+
+        let empty = TexelUniform {
+            texel: [0.0; 2],
+            gamma: 1.0,
+            _padding: 0.0,
+        };
+        let mut post = Self {
+            input: HDR_ATTACHMENT.to_string(),
+            output_format,
+            lut: None,
+            black,
+            samplers: samplers.clone(),
+            sampler,
+            tonemap_buffer: uniform_buffer(
+                device,
+                "PostProcess::tonemap_buffer",
+                TonemapUniform {
+                    exposure: 1.0,
+                    bloom_intensity: 0.0,
+                    tonemapper: 0,
+                    lut_size: 0.0,
+                    gamma: 1.0,
+                    _padding: [0.0; 3],
+                },
+            ),
+            bloom_buffer: uniform_buffer(
+                device,
+                "PostProcess::bloom_buffer",
+                BloomUniform {
+                    threshold: 1.0,
+                    knee: 0.0,
+                    _padding: [0.0; 2],
+                },
+            ),
+            blur_buffers: [
+                uniform_buffer(device, "PostProcess::blur_buffer", empty),
+                uniform_buffer(device, "PostProcess::blur_buffer", empty),
+            ],
+            fxaa_buffer: uniform_buffer(device, "PostProcess::fxaa_buffer", empty),
+            prefilter: FullscreenPass::new(
+                device,
+                "PostProcess::prefilter",
+                || wgpu::include_spirv!("shaders/bloom_prefilter.frag.spv"),
+                &[HDR_FORMAT],
+            )?,
+            blur: FullscreenPass::new(
+                device,
+                "PostProcess::blur",
+                || wgpu::include_spirv!("shaders/bloom_blur.frag.spv"),
+                &[HDR_FORMAT],
+            )?,
+            tonemap: FullscreenPass::new(
+                device,
+                "PostProcess::tonemap",
+                || wgpu::include_spirv!("shaders/tonemap.frag.spv"),
+                &[LDR_FORMAT, output_format],
+            )?,
+            fxaa: FullscreenPass::new(
+                device,
+                "PostProcess::fxaa",
+                || wgpu::include_spirv!("shaders/fxaa.frag.spv"),
+                &[output_format],
+            )?,
+            targets: None,
+            config,
+        };
+        post.write_uniforms(queue);
+        Ok(post)
+    }
+
+    pub fn config(&self) -> &PostProcessConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, queue: &wgpu::Queue, config: PostProcessConfig) {
+        self.config = config;
+        self.write_uniforms(queue);
+    }
+
+    /// The attachment read when running as part of a
+    /// [RenderGraph](crate::RenderGraph). [HDR_ATTACHMENT] by default.
+    pub fn input(&mut self, name: &str) -> &mut Self {
+        self.input = name.to_string();
+        self
+    }
+
+    /// Grades the tonemapped image with a color lookup table, stored
+    /// as a strip of square slices like the 256x16 LUTs most image
+    /// editors export. The LUT maps sRGB colors to sRGB colors.
+    pub fn set_lut(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+    ) -> Result<()> {
+        use image::GenericImageView;
+
+        let (width, height) = img.dimensions();
+        let size = lut_size(width, height)?;
+        // Loaded as linear, so the shader gets the values as they are
+        // in the file
+        let texture = Texture::from_image(
+            device,
+            queue,
+            img,
+            Some("PostProcess::lut"),
+            true,
+            &self.samplers,
+            &SamplerConfig::default(),
+        )?;
+        self.lut = Some((texture, size));
+        self.write_uniforms(queue);
+        Ok(())
+    }
+
+    pub fn clear_lut(&mut self, queue: &wgpu::Queue) {
+        self.lut = None;
+        self.write_uniforms(queue);
+    }
+
+    fn write_uniforms(&self, queue: &wgpu::Queue) {
+        let output_gamma = if is_srgb(self.output_format) {
+            1.0
+        } else {
+            self.config.gamma
+        };
+        let bloom = self.config.bloom.unwrap_or_default();
+        queue.write_buffer(
+            &self.tonemap_buffer,
+            0,
+            bytemuck::cast_slice(&[TonemapUniform {
+                exposure: self.config.exposure,
+                bloom_intensity: self.config.bloom.map_or(0.0, |b| b.intensity),
+                tonemapper: self.config.tonemapper.to_raw(),
+                lut_size: self.lut.as_ref().map_or(0.0, |(_, size)| *size as f32),
+                // FXAA does the encoding when it's on
+                gamma: if self.config.fxaa { 1.0 } else { output_gamma },
+                _padding: [0.0; 3],
+            }]),
+        );
+        queue.write_buffer(
+            &self.bloom_buffer,
+            0,
+            bytemuck::cast_slice(&[BloomUniform {
+                threshold: bloom.threshold,
+                knee: bloom.knee,
+                _padding: [0.0; 2],
+            }]),
+        );
+        if let Some(targets) = &self.targets {
+            let bloom_width = targets.bloom[0].desc.size.width as f32;
+            let bloom_height = targets.bloom[0].desc.size.height as f32;
+            let directions = [[1.0 / bloom_width, 0.0], [0.0, 1.0 / bloom_height]];
+            for (buffer, direction) in self.blur_buffers.iter().zip(&directions) {
+                queue.write_buffer(
+                    buffer,
+                    0,
+                    bytemuck::cast_slice(&[TexelUniform {
+                        texel: *direction,
+                        gamma: 1.0,
+                        _padding: 0.0,
+                    }]),
+                );
+            }
+            queue.write_buffer(
+                &self.fxaa_buffer,
+                0,
+                bytemuck::cast_slice(&[TexelUniform {
+                    texel: [1.0 / targets.width as f32, 1.0 / targets.height as f32],
+                    gamma: output_gamma,
+                    _padding: 0.0,
+                }]),
+            );
+        }
+    }
+
+    fn create_targets(&self, device: &wgpu::Device, width: u32, height: u32) -> Targets {
+        let texture = |label, width: u32, height: u32, format| {
+            Texture::from_descriptor(
+                device,
+                wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: width.max(1),
+                        height: height.max(1),
+                        depth: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+                },
+                &self.samplers,
+                &SamplerConfig::default(),
+            )
+        };
+        let bloom = [
+            texture("PostProcess::bloom", width / 2, height / 2, HDR_FORMAT),
+            texture("PostProcess::bloom", width / 2, height / 2, HDR_FORMAT),
+        ];
+        let ldr = texture("PostProcess::ldr", width, height, LDR_FORMAT);
+
+        let blur_bind_groups = [
+            self.blur.bind_group(
+                device,
+                &[
+                    wgpu::BindingResource::TextureView(&bloom[0].view),
+                    wgpu::BindingResource::Sampler(&self.sampler),
+                    wgpu::BindingResource::Buffer(self.blur_buffers[0].slice(..)),
+                ],
+            ),
+            self.blur.bind_group(
+                device,
+                &[
+                    wgpu::BindingResource::TextureView(&bloom[1].view),
+                    wgpu::BindingResource::Sampler(&self.sampler),
+                    wgpu::BindingResource::Buffer(self.blur_buffers[1].slice(..)),
+                ],
+            ),
+        ];
+        let fxaa_bind_group = self.fxaa.bind_group(
+            device,
+            &[
+                wgpu::BindingResource::TextureView(&ldr.view),
+                wgpu::BindingResource::Sampler(&self.sampler),
+                wgpu::BindingResource::Buffer(self.fxaa_buffer.slice(..)),
+            ],
+        );
+
+        Targets {
+            width,
+            height,
+            bloom,
+            ldr,
+            blur_bind_groups,
+            fxaa_bind_group,
+        }
+    }
+
+    /// Recreates the intermediate textures if the input's size changed.
+    /// [PostProcess::render] calls this itself.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        let size_matches = |t: &Targets| (t.width, t.height) == (width, height);
+        if self.targets.as_ref().map_or(false, size_matches) {
+            return;
+        }
+        self.targets = Some(self.create_targets(device, width, height));
+        self.write_uniforms(queue);
+    }
+
+    /// Post-processes `input`, a [HDR_FORMAT] texture that's `width` x
+    /// `height`, into `output`, which has the format given to
+    /// [PostProcess::new].
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        self.resize(device, queue, width, height);
+        let targets = self.targets.as_ref().unwrap();
+
+        let bloom = match self.config.bloom {
+            Some(bloom) => {
+                let prefilter_bind_group = self.prefilter.bind_group(
+                    device,
+                    &[
+                        wgpu::BindingResource::TextureView(input),
+                        wgpu::BindingResource::Sampler(&self.sampler),
+                        wgpu::BindingResource::Buffer(self.bloom_buffer.slice(..)),
+                    ],
+                );
+                self.prefilter.draw(
+                    encoder,
+                    &targets.bloom[0].view,
+                    HDR_FORMAT,
+                    &prefilter_bind_group,
+                );
+                for _ in 0..bloom.blur_passes {
+                    self.blur.draw(
+                        encoder,
+                        &targets.bloom[1].view,
+                        HDR_FORMAT,
+                        &targets.blur_bind_groups[0],
+                    );
+                    self.blur.draw(
+                        encoder,
+                        &targets.bloom[0].view,
+                        HDR_FORMAT,
+                        &targets.blur_bind_groups[1],
+                    );
+                }
+                &targets.bloom[0].view
+            }
+            None => &self.black.view,
+        };
+        let lut = self
+            .lut
+            .as_ref()
+            .map_or(&self.black.view, |(texture, _)| &texture.view);
+
+        // The input can be a different texture every frame, so this
+        // can't be kept around like the other bind groups
+        let tonemap_bind_group = self.tonemap.bind_group(
+            device,
+            &[
+                wgpu::BindingResource::TextureView(input),
+                wgpu::BindingResource::Sampler(&self.sampler),
+                wgpu::BindingResource::TextureView(bloom),
+                wgpu::BindingResource::TextureView(lut),
+                wgpu::BindingResource::Buffer(self.tonemap_buffer.slice(..)),
+            ],
+        );
+        if self.config.fxaa {
+            self.tonemap
+                .draw(encoder, &targets.ldr.view, LDR_FORMAT, &tonemap_bind_group);
+            self.fxaa.draw(
+                encoder,
+                output,
+                self.output_format,
+                &targets.fxaa_bind_group,
+            );
+        } else {
+            self.tonemap
+                .draw(encoder, output, self.output_format, &tonemap_bind_group);
+        }
+    }
+}
+
+impl<S> GraphPass<S> for PostProcess {
+    fn execute(&mut self, ctx: &mut PassContext, _state: &mut S) -> Result<()> {
+        let input = ctx.texture(&self.input)?;
+        if input.desc.format != HDR_FORMAT {
+            bail!(
+                "PostProcess needs {} to be {:?}, not {:?}",
+                self.input,
+                HDR_FORMAT,
+                input.desc.format
+            );
+        }
+        if ctx.sc_desc.format != self.output_format {
+            bail!(
+                "PostProcess was created for {:?}, but the swap chain is {:?}",
+                self.output_format,
+                ctx.sc_desc.format
+            );
+        }
+        let output = ctx.view(SWAP_CHAIN)?;
+        self.render(
+            ctx.device,
+            ctx.queue,
+            ctx.encoder,
+            &input.view,
+            output,
+            input.desc.size.width,
+            input.desc.size.height,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reflect::*;
+
+    fn fragment_layout(bytes: &[u8]) -> Vec<wgpu::BindGroupLayoutEntry> {
+        let vs = ShaderReflection::from_bytes(include_bytes!("shaders/blit.vert.spv")).unwrap();
+        let fs = ShaderReflection::from_bytes(bytes).unwrap();
+        let mut layouts = reflect_bind_group_layouts(&[&vs, &fs]).unwrap();
+        assert_eq!(layouts.len(), 1);
+        layouts.remove(0).entries
+    }
+
+    #[test]
+    fn post_shaders_match_bind_groups() {
+        let vs = ShaderReflection::from_bytes(include_bytes!("shaders/blit.vert.spv")).unwrap();
+        validate_vertex_inputs(&vs.inputs, &[]).unwrap();
+
+        // FullscreenPass::bind_group binds resources in order, so
+        // bindings can't have gaps
+        let texel_passes = [
+            &include_bytes!("shaders/bloom_prefilter.frag.spv")[..],
+            &include_bytes!("shaders/bloom_blur.frag.spv")[..],
+            &include_bytes!("shaders/fxaa.frag.spv")[..],
+        ];
+        for bytes in &texel_passes {
+            let entries = fragment_layout(bytes);
+            let bindings = entries.iter().map(|e| e.binding).collect::<Vec<_>>();
+            assert_eq!(bindings, vec![0, 1, 2]);
+        }
+        let entries = fragment_layout(include_bytes!("shaders/tonemap.frag.spv"));
+        let bindings = entries.iter().map(|e| e.binding).collect::<Vec<_>>();
+        assert_eq!(bindings, vec![0, 1, 2, 3, 4]);
+
+        assert_eq!(mem::size_of::<TonemapUniform>() % 16, 0);
+        assert_eq!(mem::size_of::<BloomUniform>() % 16, 0);
+        assert_eq!(mem::size_of::<TexelUniform>() % 16, 0);
+    }
+
+    #[test]
+    fn luts_are_strips_of_square_slices() {
+        assert_eq!(lut_size(256, 16).unwrap(), 16);
+        assert_eq!(lut_size(1024, 32).unwrap(), 32);
+        assert!(lut_size(256, 256).is_err());
+        assert!(lut_size(1, 1).is_err());
+    }
+
+    #[test]
+    fn only_linear_targets_are_gamma_encoded() {
+        assert!(is_srgb(wgpu::TextureFormat::Bgra8UnormSrgb));
+        assert!(!is_srgb(wgpu::TextureFormat::Bgra8Unorm));
+        assert!(!is_srgb(HDR_FORMAT));
+    }
+}
@@ -0,0 +1,532 @@
+use anyhow::*;
+use std::fs::{create_dir_all, File};
+use std::io::BufWriter;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::{advance_demo, init_demo, render_demo, Demo, Display};
+
+/// How colors are picked when encoding a gif. Gifs can only store
+/// 256 colors per frame.
+#[derive(Debug, Clone)]
+pub enum GifPalette {
+    /// Each frame gets its own palette using NeuQuant. `speed` goes
+    /// from 1 (best quality) to 30 (fastest).
+    Quantized { speed: i32 },
+    /// Every frame uses the supplied RGB palette. Pixels are mapped
+    /// to the closest color.
+    Global(Vec<[u8; 3]>),
+}
+
+#[derive(Debug, Clone)]
+pub struct GifOptions {
+    pub palette: GifPalette,
+    /// `None` loops forever.
+    pub repeat: Option<u16>,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self {
+            palette: GifPalette::Quantized { speed: 10 },
+            repeat: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RecordingFormat {
+    Gif(GifOptions),
+    Apng,
+    /// Each frame is saved as `{prefix}{index:04}.png` in the
+    /// directory passed to [Recording::save].
+    PngSequence { prefix: String },
+}
+
+/// Frames captured by a [Recorder].
+pub struct Recording {
+    pub frames: Vec<image::RgbaImage>,
+    /// The amount of simulated time between two frames.
+    pub timestep: Duration,
+}
+
+impl Recording {
+    pub fn width(&self) -> u32 {
+        self.frames.first().map(|f| f.width()).unwrap_or(0)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.frames.first().map(|f| f.height()).unwrap_or(0)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: &RecordingFormat) -> Result<()> {
+        match format {
+            RecordingFormat::Gif(options) => self.save_gif(path, options),
+            RecordingFormat::Apng => self.save_apng(path),
+            RecordingFormat::PngSequence { prefix } => self.save_png_sequence(path, prefix),
+        }
+    }
+
+    pub fn save_gif<P: AsRef<Path>>(&self, path: P, options: &GifOptions) -> Result<()> {
+        use gif::{Encoder, Frame, Repeat, SetParameter};
+
+        self.check_frame_sizes()?;
+        let width = to_u16(self.width())?;
+        let height = to_u16(self.height())?;
+        let delay = gif_delay(self.timestep);
+
+        let flat_palette = match &options.palette {
+            GifPalette::Global(palette) if palette.len() > 256 => {
+                bail!("Gif palettes can't have more than 256 colors")
+            }
+            GifPalette::Quantized { speed } if !(1..=30).contains(speed) => {
+                bail!(
+                    "Gif quantization speed has to be from 1 to 30, not {}",
+                    speed
+                )
+            }
+            GifPalette::Global(palette) => palette.iter().flatten().copied().collect(),
+            GifPalette::Quantized { .. } => Vec::new(),
+        };
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = Encoder::new(file, width, height, &flat_palette)?;
+        encoder.set(match options.repeat {
+            Some(n) => Repeat::Finite(n),
+            None => Repeat::Infinite,
+        })?;
+
+        for image in &self.frames {
+            let mut frame = match &options.palette {
+                GifPalette::Quantized { speed } => {
+                    let mut pixels = image.clone().into_raw();
+                    Frame::from_rgba_speed(width, height, &mut pixels, *speed)
+                }
+                GifPalette::Global(palette) => {
+                    let indices = image
+                        .pixels()
+                        .map(|p| closest_color(palette, [p[0], p[1], p[2]]))
+                        .collect::<Vec<_>>();
+                    Frame::from_indexed_pixels(width, height, &indices, None)
+                }
+            };
+            frame.delay = delay;
+            encoder.write_frame(&frame)?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves the recording as an animated png. The `png` crate can't
+    /// write animations by itself, so each frame is encoded as a
+    /// regular png and its image data is moved into APNG chunks.
+    pub fn save_apng<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.check_frame_sizes()?;
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width(), self.height());
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+
+        // acTL: number of frames, number of plays (0 = forever)
+        let mut actl = Vec::new();
+        actl.extend_from_slice(&(self.frames.len() as u32).to_be_bytes());
+        actl.extend_from_slice(&0u32.to_be_bytes());
+        writer.write_chunk(*b"acTL", &actl)?;
+
+        let (delay_num, delay_den) = apng_delay(self.timestep);
+        let mut sequence_number = 0u32;
+        for (i, image) in self.frames.iter().enumerate() {
+            let mut fctl = Vec::new();
+            fctl.extend_from_slice(&sequence_number.to_be_bytes());
+            fctl.extend_from_slice(&image.width().to_be_bytes());
+            fctl.extend_from_slice(&image.height().to_be_bytes());
+            fctl.extend_from_slice(&0u32.to_be_bytes()); // x offset
+            fctl.extend_from_slice(&0u32.to_be_bytes()); // y offset
+            fctl.extend_from_slice(&delay_num.to_be_bytes());
+            fctl.extend_from_slice(&delay_den.to_be_bytes());
+            fctl.push(0); // dispose_op: none
+            fctl.push(0); // blend_op: source
+            writer.write_chunk(*b"fcTL", &fctl)?;
+            sequence_number += 1;
+
+            let encoded = encode_png(image)?;
+            for data in idat_chunks(&encoded)? {
+                // The first frame doubles as the default image, so
+                // it uses IDAT. Every other frame uses fdAT.
+                if i == 0 {
+                    writer.write_chunk(*b"IDAT", data)?;
+                } else {
+                    let mut fdat = Vec::with_capacity(data.len() + 4);
+                    fdat.extend_from_slice(&sequence_number.to_be_bytes());
+                    fdat.extend_from_slice(data);
+                    writer.write_chunk(*b"fdAT", &fdat)?;
+                    sequence_number += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save_png_sequence<P: AsRef<Path>>(&self, dir: P, prefix: &str) -> Result<()> {
+        let dir = dir.as_ref();
+        create_dir_all(dir)?;
+        for (i, image) in self.frames.iter().enumerate() {
+            image.save(dir.join(format!("{}{:04}.png", prefix, i)))?;
+        }
+        Ok(())
+    }
+
+    /// Animations have a single size, which every frame has to match.
+    fn check_frame_sizes(&self) -> Result<()> {
+        let (width, height) = (self.width(), self.height());
+        for (i, image) in self.frames.iter().enumerate() {
+            if image.dimensions() != (width, height) {
+                bail!(
+                    "Frame {} is {}x{}, but the recording is {}x{}",
+                    i,
+                    image.width(),
+                    image.height(),
+                    width,
+                    height
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records frames from a [Demo] without opening a window. Time is
+/// advanced by a fixed timestep, so a recording looks the same no
+/// matter how long each frame takes to render.
+pub struct Recorder {
+    width: u32,
+    height: u32,
+    frames: Range<usize>,
+    timestep: Duration,
+}
+
+impl Recorder {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            frames: 0..60,
+            timestep: Duration::from_secs_f64(1.0 / 30.0),
+        }
+    }
+
+    /// Which frames to keep. Frames before `frames.start` are still
+    /// simulated and rendered, they're just not captured.
+    pub fn frames(&mut self, frames: Range<usize>) -> &mut Self {
+        self.frames = frames;
+        self
+    }
+
+    pub fn timestep(&mut self, timestep: Duration) -> &mut Self {
+        self.timestep = timestep;
+        self
+    }
+
+    pub fn size(&mut self, width: u32, height: u32) -> &mut Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Creates a headless [Display], initializes `D` with it, and
+    /// records it.
+    pub async fn record<D: Demo>(&self) -> Result<Recording> {
+        let mut display = Display::headless(self.width, self.height).await?;
+        let mut demo = init_demo::<D>(&mut display)?;
+        self.record_with(&mut display, &mut demo).await
+    }
+
+    /// Records a [Demo] that has already been set up.
+    pub async fn record_with<D: Demo>(
+        &self,
+        display: &mut Display,
+        demo: &mut D,
+    ) -> Result<Recording> {
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for i in 0..self.frames.end {
+            advance_demo(display, demo, self.timestep);
+            if self.frames.contains(&i) {
+                frames.push(display.capture_frame(demo).await?);
+            } else {
+                render_demo(display, demo);
+            }
+        }
+
+        Ok(Recording {
+            frames,
+            timestep: self.timestep,
+        })
+    }
+}
+
+fn to_u16(value: u32) -> Result<u16> {
+    if value > u16::MAX as u32 {
+        bail!("{} is too large for a gif dimension", value);
+    }
+    Ok(value as u16)
+}
+
+/// The delay between frames in the hundredths of a second gifs use,
+/// rounded to the nearest one. Viewers treat a delay of 0 as a default
+/// that's usually much longer, so it's at least 1.
+fn gif_delay(timestep: Duration) -> u16 {
+    let hundredths = (timestep.as_micros() + 5_000) / 10_000;
+    hundredths.max(1).min(u16::MAX as u128) as u16
+}
+
+/// The delay between frames as the numerator and denominator of a
+/// second that fcTL chunks use. This is the closest convergent of the
+/// timestep's continued fraction that fits in a u16, so timesteps like
+/// 1/30s come out exact.
+fn apng_delay(timestep: Duration) -> (u16, u16) {
+    let max = u16::MAX as u128;
+    let (mut p, mut q) = (timestep.as_nanos(), 1_000_000_000);
+    let (mut num, mut prev_num) = (1, 0);
+    let (mut den, mut prev_den) = (0, 1);
+    loop {
+        let a = p / q;
+        let next_num = a * num + prev_num;
+        let next_den = a * den + prev_den;
+        if next_num > max || next_den > max {
+            break;
+        }
+        prev_num = num;
+        num = next_num;
+        prev_den = den;
+        den = next_den;
+        let remainder = p % q;
+        if remainder == 0 {
+            break;
+        }
+        p = q;
+        q = remainder;
+    }
+    if den == 0 {
+        // Longer than u16::MAX seconds
+        (u16::MAX, 1)
+    } else {
+        (num as u16, den as u16)
+    }
+}
+
+fn closest_color(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |p: &[u8; 3]| {
+        (0..3)
+            .map(|i| {
+                let d = p[i] as i32 - color[i] as i32;
+                d * d
+            })
+            .sum::<i32>()
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, p)| distance(p))
+        .map(|(i, _)| i as u8)
+        .unwrap_or(0)
+}
+
+fn encode_png(image: &image::RgbaImage) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, image.width(), image.height());
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(image)?;
+    }
+    Ok(bytes)
+}
+
+/// Returns the data of every IDAT chunk in an encoded png.
+fn idat_chunks(png: &[u8]) -> Result<Vec<&[u8]>> {
+    // Skip the 8 byte signature
+    let mut rest = png.get(8..).context("Png is missing its signature")?;
+    let mut chunks = Vec::new();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let name = &rest[4..8];
+        let data = rest
+            .get(8..8 + length)
+            .context("Png chunk is longer than the file")?;
+        if name == b"IDAT" {
+            chunks.push(data);
+        }
+        // length + name + data + crc
+        rest = &rest[12 + length..];
+    }
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The name and data of every chunk in an encoded png.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut rest = &png[8..];
+        let mut chunks = Vec::new();
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let name = [rest[4], rest[5], rest[6], rest[7]];
+            chunks.push((name, &rest[8..8 + length]));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    fn be_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    #[test]
+    fn apng_frames_are_numbered_in_order() {
+        let recording = Recording {
+            frames: vec![
+                image::RgbaImage::from_pixel(3, 2, image::Rgba([255, 0, 0, 255])),
+                image::RgbaImage::from_pixel(3, 2, image::Rgba([0, 0, 255, 255])),
+            ],
+            timestep: Duration::from_millis(50),
+        };
+        let path = std::env::temp_dir().join(format!("recorder-{}.png", std::process::id()));
+        recording.save_apng(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let chunks = chunks(&bytes);
+        let names = chunks
+            .iter()
+            .map(|(name, _)| std::str::from_utf8(name).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "IEND"]
+        );
+
+        let (_, actl) = chunks[1];
+        assert_eq!(be_u32(&actl[0..]), 2, "frame count");
+        assert_eq!(be_u32(&actl[4..]), 0, "loops forever");
+
+        // fcTL and fdAT share one sequence, IDAT isn't part of it
+        let (_, first) = chunks[2];
+        let (_, second) = chunks[4];
+        let (_, fdat) = chunks[5];
+        assert_eq!(be_u32(&first[0..]), 0);
+        assert_eq!(be_u32(&second[0..]), 1);
+        assert_eq!(be_u32(&fdat[0..]), 2);
+        assert_eq!((be_u32(&second[4..]), be_u32(&second[8..])), (3, 2));
+        // 50ms is 1/20s
+        assert_eq!(u16::from_be_bytes([second[20], second[21]]), 1);
+        assert_eq!(u16::from_be_bytes([second[22], second[23]]), 20);
+
+        // Viewers without APNG support show the first frame
+        let decoder = png::Decoder::new(&bytes[..]);
+        let (info, mut reader) = decoder.read_info().unwrap();
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn gif_frames_use_the_global_palette() {
+        let mut frame = image::RgbaImage::from_pixel(2, 1, image::Rgba([200, 30, 30, 255]));
+        frame.put_pixel(1, 0, image::Rgba([250, 250, 240, 255]));
+        let recording = Recording {
+            frames: vec![frame.clone(), frame],
+            timestep: Duration::from_millis(50),
+        };
+        let palette = vec![[0, 0, 0], [255, 255, 255], [255, 0, 0]];
+        let options = GifOptions {
+            palette: GifPalette::Global(palette),
+            repeat: None,
+        };
+        let path = std::env::temp_dir().join(format!("recorder-{}.gif", std::process::id()));
+        recording.save_gif(&path, &options).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut reader = gif::Decoder::new(&bytes[..]).read_info().unwrap();
+        // Palettes are padded to a power of two
+        assert_eq!(
+            &reader.global_palette().unwrap()[..9],
+            &[0, 0, 0, 255, 255, 255, 255, 0, 0]
+        );
+        for _ in 0..2 {
+            let frame = reader.read_next_frame().unwrap().unwrap();
+            assert_eq!(&frame.buffer[..], &[2, 1]);
+            assert_eq!(frame.delay, 5);
+        }
+        assert!(reader.read_next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn gif_delays_round_to_the_nearest_hundredth() {
+        assert_eq!(gif_delay(Duration::from_millis(50)), 5);
+        assert_eq!(gif_delay(Duration::from_secs_f64(1.0 / 30.0)), 3);
+        assert_eq!(gif_delay(Duration::from_secs_f64(1.0 / 60.0)), 2);
+        assert_eq!(gif_delay(Duration::from_millis(15)), 2);
+        assert_eq!(gif_delay(Duration::from_millis(1)), 1);
+        assert_eq!(gif_delay(Duration::from_secs(0)), 1);
+        assert_eq!(gif_delay(Duration::from_secs(1000)), u16::MAX);
+    }
+
+    #[test]
+    fn apng_delays_are_fractions() {
+        assert_eq!(apng_delay(Duration::from_secs_f64(1.0 / 30.0)), (1, 30));
+        assert_eq!(apng_delay(Duration::from_secs_f64(1.0 / 60.0)), (1, 60));
+        assert_eq!(apng_delay(Duration::from_millis(50)), (1, 20));
+        assert_eq!(apng_delay(Duration::from_millis(1500)), (3, 2));
+        assert_eq!(apng_delay(Duration::from_secs(2)), (2, 1));
+        assert_eq!(apng_delay(Duration::from_secs(0)), (0, 1));
+        assert_eq!(apng_delay(Duration::from_secs(100_000)), (u16::MAX, 1));
+    }
+
+    #[test]
+    fn invalid_recordings_are_errors() {
+        let frame = image::RgbaImage::new(2, 2);
+        let recording = Recording {
+            frames: vec![frame.clone(), frame],
+            timestep: Duration::from_millis(50),
+        };
+        let path = std::env::temp_dir().join(format!("recorder-{}-speed.gif", std::process::id()));
+        for &speed in &[0, 31] {
+            let options = GifOptions {
+                palette: GifPalette::Quantized { speed },
+                repeat: None,
+            };
+            let message = recording.save_gif(&path, &options).unwrap_err().to_string();
+            assert!(message.contains("speed"));
+        }
+        // Nothing is written for invalid recordings
+        assert!(!path.exists());
+
+        let recording = Recording {
+            frames: vec![image::RgbaImage::new(2, 2), image::RgbaImage::new(3, 2)],
+            timestep: Duration::from_millis(50),
+        };
+        let path = std::env::temp_dir().join(format!("recorder-{}-sizes.png", std::process::id()));
+        let message = recording.save_apng(&path).unwrap_err().to_string();
+        assert!(message.contains("Frame 1 is 3x2"));
+        assert!(recording.save_gif(&path, &GifOptions::default()).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn colors_map_to_the_closest_palette_entry() {
+        let palette = [[0, 0, 0], [255, 255, 255], [255, 0, 0]];
+        assert_eq!(closest_color(&palette, [10, 10, 10]), 0);
+        assert_eq!(closest_color(&palette, [250, 250, 240]), 1);
+        assert_eq!(closest_color(&palette, [200, 30, 30]), 2);
+        // Ties go to the first entry
+        assert_eq!(closest_color(&[[0; 3], [0; 3]], [0; 3]), 0);
+        assert_eq!(closest_color(&[], [1, 2, 3]), 0);
+    }
+}
@@ -0,0 +1,779 @@
+//! Reads the interface of compiled SPIR-V shaders, so that bind group
+//! layouts and vertex buffers don't have to be kept in sync with the
+//! GLSL by hand.
+//!
+//! This only looks at the handful of instructions that describe vertex
+//! inputs and resource bindings, and it only understands the resources
+//! the framework's shaders use: uniform and storage buffers, sampled
+//! textures and samplers. Anything else is an error, and the layout has
+//! to be written by hand.
+
+use anyhow::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// Opcodes
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_VARIABLE: u32 = 59;
+const OP_LOAD: u32 = 61;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_SAMPLED_IMAGE: u32 = 86;
+
+// Decorations
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_NON_WRITABLE: u32 = 24;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+/// The type of a single vertex shader input, ie. `vec3` is
+/// `{ kind: Float, components: 3 }`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InputType {
+    pub kind: ScalarKind,
+    pub components: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScalarKind {
+    Float,
+    Sint,
+    Uint,
+}
+
+impl InputType {
+    /// What the shader sees when reading an attribute with `format`.
+    /// Normalized formats are read as floats.
+    pub fn of_format(format: wgpu::VertexFormat) -> Self {
+        use wgpu::VertexFormat::*;
+        let (kind, components) = match format {
+            Uchar2Norm | Char2Norm | Ushort2Norm | Short2Norm | Half2 | Float2 => {
+                (ScalarKind::Float, 2)
+            }
+            Uchar4Norm | Char4Norm | Ushort4Norm | Short4Norm | Half4 | Float4 => {
+                (ScalarKind::Float, 4)
+            }
+            Float => (ScalarKind::Float, 1),
+            Float3 => (ScalarKind::Float, 3),
+            Uchar2 | Ushort2 | Uint2 => (ScalarKind::Uint, 2),
+            Uchar4 | Ushort4 | Uint4 => (ScalarKind::Uint, 4),
+            Uint => (ScalarKind::Uint, 1),
+            Uint3 => (ScalarKind::Uint, 3),
+            Char2 | Short2 | Int2 => (ScalarKind::Sint, 2),
+            Char4 | Short4 | Int4 => (ScalarKind::Sint, 4),
+            Int => (ScalarKind::Sint, 1),
+            Int3 => (ScalarKind::Sint, 3),
+        };
+        Self { kind, components }
+    }
+}
+
+impl std::fmt::Display for InputType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let prefix = match self.kind {
+            ScalarKind::Float => "",
+            ScalarKind::Sint => "i",
+            ScalarKind::Uint => "u",
+        };
+        match (self.kind, self.components) {
+            (ScalarKind::Float, 1) => write!(f, "float"),
+            (ScalarKind::Sint, 1) => write!(f, "int"),
+            (ScalarKind::Uint, 1) => write!(f, "uint"),
+            (_, n) => write!(f, "{}vec{}", prefix, n),
+        }
+    }
+}
+
+/// A `layout(location=...) in` variable of a vertex shader. Matrices
+/// take up one location per column, so they show up as several
+/// inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderInput {
+    pub location: u32,
+    pub name: Option<String>,
+    pub ty: InputType,
+}
+
+/// A `layout(set=..., binding=...) uniform` variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub name: Option<String>,
+    /// Buffers are never reported as dynamic, and don't have a
+    /// `min_binding_size`, as neither can be known from the shader.
+    pub ty: wgpu::BindingType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryPoint {
+    pub name: String,
+    pub stage: wgpu::ShaderStage,
+}
+
+/// Everything the pipeline needs to know about a shader module.
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<EntryPoint>,
+    /// Only filled in for vertex shaders.
+    pub inputs: Vec<ShaderInput>,
+    pub bindings: Vec<ShaderBinding>,
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Scalar(ScalarKind),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Image {
+        sampled_type: u32,
+        dim: u32,
+        depth: bool,
+        arrayed: bool,
+        multisampled: bool,
+        storage: bool,
+    },
+    Sampler,
+    SampledImage(u32),
+    Array,
+    Struct,
+    Pointer(u32),
+}
+
+#[derive(Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    buffer_block: bool,
+}
+
+/// The parts of a module that the reflection needs, by result id.
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    entry_points: Vec<EntryPoint>,
+    types: HashMap<u32, Type>,
+    decorations: HashMap<u32, Decorations>,
+    /// Structs of `readonly buffer`s
+    readonly_structs: HashSet<u32>,
+    /// Variable id, pointer type and storage class
+    variables: Vec<(u32, u32, u32)>,
+    loads: HashMap<u32, u32>,
+    comparison_samplers: HashSet<u32>,
+}
+
+impl ShaderReflection {
+    /// Reflects SPIR-V that was loaded as bytes, ie. with
+    /// `include_bytes!`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() % 4 != 0 {
+            bail!("SPIR-V length must be a multiple of 4, got {}", bytes.len());
+        }
+        let words = bytes
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect::<Vec<_>>();
+        Self::from_spirv(&words)
+    }
+
+    pub fn from_spirv(words: &[u32]) -> Result<Self> {
+        let module = Module::parse(words)?;
+        let is_vertex_shader = module
+            .entry_points
+            .iter()
+            .any(|e| e.stage == wgpu::ShaderStage::VERTEX);
+
+        let mut inputs = Vec::new();
+        let mut bindings = Vec::new();
+        for &(id, pointer_type, storage_class) in &module.variables {
+            let d = match module.decorations.get(&id) {
+                Some(d) => d,
+                None => continue,
+            };
+            let name = module.names.get(&id).cloned().filter(|n| !n.is_empty());
+            let pointee = match module.types.get(&pointer_type) {
+                Some(Type::Pointer(ty)) => *ty,
+                _ => continue,
+            };
+
+            match (storage_class, d.location, d.set, d.binding) {
+                // Built-ins like gl_VertexIndex don't have a location
+                (STORAGE_INPUT, Some(location), _, _) if is_vertex_shader => {
+                    let (columns, ty) = module.input_type(pointee).with_context(|| {
+                        format!("Unsupported type for vertex input at location {}", location)
+                    })?;
+                    for column in 0..columns {
+                        inputs.push(ShaderInput {
+                            location: location + column,
+                            name: name.clone(),
+                            ty,
+                        });
+                    }
+                }
+                (_, _, Some(set), Some(binding))
+                    if storage_class == STORAGE_UNIFORM_CONSTANT
+                        || storage_class == STORAGE_UNIFORM
+                        || storage_class == STORAGE_STORAGE_BUFFER =>
+                {
+                    let ty = module
+                        .binding_type(id, pointee, storage_class)
+                        .with_context(|| {
+                            format!(
+                                "Unable to reflect set={}, binding={} ({})",
+                                set,
+                                binding,
+                                name.as_deref().unwrap_or("unnamed")
+                            )
+                        })?;
+                    if let Some(ty) = ty {
+                        bindings.push(ShaderBinding {
+                            set,
+                            binding,
+                            name,
+                            ty,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        inputs.sort_by_key(|i| i.location);
+        bindings.sort_by_key(|b| (b.set, b.binding));
+
+        Ok(Self {
+            entry_points: module.entry_points,
+            inputs,
+            bindings,
+        })
+    }
+
+    /// All the stages this module has entry points for.
+    pub fn stages(&self) -> wgpu::ShaderStage {
+        self.entry_points
+            .iter()
+            .fold(wgpu::ShaderStage::NONE, |stages, e| stages | e.stage)
+    }
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self> {
+        if words.len() < 5 || words[0] != SPIRV_MAGIC {
+            bail!("Not a SPIR-V module");
+        }
+
+        let mut module = Self::default();
+        let mut rest = &words[5..];
+        while !rest.is_empty() {
+            let word_count = (rest[0] >> 16) as usize;
+            let opcode = rest[0] & 0xffff;
+            if word_count == 0 || word_count > rest.len() {
+                bail!("Malformed SPIR-V instruction (opcode {})", opcode);
+            }
+            let ops = &rest[1..word_count];
+            rest = &rest[word_count..];
+
+            let ty = match opcode {
+                OP_TYPE_INT if ops.len() >= 3 && ops[2] == 1 => Type::Scalar(ScalarKind::Sint),
+                OP_TYPE_INT if ops.len() >= 3 => Type::Scalar(ScalarKind::Uint),
+                OP_TYPE_FLOAT if !ops.is_empty() => Type::Scalar(ScalarKind::Float),
+                OP_TYPE_VECTOR if ops.len() >= 3 => Type::Vector(ops[1], ops[2]),
+                OP_TYPE_MATRIX if ops.len() >= 3 => Type::Matrix(ops[1], ops[2]),
+                OP_TYPE_IMAGE if ops.len() >= 7 => Type::Image {
+                    sampled_type: ops[1],
+                    dim: ops[2],
+                    depth: ops[3] == 1,
+                    arrayed: ops[4] == 1,
+                    multisampled: ops[5] == 1,
+                    storage: ops[6] == 2,
+                },
+                OP_TYPE_SAMPLER if !ops.is_empty() => Type::Sampler,
+                OP_TYPE_SAMPLED_IMAGE if ops.len() >= 2 => Type::SampledImage(ops[1]),
+                OP_TYPE_ARRAY | OP_TYPE_RUNTIME_ARRAY if !ops.is_empty() => Type::Array,
+                OP_TYPE_STRUCT if !ops.is_empty() => Type::Struct,
+                OP_TYPE_POINTER if ops.len() >= 3 => Type::Pointer(ops[2]),
+                _ => {
+                    module.parse_instruction(opcode, ops);
+                    continue;
+                }
+            };
+            module.types.insert(ops[0], ty);
+        }
+        Ok(module)
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, ops: &[u32]) {
+        match opcode {
+            OP_NAME if !ops.is_empty() => {
+                self.names.insert(ops[0], parse_string(&ops[1..]));
+            }
+            OP_ENTRY_POINT if ops.len() >= 3 => {
+                let stage = match ops[0] {
+                    0 => wgpu::ShaderStage::VERTEX,
+                    4 => wgpu::ShaderStage::FRAGMENT,
+                    5 => wgpu::ShaderStage::COMPUTE,
+                    _ => wgpu::ShaderStage::NONE,
+                };
+                self.entry_points.push(EntryPoint {
+                    name: parse_string(&ops[2..]),
+                    stage,
+                });
+            }
+            OP_VARIABLE if ops.len() >= 3 => self.variables.push((ops[1], ops[0], ops[2])),
+            OP_LOAD if ops.len() >= 3 => {
+                self.loads.insert(ops[1], ops[2]);
+            }
+            OP_SAMPLED_IMAGE if ops.len() >= 4 => {
+                // A sampler combined with a depth image, ie. with
+                // sampler2DShadow(t, s), is a comparison sampler
+                let depth = match self.types.get(&ops[0]) {
+                    Some(Type::SampledImage(image)) => match self.types.get(image) {
+                        Some(Type::Image { depth, .. }) => *depth,
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                if let (true, Some(sampler)) = (depth, self.loads.get(&ops[3])) {
+                    self.comparison_samplers.insert(*sampler);
+                }
+            }
+            OP_DECORATE if ops.len() >= 2 => {
+                let d = self.decorations.entry(ops[0]).or_default();
+                let value = ops.get(2).copied();
+                match ops[1] {
+                    DECORATION_BUFFER_BLOCK => d.buffer_block = true,
+                    DECORATION_LOCATION => d.location = value,
+                    DECORATION_BINDING => d.binding = value,
+                    DECORATION_DESCRIPTOR_SET => d.set = value,
+                    _ => {}
+                }
+            }
+            // `readonly buffer` marks every member as non writable
+            OP_MEMBER_DECORATE if ops.len() >= 3 && ops[2] == DECORATION_NON_WRITABLE => {
+                self.readonly_structs.insert(ops[0]);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the number of locations the type takes up, and the type
+    /// of each one.
+    fn input_type(&self, id: u32) -> Option<(u32, InputType)> {
+        match self.types.get(&id)? {
+            Type::Scalar(kind) => Some((
+                1,
+                InputType {
+                    kind: *kind,
+                    components: 1,
+                },
+            )),
+            Type::Vector(component, count) => match self.types.get(component)? {
+                Type::Scalar(kind) => Some((
+                    1,
+                    InputType {
+                        kind: *kind,
+                        components: *count,
+                    },
+                )),
+                _ => None,
+            },
+            Type::Matrix(column, count) => {
+                let (_, column) = self.input_type(*column)?;
+                Some((*count, column))
+            }
+            _ => None,
+        }
+    }
+
+    /// The binding type of a uniform variable, or `None` for variables
+    /// that aren't resources.
+    fn binding_type(
+        &self,
+        variable: u32,
+        ty: u32,
+        storage_class: u32,
+    ) -> Result<Option<wgpu::BindingType>> {
+        let buffer_block = self
+            .decorations
+            .get(&ty)
+            .map(|d| d.buffer_block)
+            .unwrap_or(false);
+        Ok(Some(match self.types.get(&ty) {
+            Some(Type::Struct) if storage_class == STORAGE_STORAGE_BUFFER || buffer_block => {
+                wgpu::BindingType::StorageBuffer {
+                    dynamic: false,
+                    min_binding_size: None,
+                    readonly: self.readonly_structs.contains(&ty),
+                }
+            }
+            Some(Type::Struct) => wgpu::BindingType::UniformBuffer {
+                dynamic: false,
+                min_binding_size: None,
+            },
+            Some(Type::Sampler) => wgpu::BindingType::Sampler {
+                comparison: self.comparison_samplers.contains(&variable),
+            },
+            Some(Type::Image { storage: true, .. }) => bail!("Storage textures aren't supported"),
+            Some(Type::Image {
+                sampled_type,
+                dim,
+                arrayed,
+                multisampled,
+                ..
+            }) => wgpu::BindingType::SampledTexture {
+                dimension: view_dimension(*dim, *arrayed)
+                    .context("Unsupported texture dimension")?,
+                component_type: match self.types.get(sampled_type) {
+                    Some(Type::Scalar(ScalarKind::Sint)) => wgpu::TextureComponentType::Sint,
+                    Some(Type::Scalar(ScalarKind::Uint)) => wgpu::TextureComponentType::Uint,
+                    _ => wgpu::TextureComponentType::Float,
+                },
+                multisampled: *multisampled,
+            },
+            Some(Type::Array) => bail!("Arrays of resources aren't supported"),
+            Some(Type::SampledImage(_)) => bail!(
+                "Combined image samplers aren't supported. wgpu needs separate texture and \
+                 sampler bindings."
+            ),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// The entries of the bind group at index `set`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedBindGroupLayout {
+    pub set: u32,
+    pub entries: Vec<wgpu::BindGroupLayoutEntry>,
+}
+
+impl ReflectedBindGroupLayout {
+    pub fn descriptor<'a>(&'a self, label: Option<&'a str>) -> wgpu::BindGroupLayoutDescriptor<'a> {
+        wgpu::BindGroupLayoutDescriptor {
+            label,
+            entries: &self.entries,
+        }
+    }
+
+    pub fn create(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&self.descriptor(label))
+    }
+}
+
+/// Merges the bindings of every shader in a pipeline into one layout
+/// per bind group. A binding that is used by several shaders is
+/// visible to all of them. The result has an entry for every set up
+/// to the highest one used, so it can be indexed by set.
+pub fn reflect_bind_group_layouts(
+    shaders: &[&ShaderReflection],
+) -> Result<Vec<ReflectedBindGroupLayout>> {
+    let mut sets: BTreeMap<u32, BTreeMap<u32, wgpu::BindGroupLayoutEntry>> = BTreeMap::new();
+    for shader in shaders {
+        let visibility = shader.stages();
+        for b in &shader.bindings {
+            let entries = sets.entry(b.set).or_default();
+            match entries.get_mut(&b.binding) {
+                Some(entry) => {
+                    if entry.ty != b.ty {
+                        bail!(
+                            "Shaders disagree about set={}, binding={}: {:?} vs {:?}",
+                            b.set,
+                            b.binding,
+                            entry.ty,
+                            b.ty
+                        );
+                    }
+                    entry.visibility |= visibility;
+                }
+                None => {
+                    entries.insert(
+                        b.binding,
+                        wgpu::BindGroupLayoutEntry {
+                            binding: b.binding,
+                            visibility,
+                            ty: b.ty.clone(),
+                            count: None,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    let max_set = match sets.keys().next_back() {
+        Some(set) => *set,
+        None => return Ok(Vec::new()),
+    };
+    Ok((0..=max_set)
+        .map(|set| ReflectedBindGroupLayout {
+            set,
+            entries: sets
+                .remove(&set)
+                .map(|entries| entries.into_iter().map(|(_, e)| e).collect())
+                .unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Checks that every input of a vertex shader is fed by an attribute
+/// of the right type.
+pub fn validate_vertex_inputs(
+    inputs: &[ShaderInput],
+    buffers: &[wgpu::VertexBufferDescriptor],
+) -> Result<()> {
+    let mut attributes = HashMap::new();
+    for (buffer_index, buffer) in buffers.iter().enumerate() {
+        for attribute in buffer.attributes {
+            if let Some(previous) =
+                attributes.insert(attribute.shader_location, (buffer_index, attribute.format))
+            {
+                bail!(
+                    "Location {} is used by vertex buffers {} and {}",
+                    attribute.shader_location,
+                    previous.0,
+                    buffer_index
+                );
+            }
+        }
+    }
+
+    let mut errors = Vec::new();
+    for input in inputs {
+        let name = input.name.as_deref().unwrap_or("unnamed");
+        match attributes.get(&input.location) {
+            None => errors.push(format!(
+                "location {} ({}: {}) has no matching vertex attribute",
+                input.location, name, input.ty
+            )),
+            Some((buffer_index, format)) => {
+                let provided = InputType::of_format(*format);
+                if provided.kind != input.ty.kind {
+                    errors.push(format!(
+                        "location {} ({}: {}) is fed {:?} by vertex buffer {}",
+                        input.location, name, input.ty, format, buffer_index
+                    ));
+                } else if provided.components != input.ty.components {
+                    // Vulkan fills in or drops the extra components, so
+                    // this works, but it's usually a mistake
+                    log::warn!(
+                        "Vertex shader input at location {} ({}: {}) is fed {:?}",
+                        input.location,
+                        name,
+                        input.ty,
+                        format
+                    );
+                }
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!(
+            "The vertex buffers don't match the vertex shader:\n  {}",
+            errors.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// SPIR-V strings are nul terminated UTF-8 packed into words.
+fn parse_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes().to_vec())
+        .take_while(|b| *b != 0)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn view_dimension(dim: u32, arrayed: bool) -> Option<wgpu::TextureViewDimension> {
+    Some(match (dim, arrayed) {
+        (0, false) => wgpu::TextureViewDimension::D1,
+        (1, false) => wgpu::TextureViewDimension::D2,
+        (1, true) => wgpu::TextureViewDimension::D2Array,
+        (2, false) => wgpu::TextureViewDimension::D3,
+        (3, false) => wgpu::TextureViewDimension::Cube,
+        (3, true) => wgpu::TextureViewDimension::CubeArray,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn compile(src: &str, kind: shaderc::ShaderKind) -> ShaderReflection {
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        let spirv = compiler
+            .compile_into_spirv(src, kind, "test.glsl", "main", None)
+            .unwrap();
+        ShaderReflection::from_spirv(spirv.as_binary()).unwrap()
+    }
+
+    const VERTEX_SHADER: &str = r#"
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+layout(location=5) in mat4 a_model;
+
+layout(set=1, binding=0) uniform Uniforms {
+    mat4 u_view_proj;
+};
+
+void main() {
+    gl_Position = u_view_proj * a_model * vec4(a_position + vec3(a_tex_coords, 0.0), 1.0);
+}
+"#;
+
+    const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_diffuse;
+layout(set=0, binding=1) uniform sampler s_diffuse;
+layout(set=0, binding=2) uniform texture2D t_shadow;
+layout(set=0, binding=3) uniform samplerShadow s_shadow;
+layout(set=1, binding=0) uniform Uniforms {
+    mat4 u_view_proj;
+};
+layout(set=2, binding=0) readonly buffer Lights {
+    vec4 lights[4];
+};
+
+void main() {
+    float shadow = texture(sampler2DShadow(t_shadow, s_shadow), vec3(0.5));
+    f_color = texture(sampler2D(t_diffuse, s_diffuse), vec2(0.5)) * shadow + lights[0];
+}
+"#;
+
+    #[test]
+    fn vertex_inputs() {
+        let vs = compile(VERTEX_SHADER, shaderc::ShaderKind::Vertex);
+        let locations = vs.inputs.iter().map(|i| i.location).collect::<Vec<_>>();
+        assert_eq!(locations, vec![0, 1, 5, 6, 7, 8]);
+        assert_eq!(vs.inputs[0].name.as_deref(), Some("a_position"));
+        assert_eq!(
+            vs.inputs[0].ty,
+            InputType::of_format(wgpu::VertexFormat::Float3)
+        );
+        assert_eq!(
+            vs.inputs[5].ty,
+            InputType::of_format(wgpu::VertexFormat::Float4)
+        );
+    }
+
+    #[test]
+    fn bind_group_layouts() {
+        let vs = compile(VERTEX_SHADER, shaderc::ShaderKind::Vertex);
+        let fs = compile(FRAGMENT_SHADER, shaderc::ShaderKind::Fragment);
+        let layouts = reflect_bind_group_layouts(&[&vs, &fs]).unwrap();
+        assert_eq!(layouts.len(), 3);
+
+        let textures = &layouts[0].entries;
+        assert_eq!(textures.len(), 4);
+        assert_eq!(
+            textures[1].ty,
+            wgpu::BindingType::Sampler { comparison: false }
+        );
+        assert_eq!(
+            textures[3].ty,
+            wgpu::BindingType::Sampler { comparison: true }
+        );
+        assert_eq!(textures[0].visibility, wgpu::ShaderStage::FRAGMENT);
+
+        let uniforms = &layouts[1].entries;
+        assert_eq!(
+            uniforms[0].visibility,
+            wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT
+        );
+
+        let lights = &layouts[2].entries;
+        assert_eq!(
+            lights[0].ty,
+            wgpu::BindingType::StorageBuffer {
+                dynamic: false,
+                min_binding_size: None,
+                readonly: true,
+            }
+        );
+    }
+
+    #[test]
+    fn mismatched_vertex_buffer() {
+        let vs = compile(VERTEX_SHADER, shaderc::ShaderKind::Vertex);
+        let attributes = [
+            wgpu::VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float3,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: 12,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Uint2,
+            },
+        ];
+        let buffer = wgpu::VertexBufferDescriptor {
+            stride: 20,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &attributes,
+        };
+        let message = validate_vertex_inputs(&vs.inputs, &[buffer])
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("location 1 (a_tex_coords: vec2) is fed Uint2"));
+        assert!(message.contains("location 5 (a_model: vec4) has no matching vertex attribute"));
+    }
+
+    #[test]
+    fn unsupported_resources_are_an_error() {
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        let spirv = compiler
+            .compile_into_spirv(
+                r#"
+#version 450
+
+layout(local_size_x=1) in;
+layout(set=0, binding=0, rgba8) uniform writeonly image2D t_output;
+
+void main() {
+    imageStore(t_output, ivec2(0), vec4(1.0));
+}
+"#,
+                shaderc::ShaderKind::Compute,
+                "test.comp",
+                "main",
+                None,
+            )
+            .unwrap();
+        let message = format!(
+            "{:#}",
+            ShaderReflection::from_spirv(spirv.as_binary()).unwrap_err()
+        );
+        assert!(message.contains("set=0, binding=0 (t_output)"));
+        assert!(message.contains("Storage textures aren't supported"));
+    }
+}
@@ -0,0 +1,541 @@
+use anyhow::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::rc::Rc;
+
+use crate::sampler::{SamplerCache, SamplerConfig};
+use crate::texture::Texture;
+use crate::Display;
+
+/// The name passes use to read or write the frame that's presented.
+pub const SWAP_CHAIN: &str = "swap_chain";
+
+/// How big a transient attachment is.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AttachmentSize {
+    /// The same size as the [Display].
+    Display,
+    /// The size of the [Display] multiplied by a factor, such as 0.5
+    /// for a half resolution bloom target.
+    Scaled(f32),
+    /// Doesn't change when the [Display] is resized.
+    Fixed { width: u32, height: u32 },
+}
+
+impl AttachmentSize {
+    fn resolve(&self, display_width: u32, display_height: u32) -> (u32, u32) {
+        let (width, height) = match *self {
+            AttachmentSize::Display => (display_width, display_height),
+            AttachmentSize::Scaled(scale) => (
+                (display_width as f32 * scale) as u32,
+                (display_height as f32 * scale) as u32,
+            ),
+            AttachmentSize::Fixed { width, height } => (width, height),
+        };
+        // wgpu doesn't allow empty textures, which is what a minimized
+        // window would give us
+        (width.max(1), height.max(1))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AttachmentDesc {
+    pub format: wgpu::TextureFormat,
+    pub size: AttachmentSize,
+    pub usage: wgpu::TextureUsage,
+    pub sample_count: u32,
+}
+
+impl AttachmentDesc {
+    /// A display sized color target that later passes can sample.
+    pub fn color(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            size: AttachmentSize::Display,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            sample_count: 1,
+        }
+    }
+
+    /// A display sized depth buffer that later passes can sample.
+    pub fn depth() -> Self {
+        Self::color(Texture::DEPTH_FORMAT)
+    }
+
+    pub fn size(mut self, size: AttachmentSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn usage(mut self, usage: wgpu::TextureUsage) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+}
+
+/// Textures that only live for the length of a frame, such as depth
+/// buffers and HDR targets. [Display::resize] resizes them along with
+/// the swap chain.
+pub struct Attachments {
+    textures: HashMap<String, (AttachmentDesc, Texture<'static>)>,
+    width: u32,
+    height: u32,
+    generation: u64,
+    samplers: SamplerCache,
+}
+
+impl Attachments {
+    pub fn new(width: u32, height: u32, samplers: SamplerCache) -> Self {
+        Self {
+            textures: HashMap::new(),
+            width,
+            height,
+            generation: 0,
+            samplers,
+        }
+    }
+
+    fn create_texture(&self, device: &wgpu::Device, desc: &AttachmentDesc) -> Texture<'static> {
+        let (width, height) = desc.size.resolve(self.width, self.height);
+        Texture::from_descriptor(
+            device,
+            wgpu::TextureDescriptor {
+                label: Some("Transient Attachment"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth: 1,
+                },
+                mip_level_count: 1,
+                sample_count: desc.sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: desc.format,
+                usage: desc.usage,
+            },
+            &self.samplers,
+            &SamplerConfig::default(),
+        )
+    }
+
+    /// Creates the attachment, unless one with the same description
+    /// already exists.
+    pub fn declare(&mut self, device: &wgpu::Device, name: &str, desc: AttachmentDesc) {
+        if let Some((existing, _)) = self.textures.get(name) {
+            if *existing == desc {
+                return;
+            }
+        }
+        let texture = self.create_texture(device, &desc);
+        self.textures.insert(name.to_string(), (desc, texture));
+        self.generation += 1;
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Texture<'static>> {
+        self.textures.get(name).map(|(_, texture)| texture)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.textures.contains_key(name)
+    }
+
+    /// Changes every time an attachment is created or resized. Bind
+    /// groups that use attachments need to be recreated when it does.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Recreates the attachments whose size depends on the display's.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+
+        let resized = self
+            .textures
+            .iter()
+            .filter(|(_, (desc, _))| !matches!(desc.size, AttachmentSize::Fixed { .. }))
+            .map(|(name, (desc, _))| (name.clone(), *desc))
+            .collect::<Vec<_>>();
+        for (name, desc) in resized {
+            let texture = self.create_texture(device, &desc);
+            self.textures.insert(name, (desc, texture));
+        }
+        self.generation += 1;
+    }
+
+    /// Creates every attachment again on `device`, after the one they
+    /// were made with has been lost. `samplers` is the cache for the new
+    /// device.
+    pub fn recreate(&mut self, device: &wgpu::Device, samplers: SamplerCache) {
+        self.samplers = samplers;
+        let descs = self
+            .textures
+            .iter()
+            .map(|(name, (desc, _))| (name.clone(), *desc))
+            .collect::<Vec<_>>();
+        for (name, desc) in descs {
+            let texture = self.create_texture(device, &desc);
+            self.textures.insert(name, (desc, texture));
+        }
+        self.generation += 1;
+    }
+}
+
+/// What a [GraphPass] gets to record its commands with.
+pub struct PassContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub attachments: &'a Attachments,
+    pub sc_desc: &'a wgpu::SwapChainDescriptor,
+    frame: &'a wgpu::TextureView,
+}
+
+impl<'a> PassContext<'a> {
+    /// The view of an attachment, or of the frame for [SWAP_CHAIN].
+    pub fn view(&self, name: &str) -> Result<&'a wgpu::TextureView> {
+        if name == SWAP_CHAIN {
+            return Ok(self.frame);
+        }
+        self.attachments
+            .get(name)
+            .map(|texture| &texture.view)
+            .with_context(|| format!("No attachment called {}", name))
+    }
+
+    pub fn texture(&self, name: &str) -> Result<&'a Texture<'static>> {
+        self.attachments
+            .get(name)
+            .with_context(|| format!("No attachment called {}", name))
+    }
+}
+
+/// One step of a frame, such as the shadow, main, post-processing or UI
+/// pass. `S` is whatever state the passes share, usually the demo's
+/// scene.
+pub trait GraphPass<S> {
+    fn execute(&mut self, ctx: &mut PassContext, state: &mut S) -> Result<()>;
+}
+
+/// The graph owns its passes, so keep a clone of the `Rc` to change a
+/// pass after adding it.
+impl<S, P: GraphPass<S>> GraphPass<S> for Rc<RefCell<P>> {
+    fn execute(&mut self, ctx: &mut PassContext, state: &mut S) -> Result<()> {
+        self.borrow_mut().execute(ctx, state)
+    }
+}
+
+pub struct PassNode<S> {
+    name: String,
+    reads: Vec<String>,
+    writes: Vec<String>,
+    pass: Box<dyn GraphPass<S>>,
+}
+
+impl<S> PassNode<S> {
+    /// Textures the pass samples or loads. The pass runs after every
+    /// pass that writes them.
+    pub fn reads(&mut self, names: &[&str]) -> &mut Self {
+        self.reads.extend(names.iter().map(|n| n.to_string()));
+        self
+    }
+
+    /// Textures the pass renders to. Writing a texture the pass also
+    /// reads means drawing on top of it, which happens after the passes
+    /// that only write it.
+    pub fn writes(&mut self, names: &[&str]) -> &mut Self {
+        self.writes.extend(names.iter().map(|n| n.to_string()));
+        self
+    }
+}
+
+/// Runs a set of passes in an order worked out from the textures they
+/// read and write, and owns the transient attachments they share
+/// through the [Display].
+///
+/// ```no_run
+/// # use framework::*;
+/// struct Main;
+/// struct Tonemap;
+///
+/// impl GraphPass<()> for Main {
+///     fn execute(&mut self, ctx: &mut PassContext, _: &mut ()) -> anyhow::Result<()> {
+///         let _hdr = ctx.view("hdr")?;
+///         Ok(())
+///     }
+/// }
+///
+/// impl GraphPass<()> for Tonemap {
+///     fn execute(&mut self, ctx: &mut PassContext, _: &mut ()) -> anyhow::Result<()> {
+///         let _frame = ctx.view(SWAP_CHAIN)?;
+///         Ok(())
+///     }
+/// }
+///
+/// # fn passes(display: &mut Display) -> anyhow::Result<()> {
+/// let mut graph = RenderGraph::new();
+/// graph.attachment("hdr", AttachmentDesc::color(wgpu::TextureFormat::Rgba16Float));
+/// graph
+///     .add_pass("tonemap", Tonemap)
+///     .reads(&["hdr"])
+///     .writes(&[SWAP_CHAIN]);
+/// graph.add_pass("main", Main).writes(&["hdr"]);
+/// // Runs main, then tonemap
+/// graph.execute(display, &mut ())?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RenderGraph<S> {
+    nodes: Vec<PassNode<S>>,
+    attachments: Vec<(String, AttachmentDesc)>,
+    external: HashSet<String>,
+}
+
+impl<S> RenderGraph<S> {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            attachments: Vec::new(),
+            external: HashSet::new(),
+        }
+    }
+
+    /// Declares a transient attachment. It's created on the
+    /// [Display] the first time the graph runs.
+    pub fn attachment(&mut self, name: &str, desc: AttachmentDesc) -> &mut Self {
+        self.attachments.retain(|(n, _)| n != name);
+        self.attachments.push((name.to_string(), desc));
+        self
+    }
+
+    /// Declares a texture that passes read but that the graph doesn't
+    /// own, like a shadow map.
+    pub fn external(&mut self, name: &str) -> &mut Self {
+        self.external.insert(name.to_string());
+        self
+    }
+
+    pub fn add_pass<P: GraphPass<S> + 'static>(&mut self, name: &str, pass: P) -> &mut PassNode<S> {
+        self.nodes.push(PassNode {
+            name: name.to_string(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            pass: Box::new(pass),
+        });
+        self.nodes.last_mut().unwrap()
+    }
+
+    pub fn remove_pass(&mut self, name: &str) {
+        self.nodes.retain(|node| node.name != name);
+    }
+
+    /// The names of the passes in the order they run.
+    pub fn pass_order(&self) -> Result<Vec<&str>> {
+        let order = self.compile()?;
+        Ok(order.iter().map(|&i| self.nodes[i].name.as_str()).collect())
+    }
+
+    fn compile(&self) -> Result<Vec<usize>> {
+        // Passes can be changed through the PassNode returned by
+        // add_pass, so the order is worked out again every frame. It's
+        // cheap for the handful of passes a frame has.
+        let mut known = self.external.clone();
+        known.insert(SWAP_CHAIN.to_string());
+        known.extend(self.attachments.iter().map(|(name, _)| name.clone()));
+        sort_passes(
+            &self
+                .nodes
+                .iter()
+                .map(|n| (n.name.as_str(), &n.reads[..], &n.writes[..]))
+                .collect::<Vec<_>>(),
+            &known,
+        )
+    }
+
+    /// Records every pass into one command encoder and submits it.
+    pub fn execute(&mut self, display: &mut Display, state: &mut S) -> Result<()> {
+        let order = self.compile()?;
+        for (name, desc) in &self.attachments {
+            display.attachments.declare(&display.device, name, *desc);
+        }
+
+        let frame = display.get_current_frame()?;
+        let mut encoder = display
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("RenderGraph"),
+            });
+        for i in order {
+            let node = &mut self.nodes[i];
+            let mut ctx = PassContext {
+                device: &display.device,
+                queue: &display.queue,
+                encoder: &mut encoder,
+                attachments: &display.attachments,
+                sc_desc: &display.sc_desc,
+                frame: frame.view(),
+            };
+            node.pass
+                .execute(&mut ctx, state)
+                .with_context(|| format!("Pass {} failed", node.name))?;
+        }
+        display.queue.submit(iter::once(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl<S> Default for RenderGraph<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Orders passes so every pass runs after the passes that write what it
+/// reads. For each texture the passes that only write it run first, in
+/// the order they were added, then the ones that draw on top of it, then
+/// the ones that only read it. Passes that don't depend on each other
+/// stay in the order they were added.
+fn sort_passes(
+    passes: &[(&str, &[String], &[String])],
+    known: &HashSet<String>,
+) -> Result<Vec<usize>> {
+    let mut edges = vec![Vec::new(); passes.len()];
+    let mut dependencies = vec![0; passes.len()];
+    let mut add_edge = |from: usize, to: usize| {
+        if from != to && !edges[from].contains(&to) {
+            edges[from].push(to);
+            dependencies[to] += 1;
+        }
+    };
+
+    let resources = passes
+        .iter()
+        .flat_map(|(_, reads, writes)| reads.iter().chain(writes.iter()))
+        .collect::<HashSet<_>>();
+    for resource in resources {
+        let touches = |list: &[String]| list.iter().any(|r| r == resource);
+        let writers = (0..passes.len())
+            .filter(|&i| touches(passes[i].2) && !touches(passes[i].1))
+            .chain((0..passes.len()).filter(|&i| touches(passes[i].2) && touches(passes[i].1)))
+            .collect::<Vec<_>>();
+        let readers = (0..passes.len())
+            .filter(|&i| touches(passes[i].1) && !touches(passes[i].2))
+            .collect::<Vec<_>>();
+
+        if writers.is_empty() && !readers.is_empty() && !known.contains(resource) {
+            bail!(
+                "Pass {} reads {}, but no pass writes it and it isn't an attachment",
+                passes[readers[0]].0,
+                resource
+            );
+        }
+        for pair in writers.windows(2) {
+            add_edge(pair[0], pair[1]);
+        }
+        if let Some(&last) = writers.last() {
+            for &reader in &readers {
+                add_edge(last, reader);
+            }
+        }
+    }
+
+    // Kahn's algorithm, always taking the earliest added pass that's
+    // ready
+    let mut order = Vec::with_capacity(passes.len());
+    let mut ready = (0..passes.len())
+        .filter(|&i| dependencies[i] == 0)
+        .collect::<Vec<_>>();
+    while !ready.is_empty() {
+        ready.sort_unstable_by(|a, b| b.cmp(a));
+        let i = ready.pop().unwrap();
+        order.push(i);
+        for &next in &edges[i] {
+            dependencies[next] -= 1;
+            if dependencies[next] == 0 {
+                ready.push(next);
+            }
+        }
+    }
+
+    if order.len() != passes.len() {
+        let stuck = (0..passes.len())
+            .filter(|i| !order.contains(i))
+            .map(|i| passes[i].0)
+            .collect::<Vec<_>>();
+        bail!(
+            "Passes depend on each other in a cycle: {}",
+            stuck.join(", ")
+        );
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sort(passes: &[(&str, &[&str], &[&str])], known: &[&str]) -> Result<Vec<String>> {
+        let owned = passes
+            .iter()
+            .map(|(name, reads, writes)| {
+                let strings =
+                    |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+                (*name, strings(reads), strings(writes))
+            })
+            .collect::<Vec<_>>();
+        let borrowed = owned
+            .iter()
+            .map(|(name, reads, writes)| (*name, &reads[..], &writes[..]))
+            .collect::<Vec<_>>();
+        let known = known.iter().map(|s| s.to_string()).collect();
+        let order = sort_passes(&borrowed, &known)?;
+        Ok(order.iter().map(|&i| passes[i].0.to_string()).collect())
+    }
+
+    #[test]
+    fn passes_run_after_what_they_read() {
+        let order = sort(
+            &[
+                ("ui", &[SWAP_CHAIN], &[SWAP_CHAIN]),
+                ("post", &["hdr"], &[SWAP_CHAIN]),
+                ("main", &["shadow"], &["hdr", "depth"]),
+                ("shadow", &[], &["shadow"]),
+            ],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(order, vec!["shadow", "main", "post", "ui"]);
+    }
+
+    #[test]
+    fn independent_passes_keep_their_order() {
+        let order = sort(&[("a", &[], &["x"]), ("b", &[], &["y"])], &[]).unwrap();
+        assert_eq!(order, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let err = sort(&[("a", &["y"], &["x"]), ("b", &["x"], &["y"])], &[]).unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_inputs_are_errors() {
+        assert!(sort(&[("a", &["missing"], &[SWAP_CHAIN])], &[]).is_err());
+        assert!(sort(&[("a", &["shadow"], &[SWAP_CHAIN])], &["shadow"]).is_ok());
+    }
+
+    #[test]
+    fn sizes_are_never_zero() {
+        assert_eq!(AttachmentSize::Scaled(0.5).resolve(801, 1), (400, 1));
+        assert_eq!(AttachmentSize::Display.resolve(0, 0), (1, 1));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::num::NonZeroU8;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Gives every [SamplerCache] that isn't a clone its own device
+/// generation.
+static NEXT_DEVICE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Everything that makes one sampler different from another.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SamplerConfig {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+    /// Makes this a comparison sampler, for depth textures such as
+    /// shadow maps.
+    pub compare: Option<wgpu::CompareFunction>,
+    /// 1, 2, 4, 8 or 16. Sharpens textures seen at steep angles.
+    pub anisotropy: Option<NonZeroU8>,
+}
+
+impl Default for SamplerConfig {
+    /// Clamped to the edges, with trilinear filtering.
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: None,
+            anisotropy: None,
+        }
+    }
+}
+
+/// The fields of a [SamplerConfig], with the floats as bits so they
+/// can be hashed.
+type SamplerKey = (
+    [wgpu::AddressMode; 3],
+    [wgpu::FilterMode; 3],
+    [u32; 2],
+    Option<wgpu::CompareFunction>,
+    Option<NonZeroU8>,
+);
+
+impl SamplerConfig {
+    /// Tiles the texture in every direction.
+    pub fn repeat() -> Self {
+        Self::default().address_mode(wgpu::AddressMode::Repeat)
+    }
+
+    /// No filtering at all, for pixel art and data textures.
+    pub fn nearest() -> Self {
+        Self::default().filter(wgpu::FilterMode::Nearest)
+    }
+
+    /// Compares against depth textures instead of returning their
+    /// values.
+    pub fn depth(compare: wgpu::CompareFunction) -> Self {
+        Self {
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(compare),
+            ..Default::default()
+        }
+    }
+
+    pub fn address_mode(mut self, mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = mode;
+        self.address_mode_v = mode;
+        self.address_mode_w = mode;
+        self
+    }
+
+    /// Sets the mag, min and mipmap filters.
+    pub fn filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.mag_filter = filter;
+        self.min_filter = filter;
+        self.mipmap_filter = filter;
+        self
+    }
+
+    pub fn lod_clamp(mut self, min: f32, max: f32) -> Self {
+        self.lod_min_clamp = min;
+        self.lod_max_clamp = max;
+        self
+    }
+
+    pub fn compare(mut self, compare: wgpu::CompareFunction) -> Self {
+        self.compare = Some(compare);
+        self
+    }
+
+    /// Rounds `clamp` down to the nearest value wgpu accepts. 0 turns
+    /// anisotropic filtering off.
+    pub fn anisotropy(mut self, clamp: u8) -> Self {
+        let clamp = [16, 8, 4, 2, 1]
+            .iter()
+            .copied()
+            .find(|&valid| valid <= clamp)
+            .unwrap_or(0);
+        self.anisotropy = NonZeroU8::new(clamp);
+        self
+    }
+
+    pub fn descriptor<'a>(&self, label: Option<&'a str>) -> wgpu::SamplerDescriptor<'a> {
+        wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            lod_min_clamp: self.lod_min_clamp,
+            lod_max_clamp: self.lod_max_clamp,
+            compare: self.compare,
+            anisotropy_clamp: self.anisotropy,
+        }
+    }
+
+    fn key(&self) -> SamplerKey {
+        (
+            [
+                self.address_mode_u,
+                self.address_mode_v,
+                self.address_mode_w,
+            ],
+            [self.mag_filter, self.min_filter, self.mipmap_filter],
+            [self.lod_min_clamp.to_bits(), self.lod_max_clamp.to_bits()],
+            self.compare,
+            self.anisotropy,
+        )
+    }
+}
+
+/// Hands out one sampler per [SamplerConfig], so textures that sample
+/// the same way share it. A cache is for a single device. Clones share
+/// the same samplers, so the one on [Display](crate::Display) can be
+/// cloned into whatever creates textures.
+#[derive(Clone)]
+pub struct SamplerCache {
+    samplers: Arc<Mutex<HashMap<(u64, SamplerKey), Arc<wgpu::Sampler>>>>,
+    /// Which device this cache's samplers were created on. Only
+    /// [SamplerCache::for_new_device] changes it, so clones made
+    /// before then keep their samplers.
+    device_generation: u64,
+}
+
+impl Default for SamplerCache {
+    fn default() -> Self {
+        Self {
+            samplers: Default::default(),
+            device_generation: NEXT_DEVICE_GENERATION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl SamplerCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, device: &wgpu::Device, config: &SamplerConfig) -> Arc<wgpu::Sampler> {
+        let mut samplers = self.samplers.lock().unwrap();
+        samplers
+            .entry((self.device_generation, config.key()))
+            .or_insert_with(|| Arc::new(device.create_sampler(&config.descriptor(None))))
+            .clone()
+    }
+
+    /// How many different samplers this cache has created for its
+    /// device.
+    pub fn len(&self) -> usize {
+        self.samplers
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(generation, _)| *generation == self.device_generation)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A cache for the device that replaced this cache's lost one. It
+    /// shares the same map, but never hands out the samplers made on
+    /// the lost device, which are dropped from it. Clones of `self`
+    /// aren't changed, so whatever should create textures on the new
+    /// device needs the returned cache.
+    pub fn for_new_device(&self) -> Self {
+        let mut samplers = self.samplers.lock().unwrap();
+        samplers.retain(|(generation, _), _| *generation != self.device_generation);
+        Self {
+            samplers: self.samplers.clone(),
+            device_generation: NEXT_DEVICE_GENERATION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn anisotropy_rounds_down_to_valid_values() {
+        let clamp = |n| SamplerConfig::default().anisotropy(n).anisotropy;
+        assert_eq!(clamp(0), None);
+        assert_eq!(clamp(1), NonZeroU8::new(1));
+        assert_eq!(clamp(12), NonZeroU8::new(8));
+        assert_eq!(clamp(255), NonZeroU8::new(16));
+    }
+
+    #[test]
+    fn keys_only_match_identical_configs() {
+        let config = SamplerConfig::repeat().anisotropy(4);
+        assert_eq!(config.key(), SamplerConfig::repeat().anisotropy(4).key());
+        assert_ne!(config.key(), SamplerConfig::repeat().key());
+        assert_ne!(
+            SamplerConfig::default().key(),
+            SamplerConfig::default().lod_clamp(0.0, 100.0).key()
+        );
+        assert_ne!(
+            SamplerConfig::default().key(),
+            SamplerConfig::depth(wgpu::CompareFunction::LessEqual).key()
+        );
+    }
+}
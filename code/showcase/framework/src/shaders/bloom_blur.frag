@@ -0,0 +1,26 @@
+#version 450
+
+layout(location=0) in vec2 v_TexCoord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_source;
+layout(set=0, binding=1) uniform sampler s_linear;
+layout(set=0, binding=2) uniform Blur {
+    // One texel along the axis being blurred
+    vec2 direction;
+};
+
+// A 9 tap gaussian squeezed into 5 samples by sampling between texels
+// and letting the linear filtering do the rest
+const float OFFSETS[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float WEIGHTS[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main() {
+    vec3 color = textureLod(sampler2D(t_source, s_linear), v_TexCoord, 0.0).rgb * WEIGHTS[0];
+    for (int i = 1; i < 3; i++) {
+        vec2 offset = direction * OFFSETS[i];
+        color += textureLod(sampler2D(t_source, s_linear), v_TexCoord + offset, 0.0).rgb * WEIGHTS[i];
+        color += textureLod(sampler2D(t_source, s_linear), v_TexCoord - offset, 0.0).rgb * WEIGHTS[i];
+    }
+    f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 450
+
+layout(location=0) in vec2 v_TexCoord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_source;
+layout(set=0, binding=1) uniform sampler s_linear;
+layout(set=0, binding=2) uniform Bloom {
+    float threshold;
+    // How far below the threshold colors start to bloom, so the cutoff
+    // isn't a hard edge
+    float knee;
+};
+
+void main() {
+    vec3 color = textureLod(sampler2D(t_source, s_linear), v_TexCoord, 0.0).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+
+    float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-4);
+    float contribution = max(soft, brightness - threshold) / max(brightness, 1e-4);
+
+    f_color = vec4(color * contribution, 1.0);
+}
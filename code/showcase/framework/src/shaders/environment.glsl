@@ -0,0 +1,51 @@
+// Lights surfaces with the cube map bound by `framework::EnvironmentMap`.
+// Include this after `#version` and add `environment_light` to the
+// direct lighting. It uses set 3, or set 4 after the shadow maps when
+// SHADOWS is defined, in which case the device needs to allow 5 bind
+// groups.
+
+#ifdef SHADOWS
+#define ENVIRONMENT_SET 4
+#else
+#define ENVIRONMENT_SET 3
+#endif
+
+layout(set=ENVIRONMENT_SET, binding=0) uniform textureCube t_environment;
+layout(set=ENVIRONMENT_SET, binding=1) uniform sampler s_environment;
+layout(set=ENVIRONMENT_SET, binding=2) uniform Environment {
+    float environment_intensity;
+    // The mip level used for the roughest reflections, and for diffuse
+    // light
+    float environment_max_lod;
+};
+
+// Diffuse and specular light from every direction around the surface.
+// The mip chain stands in for properly convolved maps: rougher surfaces
+// sample blurrier levels.
+vec3 environment_light(
+    vec3 normal,
+    vec3 view_dir,
+    float n_dot_v,
+    vec3 f0,
+    vec3 albedo,
+    float metallic,
+    float roughness
+) {
+    // Fresnel with roughness, so rough surfaces don't get bright rims
+    vec3 fresnel = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+    vec3 k_diffuse = (1.0 - fresnel) * (1.0 - metallic);
+
+    vec3 irradiance = textureLod(
+        samplerCube(t_environment, s_environment),
+        normal,
+        environment_max_lod
+    ).rgb;
+    vec3 reflected = reflect(-view_dir, normal);
+    vec3 prefiltered = textureLod(
+        samplerCube(t_environment, s_environment),
+        reflected,
+        roughness * environment_max_lod
+    ).rgb;
+
+    return (k_diffuse * albedo * irradiance + fresnel * prefiltered) * environment_intensity;
+}
@@ -0,0 +1,36 @@
+#version 450
+
+const float PI = 3.14159265359;
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=1) flat in uint v_face;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_equirect;
+layout(set=0, binding=1) uniform sampler s_equirect;
+
+// The direction through a texel of a cube face, with faces in the order
+// +X, -X, +Y, -Y, +Z, -Z
+vec3 face_direction(uint face, vec2 uv) {
+    float s = uv.x * 2.0 - 1.0;
+    float t = uv.y * 2.0 - 1.0;
+    switch (face) {
+        case 0u: return vec3(1.0, -t, -s);
+        case 1u: return vec3(-1.0, -t, s);
+        case 2u: return vec3(s, 1.0, t);
+        case 3u: return vec3(s, -1.0, -t);
+        case 4u: return vec3(s, -t, 1.0);
+        default: return vec3(-s, -t, -1.0);
+    }
+}
+
+void main() {
+    vec3 direction = normalize(face_direction(v_face, v_tex_coords));
+    // Longitude across and latitude down the image
+    vec2 uv = vec2(
+        atan(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI
+    );
+    f_color = vec4(texture(sampler2D(t_equirect, s_equirect), uv).rgb, 1.0);
+}
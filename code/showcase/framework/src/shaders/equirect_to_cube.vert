@@ -0,0 +1,19 @@
+#version 450
+
+// blit.vert, but with the instance index passed along as the cube face
+// to render
+
+layout(location=0) out vec2 v_tex_coords;
+layout(location=1) flat out uint v_face;
+
+void main() {
+    switch(gl_VertexIndex % 4) {
+        case 0: v_tex_coords = vec2(1.0, 0.0); break;
+        case 1: v_tex_coords = vec2(1.0, 1.0); break;
+        case 2: v_tex_coords = vec2(0.0, 0.0); break;
+        case 3: v_tex_coords = vec2(0.0, 1.0); break;
+    }
+    gl_Position = vec4(v_tex_coords * 2.0 - 1.0, 0.5, 1.0);
+    gl_Position.y = -gl_Position.y;
+    v_face = uint(gl_InstanceIndex);
+}
@@ -0,0 +1,68 @@
+#version 450
+
+layout(location=0) in vec2 v_TexCoord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_source;
+layout(set=0, binding=1) uniform sampler s_linear;
+layout(set=0, binding=2) uniform Fxaa {
+    vec2 texel_size;
+    // 1.0 when the target is sRGB and the hardware does the encoding
+    float gamma;
+};
+
+const float EDGE_THRESHOLD_MIN = 1.0 / 32.0;
+const float EDGE_THRESHOLD = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+
+vec3 fetch(vec2 uv) {
+    return textureLod(sampler2D(t_source, s_linear), uv, 0.0).rgb;
+}
+
+// Edges are found by contrast the way they're seen, which is closer to
+// the square root of linear luminance than luminance itself
+float luma(vec3 color) {
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+void main() {
+    vec3 center = fetch(v_TexCoord);
+    float luma_m = luma(center);
+    float luma_nw = luma(fetch(v_TexCoord + vec2(-1.0, -1.0) * texel_size));
+    float luma_ne = luma(fetch(v_TexCoord + vec2(1.0, -1.0) * texel_size));
+    float luma_sw = luma(fetch(v_TexCoord + vec2(-1.0, 1.0) * texel_size));
+    float luma_se = luma(fetch(v_TexCoord + vec2(1.0, 1.0) * texel_size));
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+    if (luma_max - luma_min < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD)) {
+        f_color = vec4(pow(center, vec3(1.0 / gamma)), 1.0);
+        return;
+    }
+
+    // Blur along the edge, which is perpendicular to the gradient
+    vec2 dir = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel_size;
+
+    vec3 rgb_a = 0.5 * (
+        fetch(v_TexCoord + dir * (1.0 / 3.0 - 0.5)) +
+        fetch(v_TexCoord + dir * (2.0 / 3.0 - 0.5))
+    );
+    vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+        fetch(v_TexCoord + dir * -0.5) +
+        fetch(v_TexCoord + dir * 0.5)
+    );
+    // The wider blur is only used if it didn't pick up anything from
+    // across the edge
+    float luma_b = luma(rgb_b);
+    vec3 color = (luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b;
+
+    f_color = vec4(pow(color, vec3(1.0 / gamma)), 1.0);
+}
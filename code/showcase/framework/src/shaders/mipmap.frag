@@ -0,0 +1,15 @@
+#version 450
+
+layout(location=0) in vec2 v_TexCoord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_source;
+layout(set=0, binding=1) uniform sampler s_source;
+
+void main() {
+    // The target is half the size of the source, so with linear
+    // filtering each texel is the average of the 2x2 texels above it.
+    // sRGB textures are decoded before filtering, so they're averaged
+    // in linear space.
+    f_color = textureLod(sampler2D(t_source, s_source), v_TexCoord, 0.0);
+}
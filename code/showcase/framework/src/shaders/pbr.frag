@@ -0,0 +1,173 @@
+#version 450
+
+const float PI = 3.14159265359;
+// Stands in for the light bouncing around the scene when there's no
+// environment map
+const float AMBIENT = 0.03;
+
+layout(location=0) in vec3 v_position;
+layout(location=1) in vec2 v_tex_coords;
+layout(location=2) in vec3 v_normal;
+layout(location=3) in vec3 v_tangent;
+layout(location=4) in vec3 v_bitangent;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_albedo;
+layout(set=0, binding=1) uniform sampler s_albedo;
+layout(set=0, binding=2) uniform texture2D t_metallic_roughness;
+layout(set=0, binding=3) uniform sampler s_metallic_roughness;
+layout(set=0, binding=4) uniform texture2D t_normal;
+layout(set=0, binding=5) uniform sampler s_normal;
+layout(set=0, binding=6) uniform texture2D t_occlusion;
+layout(set=0, binding=7) uniform sampler s_occlusion;
+layout(set=0, binding=8) uniform texture2D t_emissive;
+layout(set=0, binding=9) uniform sampler s_emissive;
+layout(set=0, binding=10) uniform Material {
+    vec4 base_color_factor;
+    vec3 emissive_factor;
+    float metallic_factor;
+    float roughness_factor;
+    float normal_scale;
+    float occlusion_strength;
+    float alpha_cutoff;
+    uint double_sided;
+};
+
+layout(set=1, binding=0) uniform Uniforms {
+    vec4 u_view_position;
+    mat4 u_view_proj;
+};
+
+const uint LIGHT_POINT = 0u;
+const uint LIGHT_DIRECTIONAL = 1u;
+const uint LIGHT_SPOT = 2u;
+
+struct Light {
+    vec4 position; // w is the kind of light
+    vec4 direction;
+    vec4 color; // w is the intensity
+    vec4 attenuation; // constant, linear, quadratic
+    vec4 cone; // cosines of the inner and outer angles
+};
+
+layout(set=2, binding=0) readonly buffer Lights {
+    uint light_count;
+    Light lights[];
+};
+
+#ifdef SHADOWS
+#include "shadow.glsl"
+#endif
+
+#ifdef ENVIRONMENT
+#include "environment.glsl"
+#endif
+
+// Trowbridge-Reitz GGX normal distribution
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Schlick-GGX with Smith's method for both the view and light
+// directions
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    float r = roughness + 1.0;
+    float k = r * r / 8.0;
+    float g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    float g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+void main() {
+    vec4 albedo = texture(sampler2D(t_albedo, s_albedo), v_tex_coords) * base_color_factor;
+    if (alpha_cutoff > 0.0 && albedo.a < alpha_cutoff) {
+        discard;
+    }
+
+    // glTF stores roughness in green and metalness in blue
+    vec4 metallic_roughness = texture(sampler2D(t_metallic_roughness, s_metallic_roughness), v_tex_coords);
+    float metallic = clamp(metallic_roughness.b * metallic_factor, 0.0, 1.0);
+    // Perfectly smooth surfaces make the highlight infinitely small
+    float roughness = clamp(metallic_roughness.g * roughness_factor, 0.04, 1.0);
+    float occlusion = texture(sampler2D(t_occlusion, s_occlusion), v_tex_coords).r;
+    occlusion = mix(1.0, occlusion, occlusion_strength);
+    vec3 emissive = texture(sampler2D(t_emissive, s_emissive), v_tex_coords).rgb * emissive_factor;
+
+    vec3 tangent_normal = texture(sampler2D(t_normal, s_normal), v_tex_coords).xyz * 2.0 - 1.0;
+    tangent_normal.xy *= normal_scale;
+    mat3 tbn = mat3(normalize(v_tangent), normalize(v_bitangent), normalize(v_normal));
+    vec3 normal = normalize(tbn * tangent_normal);
+    if (double_sided != 0u && !gl_FrontFacing) {
+        normal = -normal;
+    }
+
+    vec3 view_dir = normalize(u_view_position.xyz - v_position);
+    float n_dot_v = max(dot(normal, view_dir), 1e-4);
+    // Dielectrics reflect about 4% of light head on, metals tint their
+    // reflections with their albedo
+    vec3 f0 = mix(vec3(0.04), albedo.rgb, metallic);
+
+    vec3 direct = vec3(0.0);
+    for (uint i = 0u; i < light_count; i++) {
+        Light light = lights[i];
+        uint kind = uint(light.position.w);
+
+        vec3 light_dir;
+        float attenuation = 1.0;
+        if (kind == LIGHT_DIRECTIONAL) {
+            light_dir = -light.direction.xyz;
+        } else {
+            vec3 to_light = light.position.xyz - v_position;
+            float dist = length(to_light);
+            light_dir = to_light / dist;
+            vec3 a = light.attenuation.xyz;
+            attenuation = 1.0 / max(a.x + a.y * dist + a.z * dist * dist, 1e-4);
+        }
+        if (kind == LIGHT_SPOT) {
+            float cos_angle = dot(-light_dir, light.direction.xyz);
+            attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+        }
+#ifdef SHADOWS
+        attenuation *= shadow_factor(
+            uint(light.cone.z),
+            uint(light.cone.w),
+            v_position,
+            normalize(v_normal)
+        );
+#endif
+
+        vec3 half_dir = normalize(view_dir + light_dir);
+        float n_dot_l = max(dot(normal, light_dir), 0.0);
+        float n_dot_h = max(dot(normal, half_dir), 0.0);
+        float h_dot_v = max(dot(half_dir, view_dir), 0.0);
+
+        vec3 fresnel = fresnel_schlick(h_dot_v, f0);
+        float ndf = distribution_ggx(n_dot_h, roughness);
+        float geometry = geometry_smith(n_dot_v, n_dot_l, roughness);
+        vec3 specular = ndf * geometry * fresnel / (4.0 * n_dot_v * max(n_dot_l, 1e-4));
+
+        // Whatever isn't reflected is refracted, and metals absorb all
+        // of the refracted light
+        vec3 k_diffuse = (1.0 - fresnel) * (1.0 - metallic);
+        vec3 diffuse = k_diffuse * albedo.rgb / PI;
+
+        vec3 radiance = light.color.rgb * light.color.w * attenuation;
+        direct += (diffuse + specular) * radiance * n_dot_l;
+    }
+#ifdef ENVIRONMENT
+    vec3 ambient = environment_light(normal, view_dir, n_dot_v, f0, albedo.rgb, metallic, roughness);
+    ambient *= occlusion;
+#else
+    vec3 ambient = AMBIENT * albedo.rgb * occlusion;
+#endif
+
+    f_color = vec4(ambient + direct + emissive, albedo.a);
+}
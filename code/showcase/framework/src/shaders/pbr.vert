@@ -0,0 +1,29 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+layout(location=2) in vec3 a_normal;
+layout(location=3) in vec3 a_tangent;
+layout(location=4) in vec3 a_bitangent;
+
+layout(location=0) out vec3 v_position;
+layout(location=1) out vec2 v_tex_coords;
+layout(location=2) out vec3 v_normal;
+layout(location=3) out vec3 v_tangent;
+layout(location=4) out vec3 v_bitangent;
+
+layout(set=1, binding=0) uniform Uniforms {
+    vec4 u_view_position;
+    mat4 u_view_proj;
+};
+
+void main() {
+    // Model loading already bakes the node transforms into the
+    // vertices, so everything here is in world space
+    v_position = a_position;
+    v_tex_coords = a_tex_coords;
+    v_normal = a_normal;
+    v_tangent = a_tangent;
+    v_bitangent = a_bitangent;
+    gl_Position = u_view_proj * vec4(a_position, 1.0);
+}
@@ -0,0 +1,4 @@
+#version 450
+
+// The shadow pass only writes depth
+void main() {}
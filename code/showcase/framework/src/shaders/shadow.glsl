@@ -0,0 +1,51 @@
+// Samples the shadow maps rendered by `framework::ShadowMaps`. Include
+// this after `#version` and call `shadow_factor` for each light.
+
+#define MAX_SHADOW_MAPS 16
+
+layout(set=3, binding=0) uniform texture2DArray t_shadow;
+layout(set=3, binding=1) uniform samplerShadow s_shadow;
+layout(set=3, binding=2) uniform Shadows {
+    mat4 shadow_view_proj[MAX_SHADOW_MAPS];
+    // Texels sampled on each side of the center, so 1 is 3x3 PCF
+    float pcf_radius;
+    float shadow_texel_size;
+    float normal_offset;
+};
+
+// How much of a light reaches `position`, from 0 in full shadow to 1
+// fully lit. `first_layer` and `layer_count` come from the light, and
+// a light without shadow maps always returns 1.
+float shadow_factor(uint first_layer, uint layer_count, vec3 position, vec3 normal) {
+    // Pushing the point out along the normal hides most shadow acne
+    vec4 world = vec4(position + normal * normal_offset, 1.0);
+
+    // Cascades are ordered from nearest to furthest, so the first one
+    // the point falls into has the most detail
+    for (uint c = 0u; c < layer_count; c++) {
+        uint layer = first_layer + c;
+        vec4 clip = shadow_view_proj[layer] * world;
+        vec3 ndc = clip.xyz / clip.w;
+        // Texture coordinates have y pointing down
+        vec2 uv = ndc.xy * vec2(0.5, -0.5) + 0.5;
+        if (clip.w <= 0.0 || any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))
+            || ndc.z > 1.0) {
+            continue;
+        }
+
+        int radius = int(pcf_radius);
+        float lit = 0.0;
+        for (int x = -radius; x <= radius; x++) {
+            for (int y = -radius; y <= radius; y++) {
+                vec2 offset = vec2(x, y) * shadow_texel_size;
+                lit += texture(
+                    sampler2DArrayShadow(t_shadow, s_shadow),
+                    vec4(uv + offset, float(layer), ndc.z)
+                );
+            }
+        }
+        float samples = float((2 * radius + 1) * (2 * radius + 1));
+        return lit / samples;
+    }
+    return 1.0;
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+
+layout(set=0, binding=0) uniform ShadowPass {
+    mat4 u_light_view_proj;
+};
+
+void main() {
+    gl_Position = u_light_view_proj * vec4(a_position, 1.0);
+}
@@ -0,0 +1,21 @@
+#version 450
+
+// A full screen quad on the far plane, so the sky only shows where
+// nothing else was drawn
+
+layout(location=0) out vec3 v_direction;
+
+layout(set=1, binding=0) uniform Uniforms {
+    vec4 u_view_position;
+    mat4 u_view_proj;
+};
+
+void main() {
+    vec2 corner = vec2(float(gl_VertexIndex & 1), float((gl_VertexIndex >> 1) & 1));
+    vec4 clip = vec4(corner * 2.0 - 1.0, 1.0, 1.0);
+    gl_Position = clip;
+
+    // Where the corner's ray hits the far plane, relative to the camera
+    vec4 world = inverse(u_view_proj) * clip;
+    v_direction = world.xyz / world.w - u_view_position.xyz;
+}
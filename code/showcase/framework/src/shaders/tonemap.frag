@@ -0,0 +1,79 @@
+#version 450
+
+const uint TONEMAP_NONE = 0u;
+const uint TONEMAP_REINHARD = 1u;
+const uint TONEMAP_ACES = 2u;
+
+layout(location=0) in vec2 v_TexCoord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_hdr;
+layout(set=0, binding=1) uniform sampler s_linear;
+layout(set=0, binding=2) uniform texture2D t_bloom;
+layout(set=0, binding=3) uniform texture2D t_lut;
+layout(set=0, binding=4) uniform Tonemap {
+    float exposure;
+    float bloom_intensity;
+    uint tonemapper;
+    // The LUT is a strip of lut_size slices of lut_size x lut_size, one
+    // per blue value. 0 if there's no LUT.
+    float lut_size;
+    // 1.0 when the target is sRGB and the hardware does the encoding
+    float gamma;
+};
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+vec3 linear_to_srgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+vec3 srgb_to_linear(vec3 c) {
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+}
+
+// LUTs are authored against sRGB images, so the lookup happens in sRGB
+vec3 grade(vec3 color) {
+    vec3 srgb = clamp(linear_to_srgb(color), 0.0, 1.0);
+    float n = lut_size;
+    float blue = srgb.b * (n - 1.0);
+    float slice0 = floor(blue);
+    float slice1 = min(slice0 + 1.0, n - 1.0);
+    // Sample texel centers so neighbouring slices don't bleed in
+    vec2 uv = (srgb.rg * (n - 1.0) + 0.5) / vec2(n * n, n);
+    vec3 a = textureLod(sampler2D(t_lut, s_linear), uv + vec2(slice0 / n, 0.0), 0.0).rgb;
+    vec3 b = textureLod(sampler2D(t_lut, s_linear), uv + vec2(slice1 / n, 0.0), 0.0).rgb;
+    return srgb_to_linear(mix(a, b, blue - slice0));
+}
+
+void main() {
+    vec3 color = textureLod(sampler2D(t_hdr, s_linear), v_TexCoord, 0.0).rgb;
+    color += textureLod(sampler2D(t_bloom, s_linear), v_TexCoord, 0.0).rgb * bloom_intensity;
+    color *= exposure;
+
+    if (tonemapper == TONEMAP_ACES) {
+        color = aces(color);
+    } else if (tonemapper == TONEMAP_REINHARD) {
+        color = reinhard(color);
+    } else {
+        color = clamp(color, 0.0, 1.0);
+    }
+
+    if (lut_size > 0.0) {
+        color = grade(color);
+    }
+
+    f_color = vec4(pow(color, vec3(1.0 / gamma)), 1.0);
+}
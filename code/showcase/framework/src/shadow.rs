@@ -0,0 +1,688 @@
+use anyhow::*;
+use cgmath::*;
+use std::mem;
+use std::num::NonZeroU32;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::camera::{Camera, Projection, OPENGL_TO_WGPU_MATRIX};
+use crate::light::{Attenuation, Light, LightId, LightManager, ShadowLayers};
+use crate::model::{Mesh, ModelVertex};
+use crate::pipeline::RenderPipelineBuilder;
+use crate::sampler::{SamplerCache, SamplerConfig};
+use crate::texture;
+
+/// How many layers the uniform in `shadow.glsl` has matrices for.
+pub const MAX_SHADOW_MAPS: usize = 16;
+pub const MAX_CASCADES: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowConfig {
+    /// Width and height of each shadow map.
+    pub resolution: u32,
+    /// How many shadow maps there are room for. Spot lights use one
+    /// and directional lights one per cascade. At most
+    /// [MAX_SHADOW_MAPS].
+    pub layers: u32,
+    /// How many pieces the camera's view is split into for directional
+    /// lights. At most [MAX_CASCADES].
+    pub cascade_count: usize,
+    /// Blends between evenly spaced cascades at 0.0 and logarithmically
+    /// spaced ones at 1.0, which put more detail close to the camera.
+    pub cascade_split_lambda: f32,
+    /// Directional light shadows stop this far from the camera, and
+    /// spot lights without attenuation cast shadows this far.
+    pub max_distance: f32,
+    pub depth_bias: i32,
+    pub depth_bias_slope_scale: f32,
+    pub depth_bias_clamp: f32,
+    /// How far along its normal a point is moved before it's looked up
+    /// in a shadow map.
+    pub normal_offset: f32,
+    /// Texels sampled on each side of the center, so 1 is 3x3 PCF and
+    /// 0 only uses the sampler's own filtering.
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            layers: 8,
+            cascade_count: 4,
+            cascade_split_lambda: 0.75,
+            max_distance: 50.0,
+            depth_bias: 2,
+            depth_bias_slope_scale: 2.0,
+            depth_bias_clamp: 0.0,
+            normal_offset: 0.02,
+            pcf_radius: 1,
+        }
+    }
+}
+
+/// The `Shadows` uniform from `shadow.glsl`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ShadowUniform {
+    view_proj: [Matrix4<f32>; MAX_SHADOW_MAPS],
+    pcf_radius: f32,
+    texel_size: f32,
+    normal_offset: f32,
+    _padding: f32,
+}
+
+unsafe impl bytemuck::Zeroable for ShadowUniform {}
+unsafe impl bytemuck::Pod for ShadowUniform {}
+
+/// Uniform buffer offsets have to be aligned to this.
+const PASS_UNIFORM_STRIDE: wgpu::BufferAddress = 256;
+
+struct ShadowCaster {
+    light: LightId,
+    layers: ShadowLayers,
+}
+
+/// Renders the depth of the scene from the point of view of every light
+/// that casts shadows, so lit shaders can tell what's in shadow.
+///
+/// Shaders sample the maps by including `shadow.glsl` from the
+/// framework's shader directory and binding [ShadowMaps::bind_group] to
+/// group 3. [PbrMaterial::fragment_shader_with_shadows] already does.
+///
+/// Each frame, call [ShadowMaps::update] after moving the camera or the
+/// lights, then [ShadowMaps::render] before the passes that sample the
+/// shadows.
+///
+/// [PbrMaterial::fragment_shader_with_shadows]: crate::PbrMaterial::fragment_shader_with_shadows
+pub struct ShadowMaps {
+    config: ShadowConfig,
+    casters: Vec<ShadowCaster>,
+    used_layers: Vec<bool>,
+    splits: Vec<f32>,
+    data: ShadowUniform,
+    buffer: wgpu::Buffer,
+    pass_buffer: wgpu::Buffer,
+    layer_views: Vec<wgpu::TextureView>,
+    layer_bind_groups: Vec<wgpu::BindGroup>,
+    pipeline: wgpu::RenderPipeline,
+    pub texture: texture::Texture<'static>,
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+pub(crate) fn shadow_bind_group_layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                dimension: wgpu::TextureViewDimension::D2Array,
+                component_type: wgpu::TextureComponentType::Float,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Sampler { comparison: true },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::UniformBuffer {
+                dynamic: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ]
+}
+
+impl ShadowMaps {
+    pub fn new(
+        device: &wgpu::Device,
+        samplers: &SamplerCache,
+        config: ShadowConfig,
+    ) -> Result<Self> {
+        if config.layers == 0 || config.layers as usize > MAX_SHADOW_MAPS {
+            bail!(
+                "ShadowConfig::layers must be between 1 and {}, got {}",
+                MAX_SHADOW_MAPS,
+                config.layers
+            );
+        }
+        if config.cascade_count == 0 || config.cascade_count > MAX_CASCADES {
+            bail!(
+                "ShadowConfig::cascade_count must be between 1 and {}, got {}",
+                MAX_CASCADES,
+                config.cascade_count
+            );
+        }
+
+        let mut texture = texture::Texture::from_descriptor(
+            device,
+            wgpu::TextureDescriptor {
+                label: Some("ShadowMaps::texture"),
+                size: wgpu::Extent3d {
+                    width: config.resolution,
+                    height: config.resolution,
+                    depth: config.layers,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: texture::Texture::DEPTH_FORMAT,
+                usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT
+                    | wgpu::TextureUsage::SAMPLED
+                    | wgpu::TextureUsage::COPY_SRC,
+            },
+            samplers,
+            &SamplerConfig::depth(wgpu::CompareFunction::LessEqual),
+        );
+        texture.view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..config.layers)
+            .map(|layer| {
+                texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("ShadowMaps::layer_view"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let data = ShadowUniform {
+            view_proj: [Matrix4::identity(); MAX_SHADOW_MAPS],
+            pcf_radius: config.pcf_radius as f32,
+            texel_size: 1.0 / config.resolution as f32,
+            normal_offset: config.normal_offset,
+            _padding: 0.0,
+        };
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("ShadowMaps::buffer"),
+            contents: bytemuck::cast_slice(&[data]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ShadowMaps::layout"),
+            entries: &shadow_bind_group_layout_entries(),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ShadowMaps::bind_group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+                },
+            ],
+        });
+
+        // The shadow pass renders each layer with its own matrix
+        let pass_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ShadowMaps::pass_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX,
+                ty: wgpu::BindingType::UniformBuffer {
+                    dynamic: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let pass_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ShadowMaps::pass_buffer"),
+            size: PASS_UNIFORM_STRIDE * config.layers as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layer_bind_groups = (0..config.layers as wgpu::BufferAddress)
+            .map(|layer| {
+                let offset = layer * PASS_UNIFORM_STRIDE;
+                let size = mem::size_of::<Matrix4<f32>>() as wgpu::BufferAddress;
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("ShadowMaps::layer_bind_group"),
+                    layout: &pass_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(
+                            pass_buffer.slice(offset..offset + size),
+                        ),
+                    }],
+                })
+            })
+            .collect();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ShadowMaps::pipeline_layout"),
+            bind_group_layouts: &[&pass_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = RenderPipelineBuilder::new()
+            .layout(&pipeline_layout)
+            .vertex_shader(wgpu::include_spirv!("shaders/shadow.vert.spv"))
+            .fragment_shader(wgpu::include_spirv!("shaders/shadow.frag.spv"))
+            .depth_format(texture::Texture::DEPTH_FORMAT)
+            .depth_bias(config.depth_bias)
+            .depth_bias_slope_scale(config.depth_bias_slope_scale)
+            .depth_bias_clamp(config.depth_bias_clamp)
+            .vertex_buffer::<ModelVertex>()
+            .build(device)
+            .context("Unable to create the shadow pass pipeline")?;
+
+        Ok(Self {
+            config,
+            casters: Vec::new(),
+            used_layers: vec![false; config.layers as usize],
+            splits: Vec::new(),
+            data,
+            buffer,
+            pass_buffer,
+            layer_views,
+            layer_bind_groups,
+            pipeline,
+            texture,
+            layout,
+            bind_group,
+        })
+    }
+
+    pub fn config(&self) -> &ShadowConfig {
+        &self.config
+    }
+
+    /// Where each cascade ends, as a distance along the camera's view
+    /// direction. Set by [ShadowMaps::update].
+    pub fn cascade_splits(&self) -> &[f32] {
+        &self.splits
+    }
+
+    /// Starts rendering shadows for a directional or spot light. Point
+    /// lights would need a cube map, and aren't supported.
+    pub fn add_caster(&mut self, lights: &mut LightManager, id: LightId) -> Result<()> {
+        if self.casters.iter().any(|c| c.light == id) {
+            return Ok(());
+        }
+        let count = match lights.get(id) {
+            Some(light) => self
+                .layers_needed(light)
+                .context("Point lights can't cast shadows")?,
+            None => bail!("{:?} isn't in the LightManager", id),
+        };
+        let first = find_free_layers(&self.used_layers, count as usize).with_context(|| {
+            format!(
+                "Not enough shadow map layers for {:?}: it needs {}, and {} of {} are free",
+                id,
+                count,
+                self.used_layers.iter().filter(|used| !**used).count(),
+                self.config.layers
+            )
+        })?;
+
+        let layers = ShadowLayers {
+            first: first as u32,
+            count,
+        };
+        for used in &mut self.used_layers[first..first + count as usize] {
+            *used = true;
+        }
+        self.casters.push(ShadowCaster { light: id, layers });
+        lights.set_shadow_layers(id, Some(layers));
+        Ok(())
+    }
+
+    /// How many layers `light` needs, or `None` if it can't cast
+    /// shadows.
+    fn layers_needed(&self, light: &Light) -> Option<u32> {
+        match light {
+            Light::Directional(_) => Some(self.config.cascade_count as u32),
+            Light::Spot(_) => Some(1),
+            Light::Point(_) => None,
+        }
+    }
+
+    /// The caster's light, unless it was removed or changed into a kind
+    /// of light that needs a different number of layers than the caster
+    /// has.
+    fn caster_light<'a>(
+        &self,
+        lights: &'a LightManager,
+        caster: &ShadowCaster,
+    ) -> Option<&'a Light> {
+        lights
+            .get(caster.light)
+            .filter(|light| self.layers_needed(light) == Some(caster.layers.count))
+    }
+
+    /// Stops rendering shadows for a light, and frees its layers.
+    pub fn remove_caster(&mut self, lights: &mut LightManager, id: LightId) {
+        if let Some(i) = self.casters.iter().position(|c| c.light == id) {
+            let caster = self.casters.remove(i);
+            let ShadowLayers { first, count } = caster.layers;
+            for used in &mut self.used_layers[first as usize..(first + count) as usize] {
+                *used = false;
+            }
+            lights.set_shadow_layers(id, None);
+        }
+    }
+
+    /// Fits each shadow map around what the camera can see, and uploads
+    /// the matrices. Casters whose light has been removed from `lights`,
+    /// or turned into a kind of light that needs a different number of
+    /// layers, are skipped until [ShadowMaps::remove_caster] is called.
+    /// Add the light again to give it the right number of layers.
+    pub fn update(
+        &mut self,
+        lights: &LightManager,
+        camera: &Camera,
+        projection: &Projection,
+        queue: &wgpu::Queue,
+    ) {
+        let far = projection.zfar().min(self.config.max_distance);
+        self.splits = cascade_splits(
+            projection.znear(),
+            far,
+            self.config.cascade_count,
+            self.config.cascade_split_lambda,
+        );
+
+        let view = camera.calc_matrix();
+        for caster in &self.casters {
+            let ShadowLayers { first, count } = caster.layers;
+            let layers = first as usize..(first + count) as usize;
+            match self.caster_light(lights, caster) {
+                Some(Light::Directional(light)) => {
+                    let mut near = projection.znear();
+                    for (layer, &split) in layers.zip(&self.splits) {
+                        let corners = frustum_corners(view, projection, near, split);
+                        self.data.view_proj[layer] = cascade_matrix(
+                            &corners,
+                            light.direction,
+                            self.config.max_distance,
+                            self.config.resolution,
+                        );
+                        near = split;
+                    }
+                }
+                Some(Light::Spot(light)) => {
+                    let range = attenuation_range(&light.attenuation, self.config.max_distance);
+                    self.data.view_proj[layers.start] =
+                        spot_matrix(light.position, light.direction, light.outer_angle, range);
+                }
+                _ => {}
+            }
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
+
+        // Each layer's matrix again, spaced out so every layer can have
+        // its own bind group in the shadow pass
+        let mut pass_data = vec![0u8; PASS_UNIFORM_STRIDE as usize * self.config.layers as usize];
+        for (layer, matrix) in self.data.view_proj[..self.config.layers as usize]
+            .iter()
+            .enumerate()
+        {
+            let offset = layer * PASS_UNIFORM_STRIDE as usize;
+            let matrix: &[f32; 16] = matrix.as_ref();
+            let bytes: &[u8] = bytemuck::cast_slice(matrix);
+            pass_data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        queue.write_buffer(&self.pass_buffer, 0, &pass_data);
+    }
+
+    /// Renders `meshes` into the shadow map of every caster. Meshes are
+    /// drawn once per layer, so the iterator needs to be cloneable.
+    pub fn render<'a, I>(
+        &'a self,
+        lights: &LightManager,
+        encoder: &mut wgpu::CommandEncoder,
+        meshes: I,
+    ) where
+        I: IntoIterator<Item = &'a Mesh>,
+        I::IntoIter: Clone,
+    {
+        let meshes = meshes.into_iter();
+        for caster in &self.casters {
+            if self.caster_light(lights, caster).is_none() {
+                continue;
+            }
+            let ShadowLayers { first, count } = caster.layers;
+            for layer in first as usize..(first + count) as usize {
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(
+                        wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                            attachment: &self.layer_views[layer],
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        },
+                    ),
+                });
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &self.layer_bind_groups[layer], &[]);
+                for mesh in meshes.clone() {
+                    pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    pass.set_index_buffer(mesh.index_buffer.slice(..));
+                    pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
+                }
+            }
+        }
+    }
+}
+
+/// Index of the first run of `count` unused layers.
+fn find_free_layers(used: &[bool], count: usize) -> Option<usize> {
+    (0..used.len())
+        .take_while(|start| start + count <= used.len())
+        .find(|&start| used[start..start + count].iter().all(|used| !used))
+}
+
+/// Where each cascade ends. Logarithmic splits give every cascade the
+/// same ratio of near to far, which matches how perspective shrinks
+/// things, but leave the first cascade tiny, so they're blended with
+/// evenly spaced ones.
+fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let p = i as f32 / count as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            lambda * log + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// The world space corners of the part of the camera's view between
+/// `near` and `far`.
+fn frustum_corners(
+    view: Matrix4<f32>,
+    projection: &Projection,
+    near: f32,
+    far: f32,
+) -> [Point3<f32>; 8] {
+    let inverse_view = view.invert().unwrap_or_else(Matrix4::identity);
+    let tan_half_fovy = (projection.fovy() / 2.0).tan();
+    let mut corners = [Point3::origin(); 8];
+    for (i, &z) in [near, far].iter().enumerate() {
+        let half_height = z * tan_half_fovy;
+        let half_width = half_height * projection.aspect();
+        for (j, &(x, y)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .iter()
+            .enumerate()
+        {
+            // The camera looks down -Z
+            let corner = Point3::new(x * half_width, y * half_height, -z);
+            corners[i * 4 + j] = inverse_view.transform_point(corner);
+        }
+    }
+    corners
+}
+
+fn light_up(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.normalize().y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    }
+}
+
+/// An orthographic projection around a bounding sphere of `corners`.
+/// A sphere doesn't change size as the camera turns, and snapping it to
+/// whole texels stops the shadow edges from shimmering as it moves.
+/// Everything up to `caster_distance` behind the sphere still casts
+/// shadows into it.
+fn cascade_matrix(
+    corners: &[Point3<f32>; 8],
+    direction: Vector3<f32>,
+    caster_distance: f32,
+    resolution: u32,
+) -> Matrix4<f32> {
+    let center = corners
+        .iter()
+        .fold(Vector3::zero(), |sum, c| sum + c.to_vec())
+        / corners.len() as f32;
+    let center = Point3::from_vec(center);
+    let radius = corners
+        .iter()
+        .map(|c| c.distance(center))
+        .fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let view = Matrix4::look_at_dir(center, direction.normalize(), light_up(direction));
+    let mut proj = ortho(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        -radius - caster_distance,
+        radius,
+    );
+
+    let half_resolution = resolution as f32 / 2.0;
+    let origin = (proj * view).transform_point(Point3::origin());
+    let snapped = Vector2::new(
+        (origin.x * half_resolution).round() / half_resolution,
+        (origin.y * half_resolution).round() / half_resolution,
+    );
+    proj.w.x += snapped.x - origin.x;
+    proj.w.y += snapped.y - origin.y;
+
+    OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+fn spot_matrix(
+    position: Vector3<f32>,
+    direction: Vector3<f32>,
+    outer_angle: Rad<f32>,
+    range: f32,
+) -> Matrix4<f32> {
+    let view = Matrix4::look_at_dir(
+        Point3::from_vec(position),
+        direction.normalize(),
+        light_up(direction),
+    );
+    // Perspective projections break down as the angle nears 180°
+    let max_fovy = Deg(170.0).into();
+    let fovy = if outer_angle * 2.0 > max_fovy {
+        max_fovy
+    } else {
+        outer_angle * 2.0
+    };
+    let near = (range * 0.001).max(0.05);
+    OPENGL_TO_WGPU_MATRIX * perspective(fovy, 1.0, near, range.max(near * 2.0)) * view
+}
+
+/// How far a light reaches before it's dimmed to 1/256th, which
+/// rounds to black in an 8 bit render target.
+fn attenuation_range(attenuation: &Attenuation, fallback: f32) -> f32 {
+    let Attenuation {
+        constant: c,
+        linear: l,
+        quadratic: q,
+    } = *attenuation;
+    let target = 256.0;
+    if q > 0.0 {
+        (-l + (l * l - 4.0 * q * (c - target)).sqrt()) / (2.0 * q)
+    } else if l > 0.0 {
+        (target - c) / l
+    } else {
+        fallback
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reflect::*;
+
+    #[test]
+    fn splits_blend_log_and_uniform() {
+        let uniform = cascade_splits(1.0, 101.0, 4, 0.0);
+        assert_eq!(uniform, vec![26.0, 51.0, 76.0, 101.0]);
+
+        let log = cascade_splits(1.0, 10000.0, 4, 1.0);
+        for (split, expected) in log.iter().zip(&[10.0, 100.0, 1000.0, 10000.0]) {
+            assert!((split - expected).abs() / expected < 1e-4);
+        }
+    }
+
+    #[test]
+    fn free_layers_are_contiguous() {
+        let used = [true, false, true, false, false, false];
+        assert_eq!(find_free_layers(&used, 1), Some(1));
+        assert_eq!(find_free_layers(&used, 3), Some(3));
+        assert_eq!(find_free_layers(&used, 4), None);
+    }
+
+    #[test]
+    fn cascades_contain_their_frustum_slice() {
+        let camera = Camera::new((0.0, 5.0, 10.0), Deg(-90.0), Deg(-20.0));
+        let projection = Projection::new(1600, 900, Deg(45.0), 0.1, 100.0);
+        let corners = frustum_corners(camera.calc_matrix(), &projection, 5.0, 20.0);
+        let matrix = cascade_matrix(&corners, Vector3::new(-1.0, -2.0, -0.5), 50.0, 2048);
+        for corner in &corners {
+            let ndc = matrix.transform_point(*corner);
+            assert!(ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0, "{:?}", ndc);
+            assert!(ndc.z >= 0.0 && ndc.z <= 1.0, "{:?}", ndc);
+        }
+    }
+
+    #[test]
+    fn range_matches_attenuation() {
+        let attenuation = Attenuation::range(10.0);
+        let range = attenuation_range(&attenuation, 1.0);
+        let d = range;
+        let at_range =
+            1.0 / (attenuation.constant + attenuation.linear * d + attenuation.quadratic * d * d);
+        assert!((at_range - 1.0 / 256.0).abs() < 1e-5);
+        assert_eq!(attenuation_range(&Attenuation::NONE, 42.0), 42.0);
+    }
+
+    #[test]
+    fn pbr_shadow_variant_matches_layout() {
+        let fs =
+            ShaderReflection::from_bytes(include_bytes!("shaders/pbr.shadows.frag.spv")).unwrap();
+        let layouts = reflect_bind_group_layouts(&[&fs]).unwrap();
+        assert_eq!(layouts[3].entries, shadow_bind_group_layout_entries());
+        assert_eq!(mem::size_of::<ShadowUniform>() % 16, 0);
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::*;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::pipeline::RenderPipelineBuilder;
+use crate::texture::Texture;
+
+const CUBE_MAP_ENTRIES: [wgpu::BindGroupLayoutEntry; 2] = [
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            multisampled: false,
+            dimension: wgpu::TextureViewDimension::Cube,
+            component_type: wgpu::TextureComponentType::Float,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler { comparison: false },
+        count: None,
+    },
+];
+
+const ENVIRONMENT_BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 3] = [
+    CUBE_MAP_ENTRIES[0],
+    CUBE_MAP_ENTRIES[1],
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::UniformBuffer {
+            dynamic: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+fn cube_map_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    cube_map: &Texture,
+    label: &str,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&cube_map.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&cube_map.sampler),
+            },
+        ],
+    })
+}
+
+/// Draws a cube map around the camera. The cube map is group 0 and
+/// `framework::Uniforms` group 1, like the model materials.
+pub struct Skybox {
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Skybox {
+    /// `cube_map` needs a [wgpu::TextureViewDimension::Cube] view, such
+    /// as the ones from [Texture::cube_from_images] and
+    /// [EquirectangularConverter](crate::EquirectangularConverter).
+    /// `uniform_layout` is [UniformBinding::layout](crate::UniformBinding).
+    ///
+    /// With a `depth_format`, the sky is drawn on the far plane and
+    /// tested against the depth buffer, so it can be drawn after the
+    /// scene in the same pass and only fills in what's left. Without
+    /// one, draw it first.
+    pub fn new(
+        device: &wgpu::Device,
+        cube_map: &Texture,
+        uniform_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Result<Self> {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Skybox::layout"),
+            entries: &CUBE_MAP_ENTRIES,
+        });
+        let bind_group = cube_map_bind_group(device, &layout, cube_map, "Skybox::bind_group");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skybox::pipeline_layout"),
+            bind_group_layouts: &[&layout, uniform_layout],
+            push_constant_ranges: &[],
+        });
+
+        let mut builder = RenderPipelineBuilder::new();
+        builder
+            .layout(&pipeline_layout)
+            .vertex_shader(wgpu::include_spirv!("shaders/skybox.vert.spv"))
+            .fragment_shader(wgpu::include_spirv!("shaders/skybox.frag.spv"))
+            .primitive_topology(wgpu::PrimitiveTopology::TriangleStrip)
+            .color_solid(color_format);
+        if let Some(format) = depth_format {
+            // The scene already wrote its depth, and the far plane is
+            // only equal to the cleared depth
+            builder.depth_no_stencil(format, false, wgpu::CompareFunction::LessEqual);
+        }
+        let pipeline = builder
+            .build(device)
+            .context("Unable to create the skybox pipeline")?;
+
+        Ok(Self {
+            layout,
+            bind_group,
+            pipeline,
+        })
+    }
+
+    /// Switches to another cube map, keeping the pipeline.
+    pub fn set_cube_map(&mut self, device: &wgpu::Device, cube_map: &Texture) {
+        self.bind_group = cube_map_bind_group(device, &self.layout, cube_map, "Skybox::bind_group");
+    }
+}
+
+pub trait DrawSkybox<'a, 'b>
+where
+    'b: 'a,
+{
+    fn draw_skybox(&mut self, skybox: &'b Skybox, uniforms: &'b wgpu::BindGroup);
+}
+
+impl<'a, 'b> DrawSkybox<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_skybox(&mut self, skybox: &'b Skybox, uniforms: &'b wgpu::BindGroup) {
+        self.set_pipeline(&skybox.pipeline);
+        self.set_bind_group(0, &skybox.bind_group, &[]);
+        self.set_bind_group(1, uniforms, &[]);
+        self.draw(0..4, 0..1);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct EnvironmentUniform {
+    intensity: f32,
+    max_lod: f32,
+    _padding: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for EnvironmentUniform {}
+unsafe impl bytemuck::Pod for EnvironmentUniform {}
+
+/// A cube map lighting surfaces from every direction, for
+/// [PbrMaterial::fragment_shader_with_environment](crate::PbrMaterial).
+/// Bind it to group 3, or to group 4 after the shadow maps, which needs
+/// a device that allows 5 bind groups.
+pub struct EnvironmentMap {
+    intensity: f32,
+    max_lod: f32,
+    buffer: wgpu::Buffer,
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl EnvironmentMap {
+    /// `cube_map` should have a full mip chain, which stands in for
+    /// blurring it by roughness.
+    pub fn new(device: &wgpu::Device, cube_map: &Texture, intensity: f32) -> Self {
+        // Levels smaller than 8x8 are too blocky to light with
+        let max_lod = (cube_map.desc.mip_level_count as f32 - 4.0).max(0.0);
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("EnvironmentMap::buffer"),
+            contents: bytemuck::cast_slice(&[EnvironmentUniform {
+                intensity,
+                max_lod,
+                _padding: [0.0; 2],
+            }]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("EnvironmentMap::layout"),
+            entries: &ENVIRONMENT_BIND_GROUP_LAYOUT_ENTRIES,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("EnvironmentMap::bind_group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cube_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&cube_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+                },
+            ],
+        });
+        Self {
+            intensity,
+            max_lod,
+            buffer,
+            layout,
+            bind_group,
+        }
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn set_intensity(&mut self, queue: &wgpu::Queue, intensity: f32) {
+        self.intensity = intensity;
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[EnvironmentUniform {
+                intensity,
+                max_lod: self.max_lod,
+                _padding: [0.0; 2],
+            }]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reflect::*;
+
+    #[test]
+    fn skybox_shaders_match_layout() {
+        let vs = ShaderReflection::from_bytes(include_bytes!("shaders/skybox.vert.spv")).unwrap();
+        let fs = ShaderReflection::from_bytes(include_bytes!("shaders/skybox.frag.spv")).unwrap();
+        let layouts = reflect_bind_group_layouts(&[&vs, &fs]).unwrap();
+        assert_eq!(layouts[0].entries, CUBE_MAP_ENTRIES);
+        // Only the vertex shader reads the uniforms
+        assert_eq!(layouts[1].entries.len(), 1);
+    }
+
+    #[test]
+    fn pbr_environment_variant_matches_layout() {
+        let fs = ShaderReflection::from_bytes(include_bytes!("shaders/pbr.environment.frag.spv"))
+            .unwrap();
+        let layouts = reflect_bind_group_layouts(&[&fs]).unwrap();
+        // Without shadows the environment fits in the 4 bind groups
+        // every device allows
+        assert_eq!(layouts.len(), 4);
+        assert_eq!(layouts[3].entries, ENVIRONMENT_BIND_GROUP_LAYOUT_ENTRIES);
+        assert_eq!(std::mem::size_of::<EnvironmentUniform>() % 16, 0);
+    }
+
+    #[test]
+    fn pbr_shadows_and_environment_dont_overlap() {
+        let fs = ShaderReflection::from_bytes(include_bytes!(
+            "shaders/pbr.shadows_environment.frag.spv"
+        ))
+        .unwrap();
+        let layouts = reflect_bind_group_layouts(&[&fs]).unwrap();
+        assert_eq!(
+            layouts[3].entries,
+            crate::shadow_bind_group_layout_entries()
+        );
+        assert_eq!(layouts[4].entries, ENVIRONMENT_BIND_GROUP_LAYOUT_ENTRIES);
+    }
+}
@@ -0,0 +1,531 @@
+use anyhow::*;
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+
+use crate::buffer::{Buffer, RawBuffer, ToRaw};
+use crate::compute::{dispatch, workgroup_count};
+use crate::pipeline::ComputePipelineBuilder;
+
+/// How much each triangle touching a vertex contributes to the
+/// vertex's tangent.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TangentWeighting {
+    /// By the angle of the triangle's corner at the vertex. This
+    /// doesn't depend on how a surface is split into triangles, which
+    /// is what MikkTSpace does as well.
+    Angle,
+    /// By the area of the triangle.
+    Area,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TangentOptions {
+    pub weighting: TangentWeighting,
+    /// Triangles whose UV or position area is smaller than this are
+    /// skipped, as they don't have a meaningful tangent.
+    pub degenerate_epsilon: f32,
+}
+
+impl Default for TangentOptions {
+    fn default() -> Self {
+        Self {
+            weighting: TangentWeighting::Angle,
+            degenerate_epsilon: 1e-8,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tangent {
+    pub tangent: Vector3<f32>,
+    pub bitangent: Vector3<f32>,
+    /// -1.0 where the UVs are mirrored, 1.0 otherwise. The bitangent
+    /// is always `normal.cross(tangent) * handedness`.
+    pub handedness: f32,
+}
+
+/// Calculates a tangent frame for every vertex of an indexed triangle
+/// list.
+///
+/// The tangents of every triangle sharing a vertex are accumulated,
+/// then made orthogonal to the vertex normal with Gram-Schmidt.
+/// Vertices that only belong to degenerate triangles get an arbitrary
+/// tangent that is still perpendicular to the normal, so the result
+/// never contains NaNs.
+pub fn generate_tangents(
+    positions: &[Vector3<f32>],
+    normals: &[Vector3<f32>],
+    tex_coords: &[Vector2<f32>],
+    indices: &[u32],
+    options: &TangentOptions,
+) -> Vec<Tangent> {
+    let vertex_count = positions.len();
+    let mut tangents = vec![Vector3::zero(); vertex_count];
+    let mut bitangents = vec![Vector3::zero(); vertex_count];
+
+    for triangle in indices.chunks_exact(3) {
+        let i = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        if i.iter()
+            .any(|&v| v >= vertex_count || v >= tex_coords.len())
+        {
+            continue;
+        }
+
+        let delta_pos1 = positions[i[1]] - positions[i[0]];
+        let delta_pos2 = positions[i[2]] - positions[i[0]];
+        let delta_uv1 = tex_coords[i[1]] - tex_coords[i[0]];
+        let delta_uv2 = tex_coords[i[2]] - tex_coords[i[0]];
+
+        // Solving the following system of equations gives us the
+        // tangent and bitangent.
+        //     delta_pos1 = delta_uv1.x * T + delta_uv1.y * B
+        //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
+        let uv_area = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+        let area = delta_pos1.cross(delta_pos2).magnitude();
+        if uv_area.abs() < options.degenerate_epsilon || area < options.degenerate_epsilon {
+            continue;
+        }
+        let r = 1.0 / uv_area;
+        let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+        let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r;
+        if !is_finite(tangent) || !is_finite(bitangent) {
+            continue;
+        }
+        let tangent = tangent.normalize();
+        let bitangent = bitangent.normalize();
+
+        for corner in 0..3 {
+            let weight = match options.weighting {
+                TangentWeighting::Angle => {
+                    let p = positions[i[corner]];
+                    let a = positions[i[(corner + 1) % 3]] - p;
+                    let b = positions[i[(corner + 2) % 3]] - p;
+                    corner_angle(a, b)
+                }
+                TangentWeighting::Area => area,
+            };
+            tangents[i[corner]] += tangent * weight;
+            bitangents[i[corner]] += bitangent * weight;
+        }
+    }
+
+    (0..vertex_count)
+        .map(|i| {
+            let normal = normals
+                .get(i)
+                .copied()
+                .filter(|n| n.magnitude2() > 0.0)
+                .map(|n| n.normalize())
+                .unwrap_or_else(Vector3::unit_z);
+
+            // Gram-Schmidt
+            let t = tangents[i] - normal * normal.dot(tangents[i]);
+            let tangent = if t.magnitude2() > options.degenerate_epsilon {
+                t.normalize()
+            } else {
+                any_perpendicular(normal)
+            };
+
+            let handedness = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+
+            Tangent {
+                tangent,
+                bitangent: normal.cross(tangent) * handedness,
+                handedness,
+            }
+        })
+        .collect()
+}
+
+/// The `Tangent` struct from `tangent.comp`, with the handedness in
+/// `tangent[3]`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TangentData {
+    pub tangent: [f32; 4],
+    pub bitangent: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for TangentData {}
+unsafe impl bytemuck::Zeroable for TangentData {}
+
+impl ToRaw for Tangent {
+    type Output = TangentData;
+    fn to_raw(&self) -> TangentData {
+        TangentData {
+            tangent: self.tangent.extend(self.handedness).into(),
+            bitangent: self.bitangent.extend(0.0).into(),
+        }
+    }
+}
+
+/// The `Options` uniform from `tangent.comp`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct TangentUniform {
+    vertex_count: u32,
+    weighting: u32,
+    degenerate_epsilon: f32,
+    _padding: u32,
+}
+
+unsafe impl bytemuck::Zeroable for TangentUniform {}
+unsafe impl bytemuck::Pod for TangentUniform {}
+
+// Has to match local_size_x in tangent.comp
+const TANGENT_WORKGROUP_SIZE: u32 = 64;
+
+/// Does the same as [generate_tangents] with a compute shader, for
+/// meshes big enough that the CPU version is slow to load.
+///
+/// Each vertex sums the triangles that touch it in the same order as
+/// the CPU version, so the results only differ by float rounding. The
+/// list of which triangles touch which vertex is still built on the
+/// CPU, as it needs a sort.
+pub async fn generate_tangents_gpu(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    positions: &[Vector3<f32>],
+    normals: &[Vector3<f32>],
+    tex_coords: &[Vector2<f32>],
+    indices: &[u32],
+    options: &TangentOptions,
+) -> Result<Vec<Tangent>> {
+    let vertex_count = positions.len();
+    if vertex_count == 0 {
+        return Ok(Vec::new());
+    }
+    let (offsets, corners) = vertex_corners(vertex_count, tex_coords.len(), indices);
+
+    // Missing normals and UVs are zeros, which the shader treats the
+    // same way generate_tangents does
+    let positions = positions
+        .iter()
+        .map(|p| p.extend(1.0).into())
+        .collect::<Vec<[f32; 4]>>();
+    let normals = (0..vertex_count)
+        .map(|i| normals.get(i).map_or([0.0; 4], |n| n.extend(0.0).into()))
+        .collect::<Vec<[f32; 4]>>();
+    let tex_coords = (0..vertex_count)
+        .map(|i| tex_coords.get(i).map_or([0.0; 2], |&uv| uv.into()))
+        .collect::<Vec<[f32; 2]>>();
+
+    let uniform = TangentUniform {
+        vertex_count: vertex_count as u32,
+        weighting: match options.weighting {
+            TangentWeighting::Angle => 0,
+            TangentWeighting::Area => 1,
+        },
+        degenerate_epsilon: options.degenerate_epsilon,
+        _padding: 0,
+    };
+    let usage = wgpu::BufferUsage::STORAGE;
+    let uniform = RawBuffer::from_vec(device, vec![uniform], wgpu::BufferUsage::UNIFORM);
+    let positions = RawBuffer::from_vec(device, positions, usage);
+    let normals = RawBuffer::from_vec(device, normals, usage);
+    let tex_coords = RawBuffer::from_vec(device, tex_coords, usage);
+    // Bindings can't be empty, so meshes without triangles get a
+    // dummy index that's never read
+    let indices = RawBuffer::from_vec(device, non_empty(indices.to_vec()), usage);
+    let offsets = RawBuffer::from_vec(device, offsets, usage);
+    let corners = RawBuffer::from_vec(device, non_empty(corners), usage);
+
+    let placeholder = Tangent {
+        tangent: Vector3::zero(),
+        bitangent: Vector3::zero(),
+        handedness: 1.0,
+    };
+    let output = Buffer::storage(device, vec![placeholder; vertex_count]);
+    let staging = Buffer::staging(device, &output);
+
+    let mut builder = ComputePipelineBuilder::new();
+    builder.shader(wgpu::include_spirv!("shaders/tangent.comp.spv"));
+    let layouts = builder.bind_group_layouts()?;
+    let bind_group_layout = layouts[0].create(device, Some("Tangents"));
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Tangents"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = builder.layout(&layout).build(device)?;
+
+    let buffers = [
+        &uniform.buffer,
+        &positions.buffer,
+        &normals.buffer,
+        &tex_coords.buffer,
+        &indices.buffer,
+        &offsets.buffer,
+        &corners.buffer,
+        &output.raw_buffer.buffer,
+    ];
+    let entries = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+        })
+        .collect::<Vec<_>>();
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Tangents"),
+        layout: &bind_group_layout,
+        entries: &entries,
+    });
+
+    let workgroups = workgroup_count(vertex_count as u32, TANGENT_WORKGROUP_SIZE);
+    let data = dispatch(
+        device,
+        queue,
+        &pipeline,
+        &[&bind_group],
+        (workgroups, 1, 1),
+        &output,
+        &staging,
+    )
+    .await?;
+
+    Ok(data
+        .iter()
+        .map(|d| Tangent {
+            tangent: Vector3::new(d.tangent[0], d.tangent[1], d.tangent[2]),
+            bitangent: Vector3::new(d.bitangent[0], d.bitangent[1], d.bitangent[2]),
+            handedness: d.tangent[3],
+        })
+        .collect())
+}
+
+/// Lists the triangle corners each vertex belongs to, as
+/// `triangle * 3 + corner`. Vertex `v`'s corners are
+/// `corners[offsets[v]..offsets[v + 1]]`, in triangle order. Triangles
+/// with out of range indices are left out, like in [generate_tangents].
+fn vertex_corners(
+    vertex_count: usize,
+    tex_coord_count: usize,
+    indices: &[u32],
+) -> (Vec<u32>, Vec<u32>) {
+    let valid_triangles = || {
+        indices
+            .chunks_exact(3)
+            .enumerate()
+            .filter(move |(_, triangle)| {
+                triangle
+                    .iter()
+                    .all(|&v| (v as usize) < vertex_count && (v as usize) < tex_coord_count)
+            })
+    };
+
+    let mut offsets = vec![0; vertex_count + 1];
+    for (_, triangle) in valid_triangles() {
+        for &v in triangle {
+            offsets[v as usize + 1] += 1;
+        }
+    }
+    for v in 0..vertex_count {
+        offsets[v + 1] += offsets[v];
+    }
+
+    let mut next = offsets.clone();
+    let mut corners = vec![0; offsets[vertex_count] as usize];
+    for (t, triangle) in valid_triangles() {
+        for (corner, &v) in triangle.iter().enumerate() {
+            corners[next[v as usize] as usize] = (t * 3 + corner) as u32;
+            next[v as usize] += 1;
+        }
+    }
+    (offsets, corners)
+}
+
+fn non_empty(mut data: Vec<u32>) -> Vec<u32> {
+    if data.is_empty() {
+        data.push(0);
+    }
+    data
+}
+
+fn is_finite(v: Vector3<f32>) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+fn corner_angle(a: Vector3<f32>, b: Vector3<f32>) -> f32 {
+    let lengths = a.magnitude() * b.magnitude();
+    if lengths <= 0.0 {
+        return 0.0;
+    }
+    (a.dot(b) / lengths).clamp(-1.0, 1.0).acos()
+}
+
+fn any_perpendicular(normal: Vector3<f32>) -> Vector3<f32> {
+    // Cross with whichever axis is furthest from the normal so the
+    // result isn't tiny
+    let axis = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let t = axis - normal * normal.dot(axis);
+    t.normalize()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_orthonormal(t: &Tangent, normal: Vector3<f32>) {
+        assert!((t.tangent.magnitude() - 1.0).abs() < 1e-5);
+        assert!((t.bitangent.magnitude() - 1.0).abs() < 1e-5);
+        assert!(t.tangent.dot(normal).abs() < 1e-5);
+        assert!(t.bitangent.dot(normal).abs() < 1e-5);
+        assert!(t.tangent.dot(t.bitangent).abs() < 1e-5);
+    }
+
+    /// A unit quad in the XY plane facing +Z
+    fn quad(tex_coords: [[f32; 2]; 4]) -> Vec<Tangent> {
+        let positions = vec![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        ];
+        let normals = vec![Vector3::unit_z(); 4];
+        let tex_coords = tex_coords.iter().map(|&uv| uv.into()).collect::<Vec<_>>();
+        let indices = [0, 1, 2, 0, 2, 3];
+        generate_tangents(
+            &positions,
+            &normals,
+            &tex_coords,
+            &indices,
+            &TangentOptions::default(),
+        )
+    }
+
+    #[test]
+    fn quad_tangents_follow_uvs() {
+        let tangents = quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        for t in tangents {
+            assert_close(t.tangent, Vector3::unit_x());
+            assert_close(t.bitangent, Vector3::unit_y());
+            assert_eq!(t.handedness, 1.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_handedness() {
+        let tangents = quad([[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+        for t in tangents {
+            assert_close(t.tangent, -Vector3::unit_x());
+            assert_close(t.bitangent, Vector3::unit_y());
+            assert_eq!(t.handedness, -1.0);
+        }
+    }
+
+    #[test]
+    fn degenerate_uvs_produce_valid_tangents() {
+        let tangents = quad([[0.5, 0.5]; 4]);
+        for t in tangents {
+            assert!(is_finite(t.tangent) && is_finite(t.bitangent));
+            assert_orthonormal(&t, Vector3::unit_z());
+        }
+    }
+
+    #[test]
+    fn shared_vertices_are_averaged() {
+        // Two triangles share vertex 0. The first has its tangent
+        // along +X, the second along +Y, and both have a right angle
+        // at vertex 0, so the result should be halfway between. The
+        // second triangle can't share vertex 2's UVs, so it has its
+        // own copy of that position.
+        let positions = vec![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        ];
+        let normals = vec![Vector3::unit_z(); 5];
+        let tex_coords = vec![
+            Vector2::new(0.0, 0.0),
+            Vector2::new(1.0, 0.0),
+            Vector2::new(0.0, 1.0),
+            Vector2::new(0.0, 1.0),
+            Vector2::new(1.0, 0.0),
+        ];
+        let indices = [0, 1, 2, 0, 4, 3];
+
+        let tangents = generate_tangents(
+            &positions,
+            &normals,
+            &tex_coords,
+            &indices,
+            &TangentOptions::default(),
+        );
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(tangents[0].tangent, Vector3::new(half, half, 0.0));
+        assert_orthonormal(&tangents[0], Vector3::unit_z());
+    }
+
+    #[test]
+    fn tangents_are_orthogonal_to_bent_normals() {
+        let tangents = quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        // Same quad, but with normals that aren't perpendicular to the
+        // surface, like on a smooth shaded mesh
+        let positions = vec![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        ];
+        let normal = Vector3::new(0.3, 0.0, 1.0).normalize();
+        let tex_coords = vec![
+            Vector2::new(0.0, 0.0),
+            Vector2::new(1.0, 0.0),
+            Vector2::new(1.0, 1.0),
+            Vector2::new(0.0, 1.0),
+        ];
+        let bent = generate_tangents(
+            &positions,
+            &[normal; 4],
+            &tex_coords,
+            &[0, 1, 2, 0, 2, 3],
+            &TangentOptions::default(),
+        );
+        for (flat, bent) in tangents.iter().zip(&bent) {
+            assert_orthonormal(bent, normal);
+            assert!(flat.tangent.dot(bent.tangent) > 0.9);
+        }
+    }
+
+    #[test]
+    fn vertex_corners_are_in_triangle_order() {
+        // The second triangle is out of range and left out
+        let (offsets, corners) = vertex_corners(4, 4, &[0, 1, 2, 0, 1, 4, 2, 3, 0]);
+        assert_eq!(offsets, vec![0, 2, 3, 5, 6]);
+        assert_eq!(corners, vec![0, 8, 1, 2, 6, 7]);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_ignored() {
+        let positions = vec![Vector3::new(0.0, 0.0, 0.0)];
+        let tangents = generate_tangents(
+            &positions,
+            &[Vector3::unit_z()],
+            &[Vector2::new(0.0, 0.0)],
+            &[0, 1, 2],
+            &TangentOptions::default(),
+        );
+        assert_eq!(tangents.len(), 1);
+        assert_orthonormal(&tangents[0], Vector3::unit_z());
+    }
+}
@@ -1,48 +1,64 @@
 use anyhow::*;
 use image::GenericImageView;
-use std::mem;
 use std::path::Path;
+use std::sync::Arc;
+use std::{iter, mem};
 
-use crate::buffer;
+use crate::compressed::TextureData;
+use crate::cubemap::CUBE_FACES;
+use crate::mipmap::{generate_mipmaps_cpu, mip_level_count, Mipmaps};
+use crate::sampler::{SamplerCache, SamplerConfig};
 
 pub struct Texture<'a> {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler,
+    /// Shared with every other texture that was created with the same
+    /// [SamplerConfig] and [SamplerCache].
+    pub sampler: Arc<wgpu::Sampler>,
     pub desc: wgpu::TextureDescriptor<'a>,
 }
 
 impl<'a> Texture<'a> {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+    /// Loads an image, or a DDS or KTX2 file with
+    /// [Texture::from_data].
     pub fn load<P: AsRef<Path>>(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         path: P,
         is_normal_map: bool,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
     ) -> Result<Self> {
         let path_copy = path.as_ref().to_path_buf();
         let label = path_copy.to_str().unwrap();
+        if TextureData::is_container(&path) {
+            let data = TextureData::load(path, !is_normal_map)?;
+            return Self::from_data(device, queue, &data, Some(label), samplers, sampler);
+        }
         let img = image::open(path)?;
-        Self::from_image(device, queue, &img, Some(label), is_normal_map)
+        Self::from_image(
+            device,
+            queue,
+            &img,
+            Some(label),
+            is_normal_map,
+            samplers,
+            sampler,
+        )
     }
 
-    pub fn from_descriptor(device: &wgpu::Device, desc: wgpu::TextureDescriptor<'a>) -> Self {
+    pub fn from_descriptor(
+        device: &wgpu::Device,
+        desc: wgpu::TextureDescriptor<'a>,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
+    ) -> Self {
         let texture = device.create_texture(&desc);
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare: Some(wgpu::CompareFunction::LessEqual),
-            ..Default::default()
-        });
+        let sampler = samplers.get(device, sampler);
 
         Self {
             texture,
@@ -58,69 +74,405 @@ impl<'a> Texture<'a> {
         label: Option<&str>,
         is_normal_map: bool,
         bytes: &[u8],
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
     ) -> Result<Self> {
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, label, is_normal_map)
+        Self::from_image(device, queue, &img, label, is_normal_map, samplers, sampler)
+    }
+
+    /// A 1x1 texture of a single color, used in place of textures a
+    /// material doesn't have.
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        label: Option<&str>,
+        is_normal_map: bool,
+        samplers: &SamplerCache,
+    ) -> Result<Self> {
+        let img =
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        // Every texel of a 1x1 texture is the same, so how it's
+        // sampled doesn't matter
+        let sampler = SamplerConfig::default();
+        Self::from_image(
+            device,
+            queue,
+            &img,
+            label,
+            is_normal_map,
+            samplers,
+            &sampler,
+        )
     }
 
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
-        _label: Option<&str>,
+        label: Option<&str>,
+        is_normal_map: bool,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
+    ) -> Result<Self> {
+        Self::from_image_with_mipmaps(
+            device,
+            queue,
+            img,
+            label,
+            is_normal_map,
+            Mipmaps::None,
+            samplers,
+            sampler,
+        )
+    }
+
+    /// Like [Texture::load], but with a full mip chain. See
+    /// [Texture::from_image_with_mipmaps]. DDS and KTX2 files keep the
+    /// mip levels they were saved with.
+    pub fn load_with_mipmaps<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+        is_normal_map: bool,
+        mipmaps: Mipmaps,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
+    ) -> Result<Self> {
+        let path_copy = path.as_ref().to_path_buf();
+        let label = path_copy.to_str().unwrap();
+        if TextureData::is_container(&path) {
+            let data = TextureData::load(path, !is_normal_map)?;
+            return Self::from_data(device, queue, &data, Some(label), samplers, sampler);
+        }
+        let img = image::open(path)?;
+        Self::from_image_with_mipmaps(
+            device,
+            queue,
+            &img,
+            Some(label),
+            is_normal_map,
+            mipmaps,
+            samplers,
+            sampler,
+        )
+    }
+
+    /// Uploads `img`, making every mip level down to 1x1 unless
+    /// `mipmaps` is [Mipmaps::None]. The default [SamplerConfig]
+    /// filters between the levels.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_image_with_mipmaps(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
         is_normal_map: bool,
+        mipmaps: Mipmaps,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
     ) -> Result<Self> {
         let rgba = img.to_rgba();
         let dimensions = img.dimensions();
+        let format = if is_normal_map {
+            wgpu::TextureFormat::Rgba8Unorm
+        } else {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        };
 
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth: 1,
+        let mip_level_count = match mipmaps {
+            Mipmaps::None => 1,
+            Mipmaps::Gpu(_) | Mipmaps::Cpu => mip_level_count(dimensions.0, dimensions.1),
         };
+        let mut usage = wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST;
+        if let Mipmaps::Gpu(_) = mipmaps {
+            usage |= wgpu::TextureUsage::OUTPUT_ATTACHMENT;
+        }
         let desc = wgpu::TextureDescriptor {
-            size,
-            mip_level_count: 1,
+            size: wgpu::Extent3d {
+                width: dimensions.0,
+                height: dimensions.1,
+                depth: 1,
+            },
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: if is_normal_map {
-                wgpu::TextureFormat::Rgba8Unorm
-            } else {
-                wgpu::TextureFormat::Rgba8UnormSrgb
-            },
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            format,
+            usage,
             label: None,
         };
-        let texture = device.create_texture(&desc);
+        let texture = device.create_texture(&wgpu::TextureDescriptor { label, ..desc });
 
-        queue.write_texture(
-            wgpu::TextureCopyView {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+        let write_level = |mip_level: u32, level: &image::RgbaImage| {
+            let (width, height) = level.dimensions();
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                level,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * width,
+                    rows_per_image: height,
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            );
+        };
+        write_level(0, &rgba);
+
+        match mipmaps {
+            Mipmaps::None => {}
+            Mipmaps::Gpu(generator) => {
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Texture::from_image_with_mipmaps"),
+                });
+                generator.generate(device, &mut encoder, &texture, &desc)?;
+                queue.submit(iter::once(encoder.finish()));
+            }
+            Mipmaps::Cpu => {
+                for (i, level) in generate_mipmaps_cpu(&rgba, !is_normal_map)
+                    .iter()
+                    .enumerate()
+                {
+                    write_level(i as u32 + 1, level);
+                }
+            }
+        }
+
+        let view = texture.create_view(&Default::default());
+        let sampler = samplers.get(device, sampler);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            desc,
+        })
+    }
+
+    /// Like [Texture::cube_from_images], loading the faces from
+    /// `paths`.
+    pub fn load_cube<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        paths: &[P],
+        mipmaps: Mipmaps,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
+    ) -> Result<Self> {
+        let faces = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                image::open(path).with_context(|| format!("Unable to load {}", path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let label = paths.first().and_then(|path| path.as_ref().to_str());
+        Self::cube_from_images(device, queue, &faces, label, mipmaps, samplers, sampler)
+    }
+
+    /// A cube map made from six square sRGB images of the same size, in
+    /// the order +X, -X, +Y, -Y, +Z, -Z. The view is a
+    /// [wgpu::TextureViewDimension::Cube].
+    pub fn cube_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: Option<&str>,
+        mipmaps: Mipmaps,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
+    ) -> Result<Self> {
+        if faces.len() != CUBE_FACES as usize {
+            bail!("Cube maps need {} faces, got {}", CUBE_FACES, faces.len());
+        }
+        let (width, height) = faces[0].dimensions();
+        if width != height {
+            bail!("Cube map faces need to be square, got {}x{}", width, height);
+        }
+        if faces
+            .iter()
+            .any(|face| face.dimensions() != (width, height))
+        {
+            bail!("Cube map faces need to be the same size");
+        }
+
+        let mip_level_count = match mipmaps {
+            Mipmaps::None => 1,
+            Mipmaps::Gpu(_) | Mipmaps::Cpu => mip_level_count(width, height),
+        };
+        let mut usage = wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST;
+        if let Mipmaps::Gpu(_) = mipmaps {
+            usage |= wgpu::TextureUsage::OUTPUT_ATTACHMENT;
+        }
+        let desc = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: CUBE_FACES,
             },
-            &rgba,
-            wgpu::TextureDataLayout {
-                offset: 0,
-                bytes_per_row: 4 * dimensions.0,
-                rows_per_image: dimensions.1,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage,
+            label: None,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor { label, ..desc });
+
+        let write_level = |face: u32, mip_level: u32, level: &image::RgbaImage| {
+            let (width, height) = level.dimensions();
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: face,
+                    },
+                },
+                level,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * width,
+                    rows_per_image: height,
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            );
+        };
+        for (face, img) in faces.iter().enumerate() {
+            let rgba = img.to_rgba();
+            write_level(face as u32, 0, &rgba);
+            if let Mipmaps::Cpu = mipmaps {
+                for (i, level) in generate_mipmaps_cpu(&rgba, true).iter().enumerate() {
+                    write_level(face as u32, i as u32 + 1, level);
+                }
+            }
+        }
+        if let Mipmaps::Gpu(generator) = mipmaps {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Texture::cube_from_images"),
+            });
+            generator.generate(device, &mut encoder, &texture, &desc)?;
+            queue.submit(iter::once(encoder.finish()));
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = samplers.get(device, sampler);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            desc,
+        })
+    }
+
+    /// Uploads every mip level of `data` as it is. Block compressed
+    /// textures are decompressed on the CPU first when the device
+    /// wasn't created with [wgpu::Features::TEXTURE_COMPRESSION_BC].
+    ///
+    /// wgpu only copies whole 4x4 blocks, so compressed levels smaller
+    /// than that are left out and the smallest level that remains is
+    /// used in their place.
+    pub fn from_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &TextureData,
+        label: Option<&str>,
+        samplers: &SamplerCache,
+        sampler: &SamplerConfig,
+    ) -> Result<Self> {
+        let supports_bc = device
+            .features()
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_BC);
+        let decompressed;
+        let data = if data.is_compressed() && (!supports_bc || data.whole_block_levels() == 0) {
+            decompressed = data.decompress()?;
+            &decompressed
+        } else {
+            data
+        };
+        if data.levels.is_empty() {
+            bail!("Texture data has no mip levels");
+        }
+        if data.width == 0 || data.height == 0 || data.layers == 0 {
+            bail!(
+                "Texture data is {}x{} with {} layers, which has no texels",
+                data.width,
+                data.height,
+                data.layers
+            );
+        }
+
+        let desc = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: data.width,
+                height: data.height,
+                depth: data.layers,
             },
-            size,
-        );
+            mip_level_count: data.whole_block_levels(),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: data.format,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            label: None,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor { label, ..desc });
 
-        let view = texture.create_view(&Default::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare: Some(wgpu::CompareFunction::Always),
+        for mip_level in 0..desc.mip_level_count {
+            let (width, height) = data.level_size(mip_level as usize);
+            let (bytes_per_row, _) = data.level_layout(mip_level as usize);
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &data.levels[mip_level as usize],
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row,
+                    // In texels rather than blocks
+                    rows_per_image: height,
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth: data.layers,
+                },
+            );
+        }
+
+        let dimension = if data.is_cube && data.layers == 6 {
+            wgpu::TextureViewDimension::Cube
+        } else if data.is_cube {
+            wgpu::TextureViewDimension::CubeArray
+        } else if data.layers > 1 {
+            wgpu::TextureViewDimension::D2Array
+        } else {
+            wgpu::TextureViewDimension::D2
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(dimension),
             ..Default::default()
         });
+        let sampler = samplers.get(device, sampler);
 
         Ok(Self {
             texture,
@@ -133,6 +485,7 @@ impl<'a> Texture<'a> {
     pub fn create_depth_texture(
         device: &wgpu::Device,
         sc_desc: &wgpu::SwapChainDescriptor,
+        samplers: &SamplerCache,
     ) -> Self {
         let desc = wgpu::TextureDescriptor {
             label: None,
@@ -145,28 +498,105 @@ impl<'a> Texture<'a> {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
         };
-        Self::from_descriptor(device, desc)
+        let sampler = SamplerConfig::depth(wgpu::CompareFunction::LessEqual);
+        Self::from_descriptor(device, desc, samplers, &sampler)
     }
 
-    pub fn prepare_buffer_rgba(&self, device: &wgpu::Device) -> buffer::RawBuffer<[f32; 4]> {
-        let num_pixels = self.desc.size.width * self.desc.size.height * self.desc.size.depth;
+    /// Copies the first mip level of the texture back to the cpu.
+    /// Bgra textures are swizzled to rgba, and depth textures are
+    /// converted to grayscale. The texture needs to have been
+    /// created with [wgpu::TextureUsage::COPY_SRC].
+    pub async fn read_pixels(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<image::RgbaImage> {
+        use wgpu::TextureFormat::*;
 
-        let buffer_size = num_pixels * mem::size_of::<[f32; 4]>() as u32;
-        let buffer_usage = wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ;
-        let buffer_desc = wgpu::BufferDescriptor {
-            size: buffer_size as wgpu::BufferAddress,
-            usage: buffer_usage,
-            label: None,
-            mapped_at_creation: false,
+        let format = self.desc.format;
+        let pixel_size = match format {
+            Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb | Depth32Float => {
+                mem::size_of::<[u8; 4]>() as u32
+            }
+            _ => bail!("Reading pixels from {:?} textures is not supported", format),
         };
-        let buffer = device.create_buffer(&buffer_desc);
+        if !self.desc.usage.contains(wgpu::TextureUsage::COPY_SRC) {
+            bail!("Texture needs TextureUsage::COPY_SRC to read its pixels");
+        }
+
+        // wgpu requires texture -> buffer copies to be aligned using
+        // wgpu::COPY_BYTES_PER_ROW_ALIGNMENT. Because of this we'll
+        // need to save both the padded_bytes_per_row as well as the
+        // unpadded_bytes_per_row
+        let width = self.desc.size.width;
+        let height = self.desc.size.height;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let unpadded_bytes_per_row = pixel_size * width;
+        let padding = (align - unpadded_bytes_per_row % align) % align;
+        let padded_bytes_per_row = unpadded_bytes_per_row + padding;
 
-        let data = Vec::with_capacity(num_pixels as usize);
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            label: Some("Texture::read_pixels"),
+            mapped_at_creation: false,
+        });
 
-        let raw_buffer = buffer::RawBuffer::from_parts(buffer, data, buffer_usage);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture::read_pixels"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &output_buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: padded_bytes_per_row,
+                    rows_per_image: height,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+        );
+        queue.submit(iter::once(encoder.finish()));
+
+        // We have to create the mapping THEN device.poll(). If we
+        // don't the future will never resolve.
+        let buffer_slice = output_buffer.slice(..);
+        let request = buffer_slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        request.await?;
+
+        let padded_data = buffer_slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for row in padded_data.chunks(padded_bytes_per_row as _) {
+            for texel in row[..unpadded_bytes_per_row as _].chunks(pixel_size as _) {
+                match format {
+                    Bgra8Unorm | Bgra8UnormSrgb => {
+                        pixels.extend_from_slice(&[texel[2], texel[1], texel[0], texel[3]])
+                    }
+                    Depth32Float => {
+                        let depth = f32::from_ne_bytes([texel[0], texel[1], texel[2], texel[3]]);
+                        let value = (depth.max(0.0).min(1.0) * 255.0) as u8;
+                        pixels.extend_from_slice(&[value, value, value, 255]);
+                    }
+                    _ => pixels.extend_from_slice(texel),
+                }
+            }
+        }
+        drop(padded_data);
+        output_buffer.unmap();
 
-        raw_buffer
+        image::RgbaImage::from_raw(width, height, pixels)
+            .context("Pixel data doesn't match the texture size")
     }
 }
@@ -0,0 +1,30 @@
+//! Helpers shared by the integration tests.
+//!
+//! Most tests that need a GPU are `#[ignore]`d, as most machines that
+//! run the tests don't have an adapter, and get their display from
+//! [headless]. Run them with `cargo test -- --ignored`. Tests that run
+//! by default use [try_headless] and skip themselves instead.
+
+// Each test binary only uses some of these
+#![allow(dead_code)]
+
+use framework::Display;
+
+/// A headless display, or a panic saying there's no adapter to test
+/// with.
+pub fn headless(width: u32, height: u32) -> Display {
+    futures::executor::block_on(Display::headless(width, height))
+        .unwrap_or_else(|e| panic!("No adapter available to run GPU tests on: {:?}", e))
+}
+
+/// A headless display, or `None` after logging that `test` is skipped
+/// because there's no adapter.
+pub fn try_headless(test: &str, width: u32, height: u32) -> Option<Display> {
+    match futures::executor::block_on(Display::headless(width, height)) {
+        Ok(display) => Some(display),
+        Err(e) => {
+            eprintln!("Skipping {}, no adapter available: {:?}", test, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use framework::*;
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn compressed_textures_upload_or_decompress() {
+    let display = common::headless(1, 1);
+    let device = &display.device;
+
+    // 8x8 of red BC1 blocks, with levels down to 1x1
+    let red = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+    let data = TextureData {
+        format: wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+        width: 8,
+        height: 8,
+        layers: 1,
+        is_cube: false,
+        levels: vec![red.repeat(4), red.to_vec(), red.to_vec(), red.to_vec()],
+    };
+    let texture = Texture::from_data(
+        device,
+        &display.queue,
+        &data,
+        Some("red.dds"),
+        &display.samplers,
+        &SamplerConfig::default(),
+    )
+    .unwrap();
+
+    if device
+        .features()
+        .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+    {
+        assert_eq!(texture.desc.format, data.format);
+        // Only the 8x8 and 4x4 levels are whole blocks
+        assert_eq!(texture.desc.mip_level_count, 2);
+    } else {
+        assert_eq!(texture.desc.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(texture.desc.mip_level_count, 4);
+    }
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use framework::*;
+
+const DOUBLE_SHADER: &str = r#"
+#version 450
+
+layout(local_size_x=64) in;
+
+layout(set=0, binding=0) buffer Data {
+    float values[];
+};
+
+void main() {
+    uint i = gl_GlobalInvocationID.x;
+    if (i < values.length()) {
+        values[i] *= 2.0;
+    }
+}
+"#;
+
+#[derive(Copy, Clone)]
+struct Value(f32);
+
+impl ToRaw for Value {
+    type Output = f32;
+    fn to_raw(&self) -> f32 {
+        self.0
+    }
+}
+
+fn double_pipeline(device: &wgpu::Device) -> (wgpu::ComputePipeline, wgpu::BindGroupLayout) {
+    let mut compiler = shaderc::Compiler::new().unwrap();
+    let spirv = compiler
+        .compile_into_spirv(
+            DOUBLE_SHADER,
+            shaderc::ShaderKind::Compute,
+            "double.comp",
+            "main",
+            None,
+        )
+        .unwrap();
+
+    let mut builder = ComputePipelineBuilder::new();
+    builder.shader(wgpu::util::make_spirv(spirv.as_binary_u8()));
+    let layouts = builder.bind_group_layouts().unwrap();
+    let bind_group_layout = layouts[0].create(device, Some("Data"));
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = builder.layout(&layout).build(device).unwrap();
+    (pipeline, bind_group_layout)
+}
+
+fn bind_buffer(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &Buffer<Value, f32>,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(buffer.raw_buffer.buffer.slice(..)),
+        }],
+    })
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn dispatch_doubles_values() {
+    let display = common::headless(1, 1);
+    let device = &display.device;
+    let (pipeline, bind_group_layout) = double_pipeline(device);
+
+    let values = (0..100).map(|i| Value(i as f32)).collect::<Vec<_>>();
+    let output = Buffer::storage(device, values);
+    let staging = Buffer::staging(device, &output);
+    let bind_group = bind_buffer(device, &bind_group_layout, &output);
+
+    let result = futures::executor::block_on(dispatch(
+        device,
+        &display.queue,
+        &pipeline,
+        &[&bind_group],
+        (workgroup_count(100, 64), 1, 1),
+        &output,
+        &staging,
+    ))
+    .unwrap();
+
+    let expected = (0..100).map(|i| i as f32 * 2.0).collect::<Vec<_>>();
+    assert_eq!(result, expected);
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn small_staging_buffers_are_errors() {
+    let display = common::headless(1, 1);
+    let device = &display.device;
+    let (pipeline, bind_group_layout) = double_pipeline(device);
+
+    let output = Buffer::storage(device, (0..100).map(|i| Value(i as f32)).collect());
+    let small = Buffer::storage(device, (0..10).map(|i| Value(i as f32)).collect());
+    let staging = Buffer::staging(device, &small);
+    let bind_group = bind_buffer(device, &bind_group_layout, &output);
+
+    let result = futures::executor::block_on(dispatch(
+        device,
+        &display.queue,
+        &pipeline,
+        &[&bind_group],
+        (workgroup_count(100, 64), 1, 1),
+        &output,
+        &staging,
+    ));
+    assert!(result.is_err());
+}
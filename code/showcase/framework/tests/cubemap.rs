@@ -0,0 +1,43 @@
+mod common;
+
+use framework::*;
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn cube_maps_have_six_layers() {
+    let display = common::headless(1, 1);
+    let device = &display.device;
+
+    let faces = (0..CUBE_FACES)
+        .map(|i| {
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                4,
+                4,
+                image::Rgba([i as u8 * 40, 0, 0, 255]),
+            ))
+        })
+        .collect::<Vec<_>>();
+    let cube_map = Texture::cube_from_images(
+        device,
+        &display.queue,
+        &faces,
+        Some("faces"),
+        Mipmaps::Cpu,
+        &display.samplers,
+        &SamplerConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(cube_map.desc.size.depth, CUBE_FACES);
+    assert_eq!(cube_map.desc.mip_level_count, 3);
+
+    let res = Texture::cube_from_images(
+        device,
+        &display.queue,
+        &faces[..5],
+        Some("faces"),
+        Mipmaps::None,
+        &display.samplers,
+        &SamplerConfig::default(),
+    );
+    assert!(res.is_err());
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use framework::*;
+use std::sync::Arc;
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn minimised_displays_skip_frames() {
+    let mut display = common::headless(64, 64);
+    assert_eq!(display.begin_frame().unwrap(), FrameStatus::Ready);
+    display.end_frame();
+
+    display.resize(0, 64);
+    assert!(display.is_minimised());
+    assert_eq!(display.begin_frame().unwrap(), FrameStatus::Skipped);
+
+    display.resize(32, 32);
+    assert!(!display.is_minimised());
+    assert_eq!(display.begin_frame().unwrap(), FrameStatus::Ready);
+    display.get_current_frame().unwrap();
+    display.end_frame();
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn recreated_devices_keep_attachments() {
+    let mut display = common::headless(64, 64);
+    display
+        .attachments
+        .declare(&display.device, "depth", AttachmentDesc::depth());
+    let generation = display.attachments.generation();
+    let sampler = display
+        .samplers
+        .get(&display.device, &SamplerConfig::repeat());
+
+    display.recreate_device().unwrap();
+    assert!(display.attachments.contains("depth"));
+    assert!(display.attachments.generation() > generation);
+    // Samplers from the lost device aren't handed out anymore
+    let new_sampler = display
+        .samplers
+        .get(&display.device, &SamplerConfig::repeat());
+    assert!(!Arc::ptr_eq(&sampler, &new_sampler));
+
+    // The new device can render and read back the display's target
+    let frame = display.get_current_frame().unwrap();
+    let mut encoder = display
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+            attachment: frame.view(),
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    });
+    display.queue.submit(std::iter::once(encoder.finish()));
+    let texture = match &display.target {
+        DisplayTarget::Headless { texture } => texture,
+        DisplayTarget::Window { .. } => unreachable!(),
+    };
+    let image =
+        futures::executor::block_on(texture.read_pixels(&display.device, &display.queue)).unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [255; 4]);
+}
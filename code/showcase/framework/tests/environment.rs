@@ -0,0 +1,37 @@
+mod common;
+
+use framework::*;
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn pbr_environment_pipeline_fits_the_default_limits() {
+    // Headless displays use the default limits, which allow 4 bind
+    // groups
+    let display = common::headless(1, 1);
+    let reflected = RenderPipelineBuilder::new()
+        .vertex_shader(PbrMaterial::vertex_shader())
+        .fragment_shader(PbrMaterial::fragment_shader_with_environment())
+        .bind_group_layouts()
+        .unwrap();
+    assert_eq!(reflected.len(), 4);
+
+    let layouts = reflected
+        .iter()
+        .map(|layout| layout.create(&display.device, None))
+        .collect::<Vec<_>>();
+    let layout = display
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("environment"),
+            bind_group_layouts: &layouts.iter().collect::<Vec<_>>(),
+            push_constant_ranges: &[],
+        });
+    RenderPipelineBuilder::new()
+        .layout(&layout)
+        .vertex_shader(PbrMaterial::vertex_shader())
+        .fragment_shader(PbrMaterial::fragment_shader_with_environment())
+        .color_solid(Display::HEADLESS_FORMAT)
+        .vertex_buffer::<ModelVertex>()
+        .build(&display.device)
+        .unwrap();
+}
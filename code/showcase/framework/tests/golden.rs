@@ -0,0 +1,188 @@
+mod common;
+
+use anyhow::*;
+use framework::*;
+use std::iter;
+use std::path::PathBuf;
+use std::time::Duration;
+use wgpu::util::DeviceExt;
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+
+layout(location=0) in vec2 a_position;
+layout(location=1) in vec3 a_color;
+
+layout(location=0) out vec3 v_color;
+
+void main() {
+    v_color = a_color;
+    gl_Position = vec4(a_position, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location=0) in vec3 v_color;
+layout(location=0) out vec4 f_color;
+
+void main() {
+    f_color = vec4(v_color, 1.0);
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ColorVertex {
+    position: [f32; 2],
+    color: [f32; 3],
+}
+
+unsafe impl bytemuck::Zeroable for ColorVertex {}
+unsafe impl bytemuck::Pod for ColorVertex {}
+
+impl Vertex for ColorVertex {
+    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        wgpu::VertexBufferDescriptor {
+            stride: std::mem::size_of::<ColorVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttributeDescriptor {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float3,
+                },
+            ],
+        }
+    }
+}
+
+const VERTICES: &[ColorVertex] = &[
+    ColorVertex {
+        position: [0.0, 0.5],
+        color: [1.0, 0.0, 0.0],
+    },
+    ColorVertex {
+        position: [-0.5, -0.5],
+        color: [0.0, 1.0, 0.0],
+    },
+    ColorVertex {
+        position: [0.5, -0.5],
+        color: [0.0, 0.0, 1.0],
+    },
+];
+
+struct TriangleDemo {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+}
+
+fn compile(src: &str, kind: shaderc::ShaderKind, name: &str) -> Result<Vec<u8>> {
+    let mut compiler = shaderc::Compiler::new().context("Unable to create shader compiler")?;
+    let spirv = compiler.compile_into_spirv(src, kind, name, "main", None)?;
+    Ok(spirv.as_binary_u8().to_vec())
+}
+
+impl Demo for TriangleDemo {
+    fn init(display: &Display) -> Result<Self, Error> {
+        let vs = compile(VERTEX_SHADER, shaderc::ShaderKind::Vertex, "golden.vert")?;
+        let fs = compile(FRAGMENT_SHADER, shaderc::ShaderKind::Fragment, "golden.frag")?;
+
+        let layout = display
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("TriangleDemo::layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+        let pipeline = RenderPipelineBuilder::new()
+            .layout(&layout)
+            .vertex_shader(wgpu::util::make_spirv(&vs))
+            .fragment_shader(wgpu::util::make_spirv(&fs))
+            .color_solid(display.sc_desc.format)
+            .sample_count(display.sample_count)
+            .vertex_buffer::<ColorVertex>()
+            .build(&display.device)?;
+        let vertex_buffer = display
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("TriangleDemo::vertex_buffer"),
+                contents: bytemuck::cast_slice(VERTICES),
+                usage: wgpu::BufferUsage::VERTEX,
+            });
+
+        Ok(Self {
+            pipeline,
+            vertex_buffer,
+        })
+    }
+
+    fn resize(&mut self, _display: &Display) {}
+
+    fn update(&mut self, _display: &Display, _dt: Duration) {}
+
+    fn recreate_resources(&mut self, display: &Display) -> Result<(), Error> {
+        // Nothing changes between frames, so there's nothing to keep
+        *self = Self::init(display)?;
+        Ok(())
+    }
+
+    fn render(&mut self, display: &mut Display, _alpha: f32) {
+        let frame = match display.get_current_frame() {
+            Ok(frame) => frame,
+            Err(_) => return,
+        };
+        let mut encoder = display
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[display.color_attachment(
+                    frame.view(),
+                    wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                )],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            pass.draw(0..3, 0..1);
+        }
+        display.queue.submit(iter::once(encoder.finish()));
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("goldens")
+        .join(name)
+}
+
+#[test]
+fn triangle_matches_golden() {
+    // Unlike the other GPU tests this runs by default, so it skips
+    // itself without an adapter rather than failing
+    let mut display = match common::try_headless("triangle_matches_golden", 128, 96) {
+        Some(display) => display,
+        None => return,
+    };
+    let mut demo = TriangleDemo::init(&display).unwrap();
+    let actual = futures::executor::block_on(display.capture_frame(&mut demo)).unwrap();
+    let result = assert_golden(
+        golden_path("triangle.png"),
+        &actual,
+        &GoldenOptions::default(),
+    );
+    if let Err(e) = result {
+        panic!("{:?}", e);
+    }
+}
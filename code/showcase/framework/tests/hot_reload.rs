@@ -0,0 +1,79 @@
+#![cfg(feature = "hot-reload")]
+
+mod common;
+
+use framework::*;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+
+void main() {
+    vec2 corner = vec2(float(gl_VertexIndex & 1), float((gl_VertexIndex >> 1) & 1));
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.5, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    f_color = vec4(1.0, 0.0, 1.0, 1.0);
+}
+"#;
+
+/// Writes `contents` to `path`, waiting until the file system reports a
+/// new modification time, as some only store seconds.
+fn rewrite(path: &Path, contents: &str) {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let before = modified(path);
+    loop {
+        fs::write(path, contents).unwrap();
+        if modified(path) != before {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn broken_edits_keep_the_last_pipeline() {
+    let display = common::headless(1, 1);
+    let dir = std::env::temp_dir().join(format!("hot-reload-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let vs_path = dir.join("shader.vert");
+    let fs_path = dir.join("shader.frag");
+    fs::write(&vs_path, VERTEX_SHADER).unwrap();
+    fs::write(&fs_path, FRAGMENT_SHADER).unwrap();
+
+    let layout = display
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+    let mut pipeline = RenderPipelineBuilder::new()
+        .vertex_shader_path(&vs_path)
+        .fragment_shader_path(&fs_path)
+        .primitive_topology(wgpu::PrimitiveTopology::TriangleStrip)
+        .color_solid(Display::HEADLESS_FORMAT)
+        .build_hot_reload(&display.device, layout)
+        .unwrap();
+    assert!(!pipeline.reload_if_changed(&display.device));
+
+    rewrite(&fs_path, &FRAGMENT_SHADER.replace("1.0, 0.0", "undefined"));
+    assert!(!pipeline.reload_if_changed(&display.device));
+    // Nothing changed since the failed attempt
+    assert!(!pipeline.reload_if_changed(&display.device));
+
+    rewrite(&fs_path, &FRAGMENT_SHADER.replace("1.0, 0.0", "0.0, 1.0"));
+    assert!(pipeline.reload_if_changed(&display.device));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use cgmath::*;
+use framework::*;
+
+fn point(x: f32) -> PointLight {
+    PointLight {
+        position: Vector3::new(x, 0.0, 0.0),
+        color: Vector3::new(1.0, 1.0, 1.0),
+        intensity: 1.0,
+        attenuation: Attenuation::NONE,
+    }
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn removed_ids_dont_refer_to_new_lights() {
+    let display = common::headless(1, 1);
+    let mut lights = LightManager::new(&display.device, 4);
+
+    let first = lights.add(point(1.0));
+    lights.set_shadow_layers(first, Some(ShadowLayers { first: 0, count: 1 }));
+    assert!(lights.remove(first).is_some());
+
+    // The slot is reused, but the old id stays dead
+    let second = lights.add(point(2.0));
+    assert_ne!(first, second);
+    assert_eq!(lights.get(first), None);
+    assert_eq!(lights.shadow_layers(second), None);
+    assert!(lights.remove(first).is_none());
+    lights.set_position(first, Vector3::zero());
+    assert_eq!(lights.get(second), Some(&Light::from(point(2.0))));
+    assert_eq!(lights.len(), 1);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use framework::*;
+use std::iter;
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn gpu_mipmaps_average_the_level_above() {
+    let display = common::headless(1, 1);
+    let device = &display.device;
+    let queue = &display.queue;
+
+    let desc = wgpu::TextureDescriptor {
+        label: Some("checkerboard"),
+        size: wgpu::Extent3d {
+            width: 2,
+            height: 2,
+            depth: 1,
+        },
+        mip_level_count: mip_level_count(2, 2),
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsage::SAMPLED
+            | wgpu::TextureUsage::OUTPUT_ATTACHMENT
+            | wgpu::TextureUsage::COPY_DST
+            | wgpu::TextureUsage::COPY_SRC,
+    };
+    let texture = device.create_texture(&desc);
+    let checkerboard: [[u8; 4]; 4] = [[255; 4], [0; 4], [0; 4], [255; 4]];
+    queue.write_texture(
+        wgpu::TextureCopyView {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        bytemuck::cast_slice(&checkerboard),
+        wgpu::TextureDataLayout {
+            offset: 0,
+            bytes_per_row: 8,
+            rows_per_image: 2,
+        },
+        desc.size,
+    );
+
+    let output = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("mip 1"),
+        size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    MipmapGenerator::new(device)
+        .generate(device, &mut encoder, &texture, &desc)
+        .unwrap();
+    encoder.copy_texture_to_buffer(
+        wgpu::TextureCopyView {
+            texture: &texture,
+            mip_level: 1,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::BufferCopyView {
+            buffer: &output,
+            layout: wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+                rows_per_image: 1,
+            },
+        },
+        wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth: 1,
+        },
+    );
+    queue.submit(iter::once(encoder.finish()));
+
+    let slice = output.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    futures::executor::block_on(mapping).unwrap();
+    let texel = slice.get_mapped_range()[..4].to_vec();
+    for channel in texel {
+        assert!((127..=128).contains(&channel), "{}", channel);
+    }
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use framework::*;
+use std::path::PathBuf;
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn gltf_meshes_and_materials_are_loaded() {
+    let display = common::headless(1, 1);
+    let layout = PbrMaterial::create_bind_group_layout(&display.device);
+    let model = PbrModel::load_gltf(
+        &display.device,
+        &display.queue,
+        &display.samplers,
+        &layout,
+        fixture_path("two_meshes.gltf"),
+        &ModelLoadOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(model.meshes.len(), 2);
+    // The file's material, and the default one for the mesh without one
+    assert_eq!(model.materials.len(), 2);
+    let mut materials = model.meshes.iter().map(|m| m.material).collect::<Vec<_>>();
+    materials.sort();
+    assert_eq!(materials, vec![0, 1]);
+    assert_eq!(model.materials[0].name, "red");
+
+    // The material only has a color, so both textures are reported, but
+    // the optional maps aren't
+    let missing = model
+        .warnings
+        .iter()
+        .filter_map(|w| match w {
+            ModelWarning::MissingTexture { material, kind, .. } => Some((material.as_str(), *kind)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        missing,
+        vec![("red", TextureKind::Diffuse), ("red", TextureKind::Normal)]
+    );
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use framework::*;
+use std::sync::Arc;
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn identical_samplers_are_shared() {
+    let display = common::headless(1, 1);
+    let device = &display.device;
+    let samplers = &display.samplers;
+    let before = samplers.len();
+
+    let repeat = samplers.get(device, &SamplerConfig::repeat());
+    let again = samplers.get(device, &SamplerConfig::repeat());
+    assert!(Arc::ptr_eq(&repeat, &again));
+
+    let texture =
+        Texture::from_color(device, &display.queue, [255; 4], None, false, samplers).unwrap();
+    let clamp = samplers.get(device, &SamplerConfig::default());
+    assert!(Arc::ptr_eq(&texture.sampler, &clamp));
+
+    let anisotropic = samplers.get(device, &SamplerConfig::repeat().anisotropy(16));
+    assert!(!Arc::ptr_eq(&repeat, &anisotropic));
+    // The display's own target texture already uses the default config
+    assert_eq!(samplers.len(), before + 2);
+}
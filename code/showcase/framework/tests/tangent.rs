@@ -0,0 +1,123 @@
+mod common;
+
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+use framework::*;
+
+/// Two bumpy grids, the second with mirrored UVs, plus a triangle with
+/// degenerate UVs, one with an out of range index and a vertex that no
+/// triangle uses. The grids don't share vertices, as the tangents on a
+/// mirrored seam cancel out and would only be noise.
+fn mesh() -> (
+    Vec<Vector3<f32>>,
+    Vec<Vector3<f32>>,
+    Vec<Vector2<f32>>,
+    Vec<u32>,
+) {
+    const SIZE: u32 = 8;
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut tex_coords = Vec::new();
+    let mut indices = Vec::new();
+    for &mirrored in &[false, true] {
+        let first = positions.len() as u32;
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (fx, fy) = (x as f32, y as f32);
+                let z = (fx * 0.7).sin() * (fy * 0.5).cos();
+                positions.push(Vector3::new(fx, fy, z));
+                normals.push(Vector3::new(-0.2 * fx.cos(), 0.1 * fy.sin(), 1.0).normalize());
+                let u = if mirrored { (SIZE - x) as f32 } else { fx };
+                tex_coords.push(Vector2::new(u, fy) / SIZE as f32);
+            }
+        }
+        for y in 0..SIZE - 1 {
+            for x in 0..SIZE - 1 {
+                let i = first + y * SIZE + x;
+                indices.extend_from_slice(&[i, i + 1, i + SIZE + 1, i, i + SIZE + 1, i + SIZE]);
+            }
+        }
+    }
+
+    let degenerate = positions.len() as u32;
+    for p in &[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]] {
+        positions.push(Vector3::new(p[0], p[1], -1.0));
+        normals.push(Vector3::unit_z());
+        tex_coords.push(Vector2::new(0.5, 0.5));
+    }
+    indices.extend_from_slice(&[degenerate, degenerate + 1, degenerate + 2]);
+    indices.extend_from_slice(&[0, 1, 1000]);
+
+    positions.push(Vector3::new(0.0, 0.0, 5.0));
+    normals.push(Vector3::zero());
+    tex_coords.push(Vector2::new(0.0, 0.0));
+
+    (positions, normals, tex_coords, indices)
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn gpu_tangents_match_the_cpu() {
+    let display = common::headless(1, 1);
+    let (positions, normals, tex_coords, indices) = mesh();
+
+    for weighting in &[TangentWeighting::Angle, TangentWeighting::Area] {
+        let options = TangentOptions {
+            weighting: *weighting,
+            ..Default::default()
+        };
+        let cpu = generate_tangents(&positions, &normals, &tex_coords, &indices, &options);
+        let gpu = futures::executor::block_on(generate_tangents_gpu(
+            &display.device,
+            &display.queue,
+            &positions,
+            &normals,
+            &tex_coords,
+            &indices,
+            &options,
+        ))
+        .unwrap();
+
+        assert_eq!(gpu.len(), cpu.len());
+        for (i, (gpu, cpu)) in gpu.iter().zip(&cpu).enumerate() {
+            assert!(
+                (gpu.tangent - cpu.tangent).magnitude() < 1e-4
+                    && (gpu.bitangent - cpu.bitangent).magnitude() < 1e-4
+                    && gpu.handedness == cpu.handedness,
+                "vertex {} with {:?}: {:?} != {:?}",
+                i,
+                weighting,
+                gpu,
+                cpu
+            );
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with --ignored"]
+fn gpu_tangents_of_empty_meshes() {
+    let display = common::headless(1, 1);
+    let options = TangentOptions::default();
+    let run = |positions: &[Vector3<f32>], indices: &[u32]| {
+        futures::executor::block_on(generate_tangents_gpu(
+            &display.device,
+            &display.queue,
+            positions,
+            &[],
+            &[],
+            indices,
+            &options,
+        ))
+        .unwrap()
+    };
+
+    assert!(run(&[], &[]).is_empty());
+    // No normals or UVs, so every vertex gets an arbitrary tangent
+    let tangents = run(&[Vector3::zero(); 3], &[0, 1, 2]);
+    assert_eq!(tangents.len(), 3);
+    for t in tangents {
+        assert!(t.tangent.dot(Vector3::unit_z()).abs() < 1e-5);
+        assert!((t.tangent.magnitude() - 1.0).abs() < 1e-5);
+    }
+}
@@ -1,7 +1,6 @@
 extern crate framework;
 
-use anyhow::*;
-use std::{iter, mem};
+use std::iter;
 
 async fn run() {
     let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
@@ -47,27 +46,12 @@ async fn run() {
         usage: wgpu::TextureUsage::COPY_SRC | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
         label: None,
     };
-    let render_target = framework::Texture::from_descriptor(&device, rt_desc);
-
-    // wgpu requires texture -> buffer copies to be aligned using
-    // wgpu::COPY_BYTES_PER_ROW_ALIGNMENT. Because of this we'll
-    // need to save both the padded_bytes_per_row as well as the
-    // unpadded_bytes_per_row
-    let pixel_size = mem::size_of::<[u8; 4]>() as u32;
-    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
-    let unpadded_bytes_per_row = pixel_size * texture_size;
-    let padding = (align - unpadded_bytes_per_row % align) % align;
-    let padded_bytes_per_row = unpadded_bytes_per_row + padding;
-
-    // create a buffer to copy the texture to so we can get the data
-    let buffer_size = (padded_bytes_per_row * texture_size) as wgpu::BufferAddress;
-    let buffer_desc = wgpu::BufferDescriptor {
-        size: buffer_size,
-        usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
-        label: Some("Output Buffer"),
-        mapped_at_creation: false,
-    };
-    let output_buffer = device.create_buffer(&buffer_desc);
+    let render_target = framework::Texture::from_descriptor(
+        &device,
+        rt_desc,
+        &framework::SamplerCache::new(),
+        &framework::SamplerConfig::default(),
+    );
 
     // a simple render pipeline that draws a triangle
     let render_pipeline = create_render_pipeline(&device, &render_target);
@@ -100,64 +84,21 @@ async fn run() {
 
         drop(rpass);
 
-        encoder.copy_texture_to_buffer(
-            wgpu::TextureCopyView {
-                texture: &render_target.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            wgpu::BufferCopyView {
-                buffer: &output_buffer,
-                layout: wgpu::TextureDataLayout {
-                    offset: 0,
-                    bytes_per_row: padded_bytes_per_row,
-                    rows_per_image: texture_size,
-                },
-            },
-            render_target.desc.size,
-        );
-
         queue.submit(iter::once(encoder.finish()));
 
-        // Create the map request
-        let buffer_slice = output_buffer.slice(..);
-        let request = buffer_slice.map_async(wgpu::MapMode::Read);
-        // wait for the GPU to finish
-        device.poll(wgpu::Maintain::Wait);
-        let result = request.await;
-
-        match result {
-            Ok(()) => {
-                let padded_data = buffer_slice.get_mapped_range();
-                let data = padded_data
-                    .chunks(padded_bytes_per_row as _)
-                    .map(|chunk| &chunk[..unpadded_bytes_per_row as _])
-                    .flatten()
-                    .map(|x| *x)
-                    .collect::<Vec<_>>();
-                drop(padded_data);
-                output_buffer.unmap();
-                frames.push(data);
-            }
-            _ => eprintln!("Something went wrong"),
+        match render_target.read_pixels(&device, &queue).await {
+            Ok(image) => frames.push(image),
+            Err(e) => eprintln!("Something went wrong: {}", e),
         }
     }
 
-    save_gif("output.gif", &mut frames, 10, texture_size as u16).unwrap();
-}
-
-fn save_gif(path: &str, frames: &mut Vec<Vec<u8>>, speed: i32, size: u16) -> Result<()> {
-    use gif::{Encoder, Frame, Repeat, SetParameter};
-
-    let mut image = std::fs::File::create(path)?;
-    let mut encoder = Encoder::new(&mut image, size, size, &[])?;
-    encoder.set(Repeat::Infinite)?;
-
-    for mut frame in frames {
-        encoder.write_frame(&Frame::from_rgba_speed(size, size, &mut frame, speed))?;
-    }
-
-    Ok(())
+    let recording = framework::Recording {
+        frames,
+        timestep: std::time::Duration::from_millis(100),
+    };
+    recording
+        .save_gif("output.gif", &framework::GifOptions::default())
+        .unwrap();
 }
 
 fn create_render_pipeline(
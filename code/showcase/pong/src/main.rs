@@ -9,6 +9,8 @@ use input::Input;
 use system::System;
 
 use futures::executor::block_on;
+use std::time::Instant;
+use timestep::Clock;
 use winit::event::*;
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Fullscreen, WindowBuilder};
@@ -109,6 +111,10 @@ fn main() {
 
     window.set_visible(true);
 
+    let mut clock = Clock::new(Some(util::timestep()));
+    let mut last_frame = Instant::now();
+    let mut previous = state::Positions::of(&state);
+
     event_loop.run(move |event, _, control_flow| {
         *control_flow = if state.game_state == state::GameState::Quiting {
             ControlFlow::Exit
@@ -160,40 +166,54 @@ fn main() {
                 }
                 events.clear();
 
-                visiblity_system.update_state(&input, &mut state, &mut events);
-                match state.game_state {
-                    state::GameState::MainMenu => {
-                        menu_system.update_state(&input, &mut state, &mut events);
-                        if state.game_state == state::GameState::Serving {
-                            serving_system.start(&mut state);
+                let now = Instant::now();
+                let (ticks, _) = clock.advance(now - last_frame, 1.0);
+                last_frame = now;
+                for _ in 0..ticks {
+                    previous = state::Positions::of(&state);
+                    let game_state = state.game_state;
+
+                    visiblity_system.update_state(&input, &mut state, &mut events);
+                    match state.game_state {
+                        state::GameState::MainMenu => {
+                            menu_system.update_state(&input, &mut state, &mut events);
+                            if state.game_state == state::GameState::Serving {
+                                serving_system.start(&mut state);
+                            }
                         }
-                    }
-                    state::GameState::Serving => {
-                        serving_system.update_state(&input, &mut state, &mut events);
-                        play_system.update_state(&input, &mut state, &mut events);
-                        if state.game_state == state::GameState::Playing {
-                            play_system.start(&mut state);
+                        state::GameState::Serving => {
+                            serving_system.update_state(&input, &mut state, &mut events);
+                            play_system.update_state(&input, &mut state, &mut events);
+                            if state.game_state == state::GameState::Playing {
+                                play_system.start(&mut state);
+                            }
                         }
-                    }
-                    state::GameState::Playing => {
-                        ball_system.update_state(&input, &mut state, &mut events);
-                        play_system.update_state(&input, &mut state, &mut events);
-                        if state.game_state == state::GameState::Serving {
-                            serving_system.start(&mut state);
-                        } else if state.game_state == state::GameState::GameOver {
-                            game_over_system.start(&mut state);
+                        state::GameState::Playing => {
+                            ball_system.update_state(&input, &mut state, &mut events);
+                            play_system.update_state(&input, &mut state, &mut events);
+                            if state.game_state == state::GameState::Serving {
+                                serving_system.start(&mut state);
+                            } else if state.game_state == state::GameState::GameOver {
+                                game_over_system.start(&mut state);
+                            }
                         }
-                    }
-                    state::GameState::GameOver => {
-                        game_over_system.update_state(&input, &mut state, &mut events);
-                        if state.game_state == state::GameState::MainMenu {
-                            menu_system.start(&mut state);
+                        state::GameState::GameOver => {
+                            game_over_system.update_state(&input, &mut state, &mut events);
+                            if state.game_state == state::GameState::MainMenu {
+                                menu_system.start(&mut state);
+                            }
                         }
+                        state::GameState::Quiting => {}
+                    }
+                    if state.game_state != game_state {
+                        // Serving puts the ball back in the middle, which
+                        // shouldn't be drawn as the ball flying there
+                        previous = state::Positions::of(&state);
                     }
-                    state::GameState::Quiting => {}
                 }
 
-                render.render_state(&state);
+                let positions = previous.lerp(&state::Positions::of(&state), clock.alpha());
+                render.render_state(&state, &positions);
                 if state.game_state != state::GameState::Quiting {
                     window.request_redraw();
                 }
@@ -39,12 +39,12 @@ impl QuadBufferBuilder {
         }
     }
 
-    pub fn push_ball(self, ball: &state::Ball) -> Self {
+    pub fn push_ball(self, ball: &state::Ball, position: cgmath::Vector2<f32>) -> Self {
         if ball.visible {
-            let min_x = ball.position.x - ball.radius;
-            let min_y = ball.position.y - ball.radius;
-            let max_x = ball.position.x + ball.radius;
-            let max_y = ball.position.y + ball.radius;
+            let min_x = position.x - ball.radius;
+            let min_y = position.y - ball.radius;
+            let max_x = position.x + ball.radius;
+            let max_y = position.y + ball.radius;
 
             self.push_quad(min_x, min_y, max_x, max_y)
         } else {
@@ -52,13 +52,13 @@ impl QuadBufferBuilder {
         }
     }
 
-    pub fn push_player(self, player: &state::Player) -> Self {
+    pub fn push_player(self, player: &state::Player, position: cgmath::Vector2<f32>) -> Self {
         if player.visible {
             self.push_quad(
-                player.position.x - player.size.x * 0.5,
-                player.position.y - player.size.y * 0.5,
-                player.position.x + player.size.x * 0.5,
-                player.position.y + player.size.y * 0.5,
+                position.x - player.size.x * 0.5,
+                position.y - player.size.y * 0.5,
+                position.x + player.size.x * 0.5,
+                position.y + player.size.y * 0.5,
             )
         } else {
             self
@@ -120,16 +120,18 @@ impl Render {
         }
     }
 
-    pub fn render_state(&mut self, state: &state::State) {
+    /// Draws the ball and the paddles at `positions` rather than where
+    /// `state` has them, so they can be blended between updates.
+    pub fn render_state(&mut self, state: &state::State, positions: &state::Positions) {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
         let num_indices = if state.ball.visible || state.player1.visible || state.player2.visible {
             let (stg_vertex, stg_index, num_indices) = QuadBufferBuilder::new()
-                .push_ball(&state.ball)
-                .push_player(&state.player1)
-                .push_player(&state.player2)
+                .push_ball(&state.ball, positions.ball)
+                .push_player(&state.player1, positions.player1)
+                .push_player(&state.player2, positions.player2)
                 .build(&self.device);
 
             stg_vertex.copy_to_buffer(&mut encoder, &self.vertex_buffer);
@@ -1,3 +1,5 @@
+use cgmath::VectorSpace;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum GameState {
     MainMenu,
@@ -20,6 +22,35 @@ pub struct State {
     pub game_state: GameState,
 }
 
+/// Where the ball and the paddles are. Frames that land between two
+/// updates draw them part way between where they were and where they
+/// are.
+#[derive(Debug, Copy, Clone)]
+pub struct Positions {
+    pub ball: cgmath::Vector2<f32>,
+    pub player1: cgmath::Vector2<f32>,
+    pub player2: cgmath::Vector2<f32>,
+}
+
+impl Positions {
+    pub fn of(state: &State) -> Self {
+        Self {
+            ball: state.ball.position,
+            player1: state.player1.position,
+            player2: state.player2.position,
+        }
+    }
+
+    /// `self` when `alpha` is 0, and `next` when it's 1.
+    pub fn lerp(&self, next: &Self, alpha: f32) -> Self {
+        Self {
+            ball: self.ball.lerp(next.ball, alpha),
+            player1: self.player1.lerp(next.player1, alpha),
+            player2: self.player2.lerp(next.player2, alpha),
+        }
+    }
+}
+
 pub struct Ball {
     pub position: cgmath::Vector2<f32>,
     pub velocity: cgmath::Vector2<f32>,
@@ -2,6 +2,17 @@
 
 use crate::state;
 
+/// How many times a second the systems update. The speeds below are
+/// per update, so the game plays the same at any refresh rate.
+pub const TICKS_PER_SECOND: u32 = 60;
+/// The most updates run for one frame, so a long stall doesn't have
+/// the game race to catch up.
+pub const MAX_TICKS_PER_FRAME: u32 = 5;
+
+pub fn timestep() -> timestep::FixedTimestep {
+    timestep::FixedTimestep::new(TICKS_PER_SECOND).max_steps(MAX_TICKS_PER_FRAME)
+}
+
 pub const PLAYER_SPEED: f32 = 0.05;
 pub const BALL_SPEED: f32 = 0.025;
 
@@ -0,0 +1,177 @@
+//! Fixed timestep updates, shared by the framework's `Demo`s and the
+//! showcases that run their own event loop.
+
+use std::time::Duration;
+
+/// Time scales above this are clamped to it, which also keeps the
+/// scaled frame time from overflowing a [Duration].
+pub const MAX_TIME_SCALE: f64 = 1000.0;
+
+/// Runs updates a fixed number of times a second instead of once a
+/// frame, so the simulation plays out the same whatever the frame rate
+/// is.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FixedTimestep {
+    /// Updates per second.
+    pub tick_rate: u32,
+    /// The most updates run for a single frame. When updates take longer
+    /// than the time they simulate, the simulation slows down instead of
+    /// falling further and further behind.
+    pub max_steps: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: u32) -> Self {
+        Self {
+            tick_rate,
+            max_steps: 8,
+        }
+    }
+
+    pub fn max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// How much time one update simulates.
+    pub fn tick(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate.max(1)
+    }
+}
+
+/// Turns the time between frames into updates, and keeps track of
+/// how far the current frame is between two updates.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    timestep: Option<FixedTimestep>,
+    accumulator: Duration,
+    alpha: f32,
+}
+
+impl Clock {
+    /// Without a [FixedTimestep], every frame is one update as long as
+    /// the frame.
+    pub fn new(timestep: Option<FixedTimestep>) -> Self {
+        Self {
+            timestep,
+            accumulator: Duration::from_secs(0),
+            alpha: 1.0,
+        }
+    }
+
+    pub fn timestep(&self) -> Option<FixedTimestep> {
+        self.timestep
+    }
+
+    /// Moves the clock forward by `frame_time` scaled by `time_scale`,
+    /// where 0 pauses and 0.5 is half speed. Scales that aren't
+    /// positive, including NaN, pause too, and ones above
+    /// [MAX_TIME_SCALE] are clamped to it. Returns how many updates to
+    /// run and how much time each one simulates.
+    pub fn advance(&mut self, frame_time: Duration, time_scale: f64) -> (u32, Duration) {
+        // Written so NaN fails the comparison and pauses
+        let time_scale = if time_scale > 0.0 {
+            time_scale.min(MAX_TIME_SCALE)
+        } else {
+            0.0
+        };
+        let scaled = frame_time.mul_f64(time_scale);
+        let timestep = match self.timestep {
+            Some(timestep) => timestep,
+            None => {
+                self.alpha = 1.0;
+                return (1, scaled);
+            }
+        };
+
+        let tick = timestep.tick();
+        self.accumulator += scaled;
+        let mut steps = 0;
+        while self.accumulator >= tick && steps < timestep.max_steps {
+            self.accumulator -= tick;
+            steps += 1;
+        }
+        if steps == timestep.max_steps && self.accumulator >= tick {
+            // Too far behind to catch up, so drop the time we couldn't
+            // simulate rather than carry it into the next frames
+            self.accumulator = Duration::from_secs(0);
+        }
+        self.alpha = self.accumulator.as_secs_f32() / tick.as_secs_f32();
+        (steps, tick)
+    }
+
+    /// How far between the last update and the next one the current
+    /// frame is, from 0 to 1. Rendering the last two states blended by
+    /// this hides that updates and frames don't line up. It's always 1
+    /// without a [FixedTimestep].
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn variable_clock_updates_once_a_frame() {
+        let mut clock = Clock::new(None);
+        assert_eq!(clock.advance(ms(16), 1.0), (1, ms(16)));
+        assert_eq!(clock.advance(ms(16), 0.5), (1, ms(8)));
+        assert_eq!(clock.alpha(), 1.0);
+    }
+
+    #[test]
+    fn invalid_time_scales_pause_or_clamp() {
+        let mut clock = Clock::new(None);
+        assert_eq!(clock.advance(ms(16), f64::NAN), (1, ms(0)));
+        assert_eq!(clock.advance(ms(16), -1.0), (1, ms(0)));
+        assert_eq!(clock.advance(ms(16), f64::NEG_INFINITY), (1, ms(0)));
+        assert_eq!(
+            clock.advance(ms(16), f64::INFINITY),
+            (1, ms(16).mul_f64(MAX_TIME_SCALE))
+        );
+    }
+
+    #[test]
+    fn fixed_clock_carries_leftover_time() {
+        let mut clock = Clock::new(Some(FixedTimestep::new(100)));
+        assert_eq!(clock.advance(ms(25), 1.0), (2, ms(10)));
+        assert!((clock.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(clock.advance(ms(5), 1.0).0, 1);
+        assert_eq!(clock.alpha(), 0.0);
+    }
+
+    #[test]
+    fn fixed_clock_frame_rate_doesnt_change_the_steps() {
+        let steps = |frame: Duration, frames: u32| {
+            let mut clock = Clock::new(Some(FixedTimestep::new(100)));
+            (0..frames)
+                .map(|_| clock.advance(frame, 1.0).0)
+                .sum::<u32>()
+        };
+        // A second at 40, 50 and 125 frames a second
+        assert_eq!(steps(ms(25), 40), 100);
+        assert_eq!(steps(ms(20), 50), 100);
+        assert_eq!(steps(ms(8), 125), 100);
+    }
+
+    #[test]
+    fn fixed_clock_pauses_and_gives_up_catching_up() {
+        let mut clock = Clock::new(Some(FixedTimestep::new(100).max_steps(3)));
+        assert_eq!(clock.advance(ms(100), 0.0).0, 0);
+        assert_eq!(clock.advance(ms(100), 1.0).0, 3);
+        assert_eq!(clock.alpha(), 0.0);
+        assert_eq!(clock.advance(ms(10), 1.0).0, 1);
+    }
+}